@@ -0,0 +1,107 @@
+use std::io::{self, ErrorKind};
+
+/// MSB-first bit cursor with Exp-Golomb support, as used by H.264/H.265 parameter sets and AAC configs
+pub struct BitReader<'a> {
+	data: &'a [u8],
+	/// position in bits
+	pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+	pub fn new(data: &'a [u8]) -> Self {
+		Self { data, pos: 0 }
+	}
+
+	pub fn remaining(&self) -> usize {
+		self.data.len() * 8 - self.pos
+	}
+
+	pub fn bit(&mut self) -> io::Result<bool> {
+		if self.pos >= self.data.len() * 8 {
+			return Err(io::Error::new(ErrorKind::UnexpectedEof, "ran out of bits"));
+		}
+
+		let bit = self.data[self.pos / 8] >> (7 - self.pos % 8) & 1;
+		self.pos += 1;
+		Ok(bit == 1)
+	}
+
+	/// reads up to 32 bits as an unsigned integer
+	pub fn bits(&mut self, count: u32) -> io::Result<u32> {
+		let mut value = 0u32;
+		for _ in 0..count {
+			value = value << 1 | self.bit()? as u32;
+		}
+		Ok(value)
+	}
+
+	pub fn skip(&mut self, count: usize) -> io::Result<()> {
+		if count > self.remaining() {
+			return Err(io::Error::new(ErrorKind::UnexpectedEof, "ran out of bits"));
+		}
+		self.pos += count;
+		Ok(())
+	}
+
+	/// unsigned Exp-Golomb code, `ue(v)`
+	pub fn ue(&mut self) -> io::Result<u32> {
+		let mut leading_zeros = 0;
+		while !self.bit()? {
+			leading_zeros += 1;
+			if leading_zeros > 31 {
+				return Err(io::Error::new(ErrorKind::InvalidData, "Exp-Golomb code too long"));
+			}
+		}
+
+		Ok(((1u64 << leading_zeros) - 1 + self.bits(leading_zeros)? as u64) as u32)
+	}
+
+	/// signed Exp-Golomb code, `se(v)`
+	pub fn se(&mut self) -> io::Result<i32> {
+		let code = self.ue()? as i64;
+		Ok(if code % 2 == 1 { (code + 1) / 2 } else { -(code / 2) } as i32)
+	}
+}
+
+/// strips the emulation prevention bytes (`00 00 03`) from a NAL unit payload
+pub fn unescape_rbsp(nal: &[u8]) -> Vec<u8> {
+	let mut rbsp = Vec::with_capacity(nal.len());
+	let mut zeros = 0;
+	for &byte in nal {
+		if zeros >= 2 && byte == 3 {
+			zeros = 0;
+			continue;
+		}
+
+		zeros = if byte == 0 { zeros + 1 } else { 0 };
+		rbsp.push(byte);
+	}
+	rbsp
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn exp_golomb_codes() {
+		// 1 010 011 00100 00111
+		let mut reader = BitReader::new(&[0b1010_0110, 0b0100_0011, 0b1000_0000]);
+		assert_eq!((0..5).map(|_| reader.ue().unwrap()).collect::<Vec<_>>(), [0, 1, 2, 3, 6]);
+
+		// 1 010 011 00100 00101
+		let mut reader = BitReader::new(&[0b1010_0110, 0b0100_0010, 0b1000_0000]);
+		assert_eq!((0..5).map(|_| reader.se().unwrap()).collect::<Vec<_>>(), [0, 1, -1, 2, -2]);
+
+		// 31 leading zeros is the longest code that fits in 32 bits
+		let mut reader = BitReader::new(&[0, 0, 0, 1, 0xff, 0xff, 0xff, 0xfe]);
+		assert_eq!(reader.ue().unwrap(), u32::MAX - 1);
+	}
+
+	#[test]
+	fn malformed_exp_golomb_codes() {
+		assert_eq!(BitReader::new(&[0, 0, 0, 0, 0x80]).ue().unwrap_err().kind(), ErrorKind::InvalidData);
+		assert_eq!(BitReader::new(&[0]).ue().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+		assert_eq!(BitReader::new(&[0b0000_0010]).ue().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+	}
+}
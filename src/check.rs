@@ -0,0 +1,146 @@
+use std::io;
+use std::path::Path;
+
+use crate::index::index_file;
+use crate::mp4::BoxType;
+use crate::track::{self, Track};
+
+/// sample table boxes without which a track cannot be decoded
+pub const CRITICAL_BOXES: [BoxType; 4] = [BoxType::StsdBox, BoxType::SttsBox, BoxType::StszBox, BoxType::StscBox];
+
+/// Verdict of the static playability check
+pub struct CheckResult {
+	/// reasons that apply to the file as a whole
+	pub errors: Vec<String>,
+	/// each track alongside the reasons it cannot be played
+	pub tracks: Vec<(String, Vec<String>)>,
+}
+
+impl CheckResult {
+	/// a file is considered playable if at least one of its tracks is complete and consistent
+	pub fn is_playable(&self) -> bool {
+		self.errors.is_empty() && self.tracks.iter().any(|(_, errors)| errors.is_empty())
+	}
+}
+
+/// applies the minimum playability criteria without launching a player
+pub fn check(file: &Path) -> io::Result<CheckResult> {
+	let (index, file_size) = index_file(file, true)?;
+
+	let mut result = CheckResult {
+		errors: Vec::new(),
+		tracks: Vec::new(),
+	};
+
+	if index.find_top_level(BoxType::MoovBox).is_none() {
+		result.errors.push("no moov box".into());
+		return Ok(result);
+	}
+
+	let tracks = track::tracks(&index);
+	if tracks.is_empty() {
+		result.errors.push("moov box contains no trak boxes".into());
+	}
+
+	for track in tracks {
+		let errors = check_track(&track, file_size);
+		result.tracks.push((track.label(), errors));
+	}
+
+	Ok(result)
+}
+
+fn check_track(track: &Track, file_size: u64) -> Vec<String> {
+	let mut errors = track.errors.clone();
+
+	let (Some(stsd), Some(stts), Some(stsc), Some(stsz)) = (&track.stsd, &track.stts, &track.stsc, &track.stsz) else {
+		for (present, name) in [
+			(track.stsd.is_some(), "stsd"),
+			(track.stts.is_some(), "stts"),
+			(track.stsc.is_some(), "stsc"),
+			(track.stsz.is_some(), "stsz"),
+		] {
+			if !present {
+				errors.push(format!("missing {} box", name));
+			}
+		}
+		return errors;
+	};
+
+	let Some(chunk_offsets) = &track.chunk_offsets else {
+		errors.push("missing stco/co64 box".into());
+		return errors;
+	};
+
+	// declared table lengths must fit inside their boxes
+	for (truncated, name, declared) in [
+		(stsd.is_truncated(), "stsd", stsd.declared as usize),
+		(stts.is_truncated(), "stts", stts.declared as usize),
+		(stsc.is_truncated(), "stsc", stsc.declared as usize),
+		(stsz.sample_size == 0 && stsz.sizes.is_truncated(), "stsz", stsz.sizes.declared as usize),
+		(chunk_offsets.is_truncated(), "chunk offset", chunk_offsets.declared as usize),
+	] {
+		if truncated {
+			errors.push(format!("{} table declares {} entries but is cut short", name, declared));
+		}
+	}
+
+	let stsd_entries = stsd.declared;
+	if stsd_entries == 0 {
+		errors.push("stsd contains no sample descriptions".into());
+	}
+
+	let sample_count = stsz.sample_count() as u64;
+	if sample_count == 0 {
+		errors.push("stsz describes no samples".into());
+	}
+
+	let timed_samples: u64 = stts.entries.iter().map(|e| e.sample_count as u64).sum();
+	if timed_samples != sample_count {
+		errors.push(format!("stts describes {} samples but stsz describes {}", timed_samples, sample_count));
+	}
+	if timed_samples > 0 && stts.entries.iter().all(|e| e.sample_count == 0 || e.sample_delta == 0) {
+		errors.push("stts assigns a zero duration to every sample".into());
+	}
+
+	// stsc runs must start at chunk 1 and increase monotonically
+	if stsc.entries.is_empty() {
+		errors.push("stsc table is empty".into());
+	} else if stsc.entries[0].first_chunk != 1 {
+		errors.push(format!("first stsc entry starts at chunk {} instead of 1", stsc.entries[0].first_chunk));
+	}
+
+	let chunk_count = chunk_offsets.entries.len() as u64;
+	let mut chunked_samples = 0u64;
+	for (i, entry) in stsc.entries.iter().enumerate() {
+		if entry.samples_per_chunk == 0 {
+			errors.push(format!("stsc entry {} has zero samples per chunk", i + 1));
+		}
+		if entry.sample_description_index == 0 || entry.sample_description_index > stsd_entries {
+			errors.push(format!("stsc entry {} references sample description {} of {}", i + 1, entry.sample_description_index, stsd_entries));
+		}
+
+		let last_chunk = match stsc.entries.get(i + 1) {
+			Some(next) if next.first_chunk <= entry.first_chunk => {
+				errors.push(format!("stsc entry {} does not increase first_chunk ({} after {})", i + 2, next.first_chunk, entry.first_chunk));
+				break;
+			},
+			Some(next) => next.first_chunk as u64 - 1,
+			None => chunk_count,
+		};
+
+		if last_chunk >= entry.first_chunk as u64 {
+			chunked_samples += (last_chunk - entry.first_chunk as u64 + 1) * entry.samples_per_chunk as u64;
+		}
+	}
+
+	if chunked_samples < sample_count {
+		errors.push(format!("stsc and chunk offsets only account for {} of {} samples", chunked_samples, sample_count));
+	}
+
+	if let Some(offset) = chunk_offsets.entries.iter().find(|&&offset| offset >= file_size) {
+		errors.push(format!("chunk offset {:#x} points past the end of the file", offset));
+	}
+
+	errors
+}
@@ -0,0 +1,836 @@
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::bits::{unescape_rbsp, BitReader};
+use crate::index::index_file;
+use crate::mp4::BoxType;
+use crate::payload::PayloadReader;
+use crate::tags::fourcc;
+use crate::track::{self, SampleEntry, Track};
+
+/// size of the fixed fields of a VisualSampleEntry, before its child boxes
+const VISUAL_ENTRY_SIZE: usize = 78;
+/// size of the fixed fields of a version 0 AudioSampleEntry, before its child boxes
+const AUDIO_ENTRY_SIZE: usize = 28;
+
+/// sampling frequencies indexed by AAC's `samplingFrequencyIndex`
+const AAC_SAMPLE_RATES: [u32; 13] = [96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350];
+
+/// H.264 profiles whose SPS carries chroma format and bit depth fields
+const AVC_HIGH_PROFILES: [u8; 13] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+/// Fixed fields of a visual sample entry
+#[derive(Debug, Clone, Copy)]
+pub struct VisualEntry {
+	pub width: u16,
+	pub height: u16,
+	pub depth: u16,
+}
+
+/// Fixed fields of an audio sample entry
+#[derive(Debug, Clone, Copy)]
+pub struct AudioEntry {
+	pub channels: u16,
+	pub sample_size: u16,
+	pub sample_rate: u32,
+}
+
+/// `AVCDecoderConfigurationRecord` from `avcC`
+#[derive(Debug, Clone)]
+pub struct AvcConfig {
+	pub profile: u8,
+	pub compatibility: u8,
+	pub level: u8,
+	pub nal_length_size: u8,
+	pub sps: Vec<Vec<u8>>,
+	pub pps: Vec<Vec<u8>>,
+}
+
+/// The parts of an H.264 or H.265 sequence parameter set needed to identify a stream
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpsInfo {
+	pub width: u32,
+	pub height: u32,
+	pub chroma_format: u32,
+	pub bit_depth_luma: u32,
+	pub bit_depth_chroma: u32,
+	pub frame_mbs_only: bool,
+	/// frame rate from VUI timing info, if present
+	pub frame_rate: Option<f64>,
+	/// log2 of the maximum frame number (H.264 only), needed to parse slice headers
+	pub log2_max_frame_num: u32,
+	/// picture order count type and its lsb width (H.264 only)
+	pub poc_type: u32,
+	pub log2_max_poc_lsb: u32,
+	pub separate_colour_plane: bool,
+}
+
+/// `HEVCDecoderConfigurationRecord` from `hvcC`
+#[derive(Debug, Clone)]
+pub struct HevcConfig {
+	pub profile_space: u8,
+	pub tier: bool,
+	pub profile: u8,
+	pub level: u8,
+	pub chroma_format: u8,
+	pub bit_depth_luma: u8,
+	pub bit_depth_chroma: u8,
+	pub nal_length_size: u8,
+	/// parameter set arrays as (NAL unit type, NAL units)
+	pub arrays: Vec<(u8, Vec<Vec<u8>>)>,
+}
+
+/// `AudioSpecificConfig` from an `esds` DecoderSpecificInfo
+#[derive(Debug, Clone, Copy)]
+pub struct AudioSpecificConfig {
+	pub object_type: u8,
+	/// `samplingFrequencyIndex`, or 15 for an explicitly coded rate
+	pub sample_rate_index: u8,
+	pub sample_rate: u32,
+	pub channels: u8,
+	/// output sample rate of SBR (HE-AAC) streams
+	pub extension_sample_rate: Option<u32>,
+}
+
+/// `ES_Descriptor` from `esds`
+#[derive(Debug, Clone)]
+pub struct EsDescriptor {
+	pub object_type: u8,
+	pub stream_type: u8,
+	pub max_bitrate: u32,
+	pub avg_bitrate: u32,
+	pub decoder_specific_info: Option<Vec<u8>>,
+}
+
+/// `VPCodecConfigurationRecord` from `vpcC`
+#[derive(Debug, Clone, Copy)]
+pub struct VpConfig {
+	pub version: u8,
+	pub profile: u8,
+	pub level: u8,
+	pub bit_depth: u8,
+	pub chroma_subsampling: u8,
+	pub full_range: bool,
+	pub colour_primaries: u8,
+	pub transfer_characteristics: u8,
+	pub matrix_coefficients: u8,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+	io::Error::new(ErrorKind::InvalidData, message.into())
+}
+
+pub fn parse_visual_entry(entry: &SampleEntry) -> io::Result<VisualEntry> {
+	let mut reader = PayloadReader::new(&entry.data);
+	// reserved (6 B), data_reference_index (2 B), pre_defined and reserved (16 B)
+	reader.skip(24)?;
+	let size = reader.u32()?;
+	// horizontal and vertical resolution (8 B), reserved (4 B), frame_count (2 B), compressorname (32 B)
+	reader.skip(46)?;
+	let depth = reader.u16()?;
+
+	Ok(VisualEntry { width: (size >> 16) as u16, height: size as u16, depth })
+}
+
+pub fn parse_audio_entry(entry: &SampleEntry) -> io::Result<AudioEntry> {
+	let mut reader = PayloadReader::new(&entry.data);
+	// reserved (6 B), data_reference_index (2 B), version/reserved (8 B)
+	reader.skip(16)?;
+	let channels = reader.u16()?;
+	let sample_size = reader.u16()?;
+	reader.skip(4)?;
+	let sample_rate = reader.u32()? >> 16;
+
+	Ok(AudioEntry { channels, sample_size, sample_rate })
+}
+
+/// child boxes of a sample entry, following its fixed fields
+pub fn entry_children<'a>(entry: &'a SampleEntry, handler: Option<&str>) -> Vec<(BoxType, &'a [u8])> {
+	let offset = match handler {
+		Some("vide") => VISUAL_ENTRY_SIZE,
+		Some("soun") => {
+			// QuickTime sound descriptions v1 and v2 append extra fields
+			match entry.data.get(8..10).map(|v| u16::from_be_bytes([v[0], v[1]])) {
+				Some(1) => AUDIO_ENTRY_SIZE + 16,
+				Some(2) => AUDIO_ENTRY_SIZE + 36,
+				_ => AUDIO_ENTRY_SIZE,
+			}
+		},
+		_ => return Vec::new(),
+	};
+
+	match entry.data.get(offset..) {
+		Some(children) => PayloadReader::new(children).child_boxes().collect(),
+		None => Vec::new(),
+	}
+}
+
+/// finds a configuration box among the entry's children, looking inside QuickTime `wave` boxes too
+pub fn find_config<'a>(entry: &'a SampleEntry, handler: Option<&str>, typ: BoxType) -> Option<&'a [u8]> {
+	let children = entry_children(entry, handler);
+	children.iter().find(|(t, _)| *t == typ).map(|(_, c)| *c).or_else(|| {
+		let (_, wave) = children.iter().find(|(t, _)| *t == BoxType::WaveBox)?;
+		PayloadReader::new(wave).child_boxes().find(|(t, _)| *t == typ).map(|(_, c)| c)
+	})
+}
+
+/// reads `count` NAL units, each prefixed by a 16-bit length
+fn read_nal_units(reader: &mut PayloadReader, count: usize) -> io::Result<Vec<Vec<u8>>> {
+	(0..count)
+		.map(|_| {
+			let len = reader.u16()? as usize;
+			Ok(reader.bytes(len)?.to_vec())
+		})
+		.collect()
+}
+
+/// splits a sample of length-prefixed NAL units, also returning how many trailing bytes do not form a whole unit
+pub fn split_nal_units(sample: &[u8], nal_length_size: usize) -> (Vec<&[u8]>, usize) {
+	let mut nals = Vec::new();
+	let mut pos = 0;
+	while pos + nal_length_size <= sample.len() {
+		let len = sample[pos..pos + nal_length_size].iter().fold(0usize, |acc, &b| acc << 8 | b as usize);
+		pos += nal_length_size;
+		if len == 0 {
+			continue;
+		}
+		if pos + len > sample.len() {
+			return (nals, sample.len() - pos);
+		}
+		nals.push(&sample[pos..pos + len]);
+		pos += len;
+	}
+	(nals, 0)
+}
+
+pub fn parse_avcc(data: &[u8]) -> io::Result<AvcConfig> {
+	let mut reader = PayloadReader::new(data);
+	let header = reader.bytes(6)?;
+	if header[0] != 1 {
+		return Err(invalid(format!("unsupported avcC configuration version {}", header[0])));
+	}
+
+	let sps = read_nal_units(&mut reader, (header[5] & 0x1f) as usize)?;
+	let pps_count = reader.bytes(1)?[0] as usize;
+	let pps = read_nal_units(&mut reader, pps_count)?;
+
+	Ok(AvcConfig {
+		profile: header[1],
+		compatibility: header[2],
+		level: header[3],
+		nal_length_size: (header[4] & 3) + 1,
+		sps,
+		pps,
+	})
+}
+
+/// evaluates Exp-Golomb arithmetic that a corrupt parameter set can push past u32
+fn in_range(value: Option<u32>, what: &str) -> io::Result<u32> {
+	value.ok_or_else(|| invalid(format!("{} out of range", what)))
+}
+
+/// frame size in samples, less the cropped edges
+fn cropped(size: Option<u32>, unit: u32, start: u32, end: u32, what: &str) -> io::Result<u32> {
+	let crop = start.checked_add(end).and_then(|crop| crop.checked_mul(unit));
+	Ok(in_range(size, what)?.saturating_sub(in_range(crop, "cropping window")?))
+}
+
+/// skips an H.264 scaling list of the given size
+fn skip_scaling_list(bits: &mut BitReader, size: usize) -> io::Result<()> {
+	let mut last = 8;
+	let mut next = 8;
+	for _ in 0..size {
+		if next != 0 {
+			let delta = bits.se()?;
+			if !(-128..=127).contains(&delta) {
+				return Err(invalid(format!("scaling list delta {} out of range", delta)));
+			}
+			next = (last + delta + 256) % 256;
+		}
+		last = if next == 0 { last } else { next };
+	}
+	Ok(())
+}
+
+/// decodes an H.264 SPS NAL unit (including its one-byte header)
+pub fn parse_avc_sps(nal: &[u8]) -> io::Result<SpsInfo> {
+	let rbsp = unescape_rbsp(nal.get(1..).ok_or_else(|| invalid("empty SPS"))?);
+	let mut bits = BitReader::new(&rbsp);
+
+	let profile = bits.bits(8)? as u8;
+	bits.skip(16)?; // constraint flags, level
+	bits.ue()?; // seq_parameter_set_id
+
+	let mut info = SpsInfo { chroma_format: 1, bit_depth_luma: 8, bit_depth_chroma: 8, ..Default::default() };
+	if AVC_HIGH_PROFILES.contains(&profile) {
+		info.chroma_format = bits.ue()?;
+		if info.chroma_format == 3 {
+			info.separate_colour_plane = bits.bit()?;
+		}
+		info.bit_depth_luma = in_range(bits.ue()?.checked_add(8), "luma bit depth")?;
+		info.bit_depth_chroma = in_range(bits.ue()?.checked_add(8), "chroma bit depth")?;
+		bits.skip(1)?; // qpprime_y_zero_transform_bypass_flag
+		if bits.bit()? {
+			for i in 0..if info.chroma_format == 3 { 12 } else { 8 } {
+				if bits.bit()? {
+					skip_scaling_list(&mut bits, if i < 6 { 16 } else { 64 })?;
+				}
+			}
+		}
+	}
+
+	info.log2_max_frame_num = in_range(bits.ue()?.checked_add(4), "log2_max_frame_num")?;
+	info.poc_type = bits.ue()?;
+	match info.poc_type {
+		0 => info.log2_max_poc_lsb = in_range(bits.ue()?.checked_add(4), "log2_max_pic_order_cnt_lsb")?,
+		1 => {
+			bits.skip(1)?; // delta_pic_order_always_zero_flag
+			bits.se()?; // offset_for_non_ref_pic
+			bits.se()?; // offset_for_top_to_bottom_field
+			for _ in 0..bits.ue()? {
+				bits.se()?;
+			}
+		},
+		_ => {},
+	}
+
+	bits.ue()?; // max_num_ref_frames
+	bits.skip(1)?; // gaps_in_frame_num_value_allowed_flag
+	let width_mbs = in_range(bits.ue()?.checked_add(1), "picture width")?;
+	let height_map_units = in_range(bits.ue()?.checked_add(1), "picture height")?;
+	info.frame_mbs_only = bits.bit()?;
+	if !info.frame_mbs_only {
+		bits.skip(1)?; // mb_adaptive_frame_field_flag
+	}
+	bits.skip(1)?; // direct_8x8_inference_flag
+
+	let (mut crop_left, mut crop_right, mut crop_top, mut crop_bottom) = (0, 0, 0, 0);
+	if bits.bit()? {
+		crop_left = bits.ue()?;
+		crop_right = bits.ue()?;
+		crop_top = bits.ue()?;
+		crop_bottom = bits.ue()?;
+	}
+
+	let field_factor = if info.frame_mbs_only { 1 } else { 2 };
+	let (crop_x, crop_y) = match info.chroma_format {
+		0 => (1, field_factor),
+		1 => (2, 2 * field_factor),
+		2 => (2, field_factor),
+		_ => (1, field_factor),
+	};
+	info.width = cropped(width_mbs.checked_mul(16), crop_x, crop_left, crop_right, "picture width")?;
+	info.height = cropped(height_map_units.checked_mul(16 * field_factor), crop_y, crop_top, crop_bottom, "picture height")?;
+
+	// VUI is optional and frequently truncated, so failing to read it is not an error
+	if bits.bit().unwrap_or(false) {
+		info.frame_rate = parse_vui_timing(&mut bits).ok().flatten();
+	}
+
+	Ok(info)
+}
+
+/// reads the VUI up to its timing info, returning the frame rate it implies
+fn parse_vui_timing(bits: &mut BitReader) -> io::Result<Option<f64>> {
+	if bits.bit()? {
+		// aspect_ratio_idc, with an explicit sample aspect ratio for Extended_SAR
+		if bits.bits(8)? == 255 {
+			bits.skip(32)?;
+		}
+	}
+	if bits.bit()? {
+		bits.skip(1)?; // overscan_appropriate_flag
+	}
+	if bits.bit()? {
+		bits.skip(4)?; // video_format, video_full_range_flag
+		if bits.bit()? {
+			bits.skip(24)?; // colour description
+		}
+	}
+	if bits.bit()? {
+		bits.ue()?; // chroma_sample_loc_type_top_field
+		bits.ue()?; // chroma_sample_loc_type_bottom_field
+	}
+	if !bits.bit()? {
+		return Ok(None);
+	}
+
+	let num_units_in_tick = bits.bits(32)?;
+	let time_scale = bits.bits(32)?;
+	if num_units_in_tick == 0 {
+		return Ok(None);
+	}
+
+	// each frame spans two field ticks
+	Ok(Some(time_scale as f64 / (2.0 * num_units_in_tick as f64)))
+}
+
+pub fn parse_hvcc(data: &[u8]) -> io::Result<HevcConfig> {
+	let mut reader = PayloadReader::new(data);
+	let version = reader.bytes(1)?[0];
+	if version != 1 {
+		return Err(invalid(format!("unsupported hvcC configuration version {}", version)));
+	}
+
+	let profile_byte = reader.bytes(1)?[0];
+	reader.skip(10)?; // compatibility flags (4 B), constraint indicator flags (6 B)
+	let level = reader.bytes(1)?[0];
+	reader.skip(3)?; // min_spatial_segmentation_idc, parallelismType
+	let chroma_format = reader.bytes(1)?[0] & 3;
+	let bit_depth_luma = (reader.bytes(1)?[0] & 7) + 8;
+	let bit_depth_chroma = (reader.bytes(1)?[0] & 7) + 8;
+	reader.skip(2)?; // avgFrameRate
+	let nal_length_size = (reader.bytes(1)?[0] & 3) + 1;
+
+	let array_count = reader.bytes(1)?[0];
+	let mut arrays = Vec::new();
+	for _ in 0..array_count {
+		let nal_type = reader.bytes(1)?[0] & 0x3f;
+		let count = reader.u16()? as usize;
+		arrays.push((nal_type, read_nal_units(&mut reader, count)?));
+	}
+
+	Ok(HevcConfig {
+		profile_space: profile_byte >> 6,
+		tier: profile_byte & 0x20 != 0,
+		profile: profile_byte & 0x1f,
+		level,
+		chroma_format,
+		bit_depth_luma,
+		bit_depth_chroma,
+		nal_length_size,
+		arrays,
+	})
+}
+
+/// decodes an H.265 SPS NAL unit (including its two-byte header)
+pub fn parse_hevc_sps(nal: &[u8]) -> io::Result<SpsInfo> {
+	let rbsp = unescape_rbsp(nal.get(2..).ok_or_else(|| invalid("empty SPS"))?);
+	let mut bits = BitReader::new(&rbsp);
+
+	bits.skip(4)?; // sps_video_parameter_set_id
+	let max_sub_layers = bits.bits(3)? + 1;
+	bits.skip(1)?; // sps_temporal_id_nesting_flag
+
+	// profile_tier_level: general profile (88 bits) and level (8 bits)
+	bits.skip(96)?;
+	let mut sub_layer_flags = Vec::new();
+	for _ in 1..max_sub_layers {
+		sub_layer_flags.push((bits.bit()?, bits.bit()?));
+	}
+	if max_sub_layers > 1 {
+		bits.skip(2 * (9 - max_sub_layers as usize))?;
+	}
+	for (profile_present, level_present) in sub_layer_flags {
+		if profile_present {
+			bits.skip(88)?;
+		}
+		if level_present {
+			bits.skip(8)?;
+		}
+	}
+
+	bits.ue()?; // sps_seq_parameter_set_id
+	let mut info = SpsInfo { frame_mbs_only: true, ..Default::default() };
+	info.chroma_format = bits.ue()?;
+	if info.chroma_format == 3 {
+		info.separate_colour_plane = bits.bit()?;
+	}
+	info.width = bits.ue()?;
+	info.height = bits.ue()?;
+
+	if bits.bit()? {
+		let (sub_width, sub_height) = match info.chroma_format {
+			1 => (2, 2),
+			2 => (2, 1),
+			_ => (1, 1),
+		};
+		let (left, right, top, bottom) = (bits.ue()?, bits.ue()?, bits.ue()?, bits.ue()?);
+		info.width = cropped(Some(info.width), sub_width, left, right, "picture width")?;
+		info.height = cropped(Some(info.height), sub_height, top, bottom, "picture height")?;
+	}
+
+	info.bit_depth_luma = in_range(bits.ue()?.checked_add(8), "luma bit depth")?;
+	info.bit_depth_chroma = in_range(bits.ue()?.checked_add(8), "chroma bit depth")?;
+
+	Ok(info)
+}
+
+/// reads an MPEG-4 descriptor header, returning its tag and content
+fn read_descriptor<'a>(reader: &mut PayloadReader<'a>) -> io::Result<(u8, &'a [u8])> {
+	let tag = reader.bytes(1)?[0];
+	let mut size = 0usize;
+	for _ in 0..4 {
+		let byte = reader.bytes(1)?[0];
+		size = size << 7 | (byte & 0x7f) as usize;
+		if byte & 0x80 == 0 {
+			break;
+		}
+	}
+
+	Ok((tag, reader.bytes(size.min(reader.remaining()))?))
+}
+
+pub fn parse_esds(data: &[u8]) -> io::Result<EsDescriptor> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+
+	let (tag, es) = read_descriptor(&mut reader)?;
+	if tag != 0x03 {
+		return Err(invalid(format!("expected ES_Descriptor (tag 3), found tag {}", tag)));
+	}
+
+	let mut es = PayloadReader::new(es);
+	es.skip(2)?; // ES_ID
+	let flags = es.bytes(1)?[0];
+	if flags & 0x80 != 0 {
+		es.skip(2)?; // dependsOn_ES_ID
+	}
+	if flags & 0x40 != 0 {
+		let len = es.bytes(1)?[0] as usize;
+		es.skip(len)?; // URL
+	}
+	if flags & 0x20 != 0 {
+		es.skip(2)?; // OCR_ES_Id
+	}
+
+	while es.remaining() > 0 {
+		let (tag, content) = read_descriptor(&mut es)?;
+		if tag != 0x04 {
+			continue;
+		}
+
+		let mut config = PayloadReader::new(content);
+		let object_type = config.bytes(1)?[0];
+		let stream_type = config.bytes(1)?[0] >> 2;
+		config.skip(3)?; // bufferSizeDB
+		let max_bitrate = config.u32()?;
+		let avg_bitrate = config.u32()?;
+
+		let mut decoder_specific_info = None;
+		while config.remaining() > 0 {
+			let (tag, content) = read_descriptor(&mut config)?;
+			if tag == 0x05 {
+				decoder_specific_info = Some(content.to_vec());
+				break;
+			}
+		}
+
+		return Ok(EsDescriptor { object_type, stream_type, max_bitrate, avg_bitrate, decoder_specific_info });
+	}
+
+	Err(invalid("ES_Descriptor has no DecoderConfigDescriptor"))
+}
+
+fn read_sample_rate(bits: &mut BitReader) -> io::Result<(u8, u32)> {
+	let index = bits.bits(4)? as u8;
+	let rate = match index {
+		0xf => bits.bits(24)?,
+		i => *AAC_SAMPLE_RATES.get(i as usize).ok_or_else(|| invalid(format!("reserved sampling frequency index {}", i)))?,
+	};
+	Ok((index, rate))
+}
+
+fn read_object_type(bits: &mut BitReader) -> io::Result<u8> {
+	let object_type = bits.bits(5)? as u8;
+	Ok(if object_type == 31 { 32 + bits.bits(6)? as u8 } else { object_type })
+}
+
+pub fn parse_audio_specific_config(data: &[u8]) -> io::Result<AudioSpecificConfig> {
+	let mut bits = BitReader::new(data);
+	let mut object_type = read_object_type(&mut bits)?;
+	let (sample_rate_index, sample_rate) = read_sample_rate(&mut bits)?;
+	let channels = bits.bits(4)? as u8;
+
+	// explicitly signalled SBR/PS: the core object type follows the extension sample rate
+	let mut extension_sample_rate = None;
+	if object_type == 5 || object_type == 29 {
+		extension_sample_rate = Some(read_sample_rate(&mut bits)?.1);
+		object_type = read_object_type(&mut bits)?;
+	}
+
+	Ok(AudioSpecificConfig { object_type, sample_rate_index, sample_rate, channels, extension_sample_rate })
+}
+
+pub fn parse_vpcc(data: &[u8]) -> io::Result<VpConfig> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+	let fields = reader.bytes(6)?;
+
+	Ok(match version {
+		1 => VpConfig {
+			version,
+			profile: fields[0],
+			level: fields[1],
+			bit_depth: fields[2] >> 4,
+			chroma_subsampling: fields[2] >> 1 & 7,
+			full_range: fields[2] & 1 != 0,
+			colour_primaries: fields[3],
+			transfer_characteristics: fields[4],
+			matrix_coefficients: fields[5],
+		},
+		// the original draft packed a colour space where the primaries now are
+		0 => VpConfig {
+			version,
+			profile: fields[0],
+			level: fields[1],
+			bit_depth: fields[2] >> 4,
+			chroma_subsampling: fields[3] >> 4,
+			full_range: fields[3] & 1 != 0,
+			colour_primaries: fields[2] & 0xf,
+			transfer_characteristics: fields[3] >> 1 & 7,
+			matrix_coefficients: 2,
+		},
+		v => return Err(invalid(format!("unsupported vpcC version {}", v))),
+	})
+}
+
+fn avc_profile_name(profile: u8) -> &'static str {
+	match profile {
+		66 => "Baseline",
+		77 => "Main",
+		88 => "Extended",
+		100 => "High",
+		110 => "High 10",
+		122 => "High 4:2:2",
+		244 => "High 4:4:4 Predictive",
+		44 => "CAVLC 4:4:4 Intra",
+		_ => "unknown",
+	}
+}
+
+fn hevc_profile_name(profile: u8) -> &'static str {
+	match profile {
+		1 => "Main",
+		2 => "Main 10",
+		3 => "Main Still Picture",
+		4 => "Range Extensions",
+		_ => "unknown",
+	}
+}
+
+fn hevc_nal_name(nal_type: u8) -> &'static str {
+	match nal_type {
+		32 => "VPS",
+		33 => "SPS",
+		34 => "PPS",
+		39 | 40 => "SEI",
+		_ => "NAL",
+	}
+}
+
+pub fn audio_object_type_name(object_type: u8) -> &'static str {
+	match object_type {
+		1 => "AAC Main",
+		2 => "AAC LC",
+		3 => "AAC SSR",
+		4 => "AAC LTP",
+		5 => "SBR",
+		29 => "PS",
+		23 => "ER AAC LD",
+		39 => "ER AAC ELD",
+		_ => "unknown",
+	}
+}
+
+fn es_object_type_name(object_type: u8) -> &'static str {
+	match object_type {
+		0x20 => "MPEG-4 Visual",
+		0x21 => "H.264",
+		0x40 => "MPEG-4 Audio",
+		0x66..=0x68 => "MPEG-2 AAC",
+		0x69 | 0x6b => "MP3",
+		0xa9 => "DTS",
+		0xa5 => "AC-3",
+		0xa6 => "E-AC-3",
+		_ => "unknown",
+	}
+}
+
+struct Chroma(u32);
+
+impl fmt::Display for Chroma {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.0 {
+			0 => write!(f, "monochrome"),
+			1 => write!(f, "4:2:0"),
+			2 => write!(f, "4:2:2"),
+			3 => write!(f, "4:4:4"),
+			n => write!(f, "chroma format {}", n),
+		}
+	}
+}
+
+fn print_sps(label: &str, info: &SpsInfo, visual: Option<VisualEntry>) {
+	print!("    {}: {}x{}, {}, {}-bit", label, info.width, info.height, Chroma(info.chroma_format), info.bit_depth_luma);
+	if info.bit_depth_chroma != info.bit_depth_luma {
+		print!(" ({}-bit chroma)", info.bit_depth_chroma);
+	}
+	if !info.frame_mbs_only {
+		print!(", interlaced");
+	}
+	if let Some(fps) = info.frame_rate {
+		print!(", {:.3} fps (VUI)", fps);
+	}
+	match visual {
+		Some(visual) if (visual.width as u32, visual.height as u32) != (info.width, info.height) => println!(" [sample entry says {}x{}]", visual.width, visual.height),
+		_ => println!(),
+	}
+}
+
+/// prints the decoded codec configuration of a single sample entry
+fn print_entry(track: &Track, entry: &SampleEntry) {
+	let handler = track.handler.as_deref();
+	let mut visual = None;
+	match handler {
+		Some("vide") => match parse_visual_entry(entry) {
+			Ok(entry_fields) => {
+				println!("  {}: {}x{}, depth {}", fourcc(entry.format), entry_fields.width, entry_fields.height, entry_fields.depth);
+				visual = Some(entry_fields);
+			},
+			Err(e) => println!("  {}: unable to decode visual sample entry: {}", fourcc(entry.format), e),
+		},
+		Some("soun") => match parse_audio_entry(entry) {
+			Ok(audio) => println!("  {}: {} channels, {}-bit, {} Hz", fourcc(entry.format), audio.channels, audio.sample_size, audio.sample_rate),
+			Err(e) => println!("  {}: unable to decode audio sample entry: {}", fourcc(entry.format), e),
+		},
+		_ => println!("  {}", fourcc(entry.format)),
+	}
+
+	let mut found = false;
+	for (typ, content) in entry_children(entry, handler) {
+		match typ {
+			BoxType::AvcCBox => print_avcc(content, visual),
+			BoxType::HvcCBox => print_hvcc(content, visual),
+			BoxType::VpccBox => print_vpcc(content),
+			BoxType::EsdsBox => print_esds(content),
+			_ => continue,
+		}
+		found = true;
+	}
+
+	if !found {
+		if let Some(esds) = find_config(entry, handler, BoxType::EsdsBox) {
+			print_esds(esds);
+		} else {
+			println!("    no supported configuration box");
+		}
+	}
+}
+
+fn print_avcc(content: &[u8], visual: Option<VisualEntry>) {
+	let config = match parse_avcc(content) {
+		Ok(config) => config,
+		Err(e) => return println!("    avcC: unable to decode: {}", e),
+	};
+
+	println!("    avcC: profile {} ({}, constraints {:#04x}), level {:.1}, {} B NAL lengths, {} SPS, {} PPS",
+		avc_profile_name(config.profile), config.profile, config.compatibility, config.level as f64 / 10.0,
+		config.nal_length_size, config.sps.len(), config.pps.len());
+
+	for sps in &config.sps {
+		match parse_avc_sps(sps) {
+			Ok(info) => print_sps("SPS", &info, visual),
+			Err(e) => println!("    SPS: unable to decode: {}", e),
+		}
+	}
+}
+
+fn print_hvcc(content: &[u8], visual: Option<VisualEntry>) {
+	let config = match parse_hvcc(content) {
+		Ok(config) => config,
+		Err(e) => return println!("    hvcC: unable to decode: {}", e),
+	};
+
+	print!("    hvcC: profile {} ({}", hevc_profile_name(config.profile), config.profile);
+	if config.profile_space != 0 {
+		print!(", profile space {}", config.profile_space);
+	}
+	print!("), {} tier, level {:.1}, {}, {}-bit", if config.tier { "High" } else { "Main" }, config.level as f64 / 30.0, Chroma(config.chroma_format as u32), config.bit_depth_luma);
+	if config.bit_depth_chroma != config.bit_depth_luma {
+		print!(" ({}-bit chroma)", config.bit_depth_chroma);
+	}
+	println!(", {} B NAL lengths", config.nal_length_size);
+
+	for (nal_type, nalus) in &config.arrays {
+		let sizes: Vec<String> = nalus.iter().map(|n| format!("{} B", n.len())).collect();
+		println!("    {} (type {}): {}", hevc_nal_name(*nal_type), nal_type, sizes.join(", "));
+		if *nal_type == 33 {
+			for sps in nalus {
+				match parse_hevc_sps(sps) {
+					Ok(info) => print_sps("SPS", &info, visual),
+					Err(e) => println!("    SPS: unable to decode: {}", e),
+				}
+			}
+		}
+	}
+}
+
+fn print_vpcc(content: &[u8]) {
+	match parse_vpcc(content) {
+		Ok(config) => {
+			let chroma = match config.chroma_subsampling {
+				0 | 1 => "4:2:0",
+				2 => "4:2:2",
+				3 => "4:4:4",
+				_ => "unknown",
+			};
+			println!("    vpcC v{}: profile {}, level {:.1}, {}-bit, {}{}, primaries {}, transfer {}, matrix {}",
+				config.version, config.profile, config.level as f64 / 10.0, config.bit_depth, chroma,
+				if config.full_range { ", full range" } else { "" },
+				config.colour_primaries, config.transfer_characteristics, config.matrix_coefficients);
+		},
+		Err(e) => println!("    vpcC: unable to decode: {}", e),
+	}
+}
+
+fn print_esds(content: &[u8]) {
+	let es = match parse_esds(content) {
+		Ok(es) => es,
+		Err(e) => return println!("    esds: unable to decode: {}", e),
+	};
+
+	println!("    esds: object type {:#04x} ({}), stream type {}, avg {} bit/s, max {} bit/s",
+		es.object_type, es_object_type_name(es.object_type), es.stream_type, es.avg_bitrate, es.max_bitrate);
+
+	if es.object_type != 0x40 {
+		return;
+	}
+	match es.decoder_specific_info.as_deref().map(parse_audio_specific_config) {
+		Some(Ok(asc)) => {
+			print!("    AudioSpecificConfig: {} ({}), {} Hz, {} channels", audio_object_type_name(asc.object_type), asc.object_type, asc.sample_rate, asc.channels);
+			match asc.extension_sample_rate {
+				Some(rate) => println!(", SBR output {} Hz", rate),
+				None => println!(),
+			}
+		},
+		Some(Err(e)) => println!("    AudioSpecificConfig: unable to decode: {}", e),
+		None => println!("    esds has no DecoderSpecificInfo"),
+	}
+}
+
+/// prints the codec configuration records of every sample entry, or only those of one track
+pub fn codec(file: &Path, track_id: Option<u32>) -> io::Result<()> {
+	let (index, _file_size) = index_file(file, true)?;
+	let tracks = track::tracks(&index);
+
+	let mut printed = false;
+	for track in tracks.iter().filter(|t| track_id.is_none() || t.tkhd.as_ref().map(|h| h.track_id) == track_id) {
+		println!("{}", track.label());
+		match &track.stsd {
+			Some(stsd) if !stsd.entries.is_empty() => stsd.entries.iter().for_each(|entry| print_entry(track, entry)),
+			_ => println!("  no sample descriptions"),
+		}
+		printed = true;
+	}
+
+	if !printed {
+		return Err(io::Error::new(ErrorKind::NotFound, match track_id {
+			Some(id) => format!("file has no track with ID {}", id),
+			None => "file has no tracks".to_string(),
+		}));
+	}
+
+	Ok(())
+}
@@ -0,0 +1,117 @@
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::index::index_file;
+use crate::mp4::BoxType;
+use crate::track;
+
+/// A sample's byte range, tagged with the track it belongs to
+struct Extent {
+	track: usize,
+	number: u32,
+	range: Range<u64>,
+}
+
+/// reports how much of each `mdat` box is referenced by the sample tables, and where they disagree with the file
+pub fn coverage(file: &Path, limit: usize) -> io::Result<()> {
+	let (index, file_size) = index_file(file, true)?;
+	let tracks = track::tracks(&index);
+
+	let mdats: Vec<Range<u64>> = index.find_all(BoxType::MdatBox).into_iter()
+		.map(|i| index.boxes[i].content_offset()..index.boxes[i].end())
+		.collect();
+
+	let mut extents = Vec::new();
+	for (t, track) in tracks.iter().enumerate() {
+		let locations = track.sample_locations();
+		let total_bytes: u64 = locations.iter().map(|l| l.size as u64).sum();
+		println!("{}: {} samples located, {} B", track.label(), locations.len(), total_bytes);
+
+		let expected = track.stsz.as_ref().map(|stsz| stsz.sample_count()).unwrap_or(0);
+		if (locations.len() as u32) < expected {
+			println!("  {} of {} samples could not be assigned to a chunk", expected - locations.len() as u32, expected);
+		}
+
+		let past_eof: Vec<_> = locations.iter().filter(|l| l.end() > file_size).collect();
+		if let Some(first) = past_eof.first() {
+			println!("  {} samples extend past the end of the file (first: sample {} in chunk {} at {:#x})", past_eof.len(), first.number, first.chunk, first.offset);
+		}
+
+		let outside_mdat: Vec<_> = locations.iter()
+			.filter(|l| !mdats.iter().any(|m| m.start <= l.offset && l.end() <= m.end))
+			.collect();
+		if let Some(first) = outside_mdat.first() {
+			println!("  {} samples lie outside of any mdat payload (first: sample {} in chunk {} at {:#x})", outside_mdat.len(), first.number, first.chunk, first.offset);
+		}
+
+		extents.extend(locations.iter().filter(|l| l.size > 0).map(|l| Extent { track: t, number: l.number, range: l.offset..l.end() }));
+	}
+
+	extents.sort_by_key(|e| (e.range.start, e.range.end));
+
+	// union of all sample ranges, used to find referenced and unreferenced bytes
+	let mut referenced: Vec<Range<u64>> = Vec::new();
+	for extent in &extents {
+		match referenced.last_mut() {
+			Some(last) if extent.range.start <= last.end => last.end = last.end.max(extent.range.end),
+			_ => referenced.push(extent.range.clone()),
+		}
+	}
+
+	for mdat in &mdats {
+		let mut covered = 0;
+		let mut gaps = Vec::new();
+		let mut cursor = mdat.start;
+
+		for range in referenced.iter().filter(|r| r.start < mdat.end && r.end > mdat.start) {
+			let start = range.start.max(mdat.start);
+			let end = range.end.min(mdat.end);
+			if start > cursor {
+				gaps.push(cursor..start);
+			}
+			covered += end - start;
+			cursor = cursor.max(end);
+		}
+		if cursor < mdat.end {
+			gaps.push(cursor..mdat.end);
+		}
+
+		let length = mdat.end - mdat.start;
+		let percent = if length == 0 { 100.0 } else { covered as f64 * 100.0 / length as f64 };
+		println!("mdat payload at {:#x} ({} B): {} B referenced ({:.2}%), {} unreferenced gap{}", mdat.start, length, covered, percent, gaps.len(), if gaps.len() == 1 { "" } else { "s" });
+
+		for gap in gaps.iter().take(limit) {
+			println!("  gap {:#x}..{:#x} ({} B)", gap.start, gap.end, gap.end - gap.start);
+		}
+		if gaps.len() > limit {
+			println!("  … {} more gaps", gaps.len() - limit);
+		}
+	}
+
+	// sweep the sorted extents, comparing each against the furthest-reaching extent seen so far
+	let mut overlaps = Vec::new();
+	let mut furthest: Option<&Extent> = None;
+	for extent in &extents {
+		if let Some(previous) = furthest {
+			if extent.range.start < previous.range.end {
+				overlaps.push((previous, extent));
+			}
+		}
+		if furthest.is_none_or(|f| extent.range.end > f.range.end) {
+			furthest = Some(extent);
+		}
+	}
+
+	println!("{} overlapping sample{}", overlaps.len(), if overlaps.len() == 1 { "" } else { "s" });
+	for (a, b) in overlaps.iter().take(limit) {
+		println!("  {} sample {} ({:#x}..{:#x}) overlaps {} sample {} ({:#x}..{:#x})",
+			tracks[a.track].label(), a.number, a.range.start, a.range.end,
+			tracks[b.track].label(), b.number, b.range.start, b.range.end);
+	}
+	if overlaps.len() > limit {
+		println!("  … {} more overlaps", overlaps.len() - limit);
+	}
+
+	Ok(())
+}
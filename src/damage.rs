@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::check::CRITICAL_BOXES;
+use crate::index::{index_file, Segment};
+use crate::mangle::{self, Mode};
+use crate::mp4::BoxType;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Default)]
+struct PathStats {
+	ranges: u64,
+	bytes: u64,
+	header_bytes: u64,
+	critical: bool,
+}
+
+/// maps the differences between an original file and its mangled copy onto the original's box tree
+pub fn damage_map(original: &Path, mangled: &Path) -> io::Result<()> {
+	let original_size = original.metadata()?.len();
+	let mangled_size = mangled.metadata()?.len();
+	let ranges = diff_ranges(original, mangled)?;
+
+	println!("compared {} B original against {} B mangled file", original_size, mangled_size);
+	if mangled_size < original_size {
+		println!("mangled file is missing the last {} B of the original", original_size - mangled_size);
+	} else if mangled_size > original_size {
+		println!("mangled file has {} B appended beyond the end of the original", mangled_size - original_size);
+	}
+
+	report(original, &ranges)
+}
+
+/// maps the ranges recorded in a mangle log onto the original file's box tree, without needing the mangled copy
+pub fn damage_from_log(original: &Path, log: &Path) -> io::Result<()> {
+	let record = mangle::read_log(log)?;
+	let original_size = original.metadata()?.len();
+	if original_size != record.file_size {
+		log::warn!("{} is {} B but the logged file was {} B", original.display(), original_size, record.file_size);
+	} else if let Some(expected) = &record.original_hash {
+		let f = File::open(original)?;
+		if &mangle::file_hash(&f)? != expected {
+			log::warn!("{} does not match the hash of the logged file; the map may not apply to it", original.display());
+		}
+	}
+
+	let mut ranges: Vec<Range<u64>> = record.touches.iter().map(|touch| match record.mode {
+		Mode::Flip { .. } => touch.offset..touch.offset + 1,
+		Mode::Blank { .. } | Mode::ZeroRange { .. } => touch.offset..touch.offset + touch.original.len() as u64,
+		Mode::Truncate { .. } => touch.offset..record.file_size,
+	}).filter(|r| r.start < r.end).collect();
+
+	// repeated hits on the same bytes count once, as they would in a comparison of the files
+	ranges.sort_by_key(|r| r.start);
+	let mut merged: Vec<Range<u64>> = Vec::new();
+	for range in ranges {
+		match merged.last_mut() {
+			Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+			_ => merged.push(range),
+		}
+	}
+
+	println!("read {} change{} from {}", record.touches.len(), if record.touches.len() == 1 { "" } else { "s" }, log.display());
+	if let Mode::Truncate { bytes } = record.mode {
+		println!("mangled file is missing the last {} B of the original", bytes);
+	}
+
+	report(original, &merged)
+}
+
+/// prints per-box statistics for the given altered byte ranges of `original`
+fn report(original: &Path, ranges: &[Range<u64>]) -> io::Result<()> {
+	let (index, size) = index_file(original, false)?;
+	let segments = index.segments(size);
+
+	let mut stats: BTreeMap<String, PathStats> = BTreeMap::new();
+	let mut metadata_ranges = 0;
+	let mut total_bytes = 0;
+
+	for range in ranges {
+		total_bytes += range.end - range.start;
+
+		let mut touched_paths = Vec::new();
+		let mut touched_metadata = false;
+		for segment in overlapping(&segments, range) {
+			let overlap = range.end.min(segment.end) - range.start.max(segment.start);
+			let (path, critical) = match segment.owner {
+				Some(i) => (index.path(i), CRITICAL_BOXES.contains(&index.boxes[i].name)),
+				None => ("<unparsed>".to_string(), false),
+			};
+
+			let is_media = !segment.is_header && segment.owner.is_some_and(|i| index.boxes[i].name == BoxType::MdatBox);
+			touched_metadata |= !is_media;
+
+			let entry = stats.entry(path.clone()).or_default();
+			entry.bytes += overlap;
+			entry.critical |= critical;
+			if segment.is_header {
+				entry.header_bytes += overlap;
+			}
+
+			if !touched_paths.contains(&path) {
+				touched_paths.push(path);
+			}
+		}
+
+		for path in touched_paths {
+			stats.get_mut(&path).unwrap().ranges += 1;
+		}
+
+		if touched_metadata {
+			metadata_ranges += 1;
+		}
+	}
+
+	println!("{} altered range{} ({} B)", ranges.len(), if ranges.len() == 1 { "" } else { "s" }, total_bytes);
+	if stats.is_empty() {
+		return Ok(());
+	}
+
+	println!("{:>8} {:>12} {:>8}  path", "ranges", "bytes", "header");
+	for (path, entry) in &stats {
+		println!("{:>8} {:>12} {:>8}  {}{}", entry.ranges, entry.bytes, entry.header_bytes, path, if entry.critical { "  [critical]" } else { "" });
+	}
+
+	println!("{} of {} range{} touched metadata outside of mdat payload", metadata_ranges, ranges.len(), if ranges.len() == 1 { "" } else { "s" });
+
+	let critical_hits: Vec<&String> = stats.iter().filter(|(_, s)| s.critical).map(|(path, _)| path).collect();
+	if !critical_hits.is_empty() {
+		log::warn!("playability-critical tables were hit:");
+		for path in critical_hits {
+			log::warn!("  {}", path);
+		}
+	}
+
+	Ok(())
+}
+
+/// segments (sorted by offset) that share at least one byte with `range`
+fn overlapping<'a>(segments: &'a [Segment], range: &'a Range<u64>) -> impl Iterator<Item = &'a Segment> {
+	let first = segments.partition_point(|s| s.end <= range.start);
+	segments[first..].iter().take_while(move |s| s.start < range.end)
+}
+
+/// byte ranges of `original` that differ in `mangled`; bytes missing from a shorter `mangled` count as altered
+fn diff_ranges(original: &Path, mangled: &Path) -> io::Result<Vec<Range<u64>>> {
+	let mut a = io::BufReader::new(File::open(original)?);
+	let mut b = io::BufReader::new(File::open(mangled)?);
+
+	let mut ranges: Vec<Range<u64>> = Vec::new();
+	let mut buf_a = vec![0u8; CHUNK_SIZE];
+	let mut buf_b = vec![0u8; CHUNK_SIZE];
+	let mut position = 0u64;
+
+	loop {
+		let len_a = read_full(&mut a, &mut buf_a)?;
+		if len_a == 0 {
+			break;
+		}
+		let len_b = read_full(&mut b, &mut buf_b)?;
+
+		for i in 0..len_a {
+			if i >= len_b || buf_a[i] != buf_b[i] {
+				let offset = position + i as u64;
+				match ranges.last_mut() {
+					Some(last) if last.end == offset => last.end += 1,
+					_ => ranges.push(offset..offset + 1),
+				}
+			}
+		}
+
+		position += len_a as u64;
+	}
+
+	Ok(ranges)
+}
+
+/// fills `buf` as far as possible, only returning a short count at EOF
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+	let mut filled = 0;
+	while filled < buf.len() {
+		match reader.read(&mut buf[filled..])? {
+			0 => break,
+			n => filled += n,
+		}
+	}
+
+	Ok(filled)
+}
@@ -0,0 +1,234 @@
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::codec::{self, AudioSpecificConfig};
+use crate::index::index_file;
+use crate::mp4::BoxType;
+use crate::tags::fourcc;
+use crate::track::{self, SampleEntry, Track};
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// H.264 SPS and PPS NAL unit types
+const AVC_PARAMETER_SETS: [u8; 2] = [7, 8];
+/// H.265 VPS, SPS and PPS NAL unit types; hvcC arrays may also hold SEI, which is not a parameter set
+const HEVC_PARAMETER_SETS: [u8; 3] = [32, 33, 34];
+/// access unit delimiter NAL unit types, which must stay first in the access unit
+const AVC_AUD: u8 = 9;
+const HEVC_AUD: u8 = 35;
+
+/// How samples are turned into an elementary stream
+enum StreamFormat {
+	/// length-prefixed NAL units rewritten to Annex B, with parameter sets repeated at keyframes
+	AnnexB {
+		nal_length_size: usize,
+		/// parameter sets with their NAL unit types, used to avoid repeating sets already in a sample
+		parameter_sets: Vec<(u8, Vec<u8>)>,
+		hevc: bool,
+	},
+	/// raw AAC frames, each preceded by an ADTS header
+	Adts(AudioSpecificConfig),
+	/// samples written back to back as stored
+	Raw,
+}
+
+impl StreamFormat {
+	fn for_entry(track: &Track, entry: &SampleEntry) -> io::Result<Self> {
+		let handler = track.handler.as_deref();
+		let invalid = |e: io::Error| io::Error::new(ErrorKind::InvalidData, format!("{} configuration: {}", fourcc(entry.format), e));
+
+		if let Some(avcc) = codec::find_config(entry, handler, BoxType::AvcCBox) {
+			let config = codec::parse_avcc(avcc).map_err(invalid)?;
+			return Ok(StreamFormat::AnnexB {
+				nal_length_size: config.nal_length_size as usize,
+				parameter_sets: config.sps.into_iter().map(|sps| (AVC_PARAMETER_SETS[0], sps))
+					.chain(config.pps.into_iter().map(|pps| (AVC_PARAMETER_SETS[1], pps)))
+					.collect(),
+				hevc: false,
+			});
+		}
+
+		if let Some(hvcc) = codec::find_config(entry, handler, BoxType::HvcCBox) {
+			let config = codec::parse_hvcc(hvcc).map_err(invalid)?;
+			return Ok(StreamFormat::AnnexB {
+				nal_length_size: config.nal_length_size as usize,
+				parameter_sets: config.arrays.into_iter()
+					.filter(|(nal_type, _)| HEVC_PARAMETER_SETS.contains(nal_type))
+					.flat_map(|(nal_type, nalus)| nalus.into_iter().map(move |nalu| (nal_type, nalu)))
+					.collect(),
+				hevc: true,
+			});
+		}
+
+		if let Some(esds) = codec::find_config(entry, handler, BoxType::EsdsBox) {
+			let es = codec::parse_esds(esds).map_err(invalid)?;
+			let asc = es.decoder_specific_info.as_deref()
+				.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "esds has no AudioSpecificConfig"))
+				.and_then(codec::parse_audio_specific_config)
+				.map_err(invalid)?;
+
+			// ADTS can only describe the first four object types and indexed sample rates
+			if !(1..=4).contains(&asc.object_type) || asc.sample_rate_index > 12 || asc.channels > 7 {
+				return Err(io::Error::new(ErrorKind::Unsupported, format!("{} ({} Hz, {} channels) cannot be described by an ADTS header",
+					codec::audio_object_type_name(asc.object_type), asc.sample_rate, asc.channels)));
+			}
+			return Ok(StreamFormat::Adts(asc));
+		}
+
+		log::warn!("no known configuration for {} samples; writing them unmodified", fourcc(entry.format));
+		Ok(StreamFormat::Raw)
+	}
+
+	/// appends the elementary stream form of `sample` to `out`
+	fn convert(&self, sample: &[u8], sync: bool, number: u32, out: &mut Vec<u8>) {
+		match self {
+			StreamFormat::AnnexB { nal_length_size, parameter_sets, hevc } => {
+				let nal_type = |nal: &[u8]| if *hevc { nal[0] >> 1 & 0x3f } else { nal[0] & 0x1f };
+
+				let (nals, leftover) = codec::split_nal_units(sample, *nal_length_size);
+				if leftover > 0 {
+					log::warn!("sample {}: last NAL unit runs past the end of the sample; dropping the remaining {} B", number, leftover);
+				}
+
+				// keyframes need every kind of parameter set in-band; those the encoder already put there are not repeated
+				let mut nals = nals.into_iter().peekable();
+				if sync {
+					let aud = if *hevc { HEVC_AUD } else { AVC_AUD };
+					if let Some(delimiter) = nals.next_if(|nal| nal_type(nal) == aud) {
+						out.extend_from_slice(&START_CODE);
+						out.extend_from_slice(delimiter);
+					}
+					let present: Vec<u8> = nals.clone().map(nal_type).collect();
+					for (_, set) in parameter_sets.iter().filter(|(set_type, _)| !present.contains(set_type)) {
+						out.extend_from_slice(&START_CODE);
+						out.extend_from_slice(set);
+					}
+				}
+				for nal in nals {
+					out.extend_from_slice(&START_CODE);
+					out.extend_from_slice(nal);
+				}
+			},
+			StreamFormat::Adts(asc) => {
+				let frame_length = sample.len() + 7;
+				if frame_length >= 1 << 13 {
+					log::warn!("sample {}: {} B is too large for an ADTS frame", number, sample.len());
+					return;
+				}
+
+				let profile = asc.object_type - 1;
+				out.extend_from_slice(&[
+					0xff,
+					0xf1, // MPEG-4, layer 0, no CRC
+					profile << 6 | asc.sample_rate_index << 2 | asc.channels >> 2,
+					(asc.channels & 3) << 6 | (frame_length >> 11) as u8,
+					(frame_length >> 3) as u8,
+					((frame_length & 7) as u8) << 5 | 0x1f, // buffer fullness 0x7ff (VBR)
+					0xfc,
+				]);
+				out.extend_from_slice(sample);
+			},
+			StreamFormat::Raw => out.extend_from_slice(sample),
+		}
+	}
+}
+
+/// writes every locatable sample of the track with the given ID as a raw elementary stream
+pub fn extract_track(input: &Path, track_id: u32, output: &Path) -> io::Result<()> {
+	let (index, file_size) = index_file(input, true)?;
+	let tracks = track::tracks(&index);
+	let track = tracks.iter()
+		.find(|t| t.tkhd.as_ref().map(|h| h.track_id) == Some(track_id))
+		.ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("file has no track with ID {}", track_id)))?;
+
+	let entries = track.stsd.as_ref().map(|stsd| stsd.entries.as_slice()).unwrap_or_default();
+	let entry = entries.first()
+		.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("{} has no sample description", track.label())))?;
+	if entries.len() > 1 {
+		log::warn!("{} has {} sample descriptions; only the first ({}) is used", track.label(), entries.len(), fourcc(entry.format));
+	}
+	let format = StreamFormat::for_entry(track, entry)?;
+
+	let locations = track.sample_locations();
+	let expected = track.stsz.as_ref().map(|stsz| stsz.sample_count()).unwrap_or(0);
+	if (locations.len() as u32) < expected {
+		log::warn!("{} of {} samples cannot be located through the chunk tables and will be missing", expected - locations.len() as u32, expected);
+	}
+
+	let mut reader = File::open(input)?;
+	let mut writer = BufWriter::new(File::create(output)?);
+	let mut sample = Vec::new();
+	let mut out = Vec::new();
+	let (mut written, mut skipped, mut bytes) = (0, 0, 0);
+
+	for location in &locations {
+		if location.end() > file_size {
+			log::warn!("sample {} at {:#x} ({} B) extends past the end of the file; skipping", location.number, location.offset, location.size);
+			skipped += 1;
+			continue;
+		}
+
+		sample.resize(location.size as usize, 0);
+		reader.seek(SeekFrom::Start(location.offset))?;
+		if let Err(e) = reader.read_exact(&mut sample) {
+			log::warn!("sample {} at {:#x}: {}; skipping", location.number, location.offset, e);
+			skipped += 1;
+			continue;
+		}
+
+		out.clear();
+		format.convert(&sample, track.is_sync(location.number), location.number, &mut out);
+		writer.write_all(&out)?;
+		written += 1;
+		bytes += out.len();
+	}
+
+	writer.flush()?;
+	log::info!("wrote {} samples ({} B) of {} to {}; skipped {}", written, bytes, track.label(), output.display(), skipped);
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// a length-prefixed HEVC NAL unit of the given type with a one-byte body
+	fn hevc_nal(nal_type: u8) -> Vec<u8> {
+		vec![0, 0, 0, 3, nal_type << 1, 1, 0xaa]
+	}
+
+	fn hevc_format() -> StreamFormat {
+		StreamFormat::AnnexB {
+			nal_length_size: 4,
+			parameter_sets: [32, 33, 34].iter().map(|&t| (t, hevc_nal(t)[4..].to_vec())).collect(),
+			hevc: true,
+		}
+	}
+
+	fn types(annex_b: &[u8]) -> Vec<u8> {
+		annex_b.windows(5).filter(|w| w[..4] == START_CODE).map(|w| w[4] >> 1 & 0x3f).collect()
+	}
+
+	#[test]
+	fn sei_does_not_count_as_a_parameter_set() {
+		let sample = [hevc_nal(39), hevc_nal(19)].concat();
+		let mut out = Vec::new();
+		hevc_format().convert(&sample, true, 1, &mut out);
+		assert_eq!(types(&out), [32, 33, 34, 39, 19]);
+	}
+
+	#[test]
+	fn only_missing_parameter_sets_are_inserted() {
+		let sample = [hevc_nal(35), hevc_nal(33), hevc_nal(19)].concat();
+		let mut out = Vec::new();
+		hevc_format().convert(&sample, true, 1, &mut out);
+		assert_eq!(types(&out), [35, 32, 34, 33, 19]);
+
+		// non-keyframes are left alone
+		let mut out = Vec::new();
+		hevc_format().convert(&hevc_nal(1), false, 2, &mut out);
+		assert_eq!(types(&out), [1]);
+	}
+}
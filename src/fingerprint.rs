@@ -0,0 +1,230 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::index::{index_file, BoxIndexVisitor};
+use crate::mp4::BoxType;
+use crate::payload::PayloadReader;
+use crate::tags::{self, fourcc, TagValue};
+use crate::track::{self, parse_ftyp};
+
+/// minimum similarity for a non-identical layout to be reported as the closest match
+const SIMILARITY_THRESHOLD: f64 = 0.75;
+
+/// A known recorder layout
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Signature {
+	pub name: String,
+	pub fingerprint: String,
+	pub layout: Vec<String>,
+	/// file the signature was learned from
+	#[serde(default)]
+	pub source: String,
+}
+
+/// The structural layout of a file: brands, box order and vendor atoms, with sizes and sample data ignored
+pub struct Layout {
+	pub tokens: Vec<String>,
+}
+
+impl Layout {
+	/// 64-bit FNV-1a hash of the canonical layout
+	pub fn fingerprint(&self) -> String {
+		let hash = self.tokens.iter().fold(FNV1A_BASIS, |hash, token| fnv1a(fnv1a(hash, token.as_bytes()), b"\n"));
+		format!("{:016x}", hash)
+	}
+}
+
+/// 64-bit FNV-1a offset basis, the hash of no bytes
+pub const FNV1A_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// continues a 64-bit FNV-1a hash over `bytes`, so input can be hashed in pieces
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+	bytes.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// builds the canonical layout of an indexed file
+pub fn layout(index: &BoxIndexVisitor) -> Layout {
+	let mut tokens = Vec::new();
+
+	if let Some(ftyp) = index.find_top_level(BoxType::FtypBox).and_then(|i| index.boxes[i].payload.as_deref()) {
+		match parse_ftyp(ftyp) {
+			Ok(ftyp) => tokens.push(format!("ftyp {} {}", ftyp.major_brand, ftyp.compatible_brands.join(","))),
+			Err(_) => tokens.push("ftyp ?".to_string()),
+		}
+	}
+
+	// tracks are told apart by handler, since their order is characteristic of the muxer
+	let handlers: Vec<(usize, String)> = track::tracks(index).into_iter()
+		.map(|t| (t.trak, t.handler.unwrap_or_else(|| "?".to_string())))
+		.collect();
+
+	// each top-level box and its subtree is one unit; a moof and the mdat after it form a single fragment unit
+	let mut units: Vec<Vec<String>> = Vec::new();
+	let mut last_top = None;
+	let mut paths = Vec::new();
+	for i in 0..index.boxes.len() {
+		let record = &index.boxes[i];
+		if record.parent.is_none() {
+			if !(record.name == BoxType::MdatBox && last_top == Some(BoxType::MoofBox)) || units.is_empty() {
+				units.push(Vec::new());
+			}
+			last_top = Some(record.name);
+		}
+		let unit = units.last_mut().expect("the first box is top-level");
+		let name = match handlers.iter().find(|(trak, _)| *trak == i) {
+			Some((_, handler)) => format!("trak({})", handler),
+			None => fourcc(record.name),
+		};
+		let path = match record.parent {
+			Some(parent) => format!("{}/{}", paths[parent], name),
+			None => name,
+		};
+		paths.push(path.clone());
+		push_collapsed(unit, path.clone());
+
+		// vendor atoms usually hide inside udta and meta, which are not parsed as containers
+		if let Some(payload) = record.payload.as_deref() {
+			let children = match record.name {
+				BoxType::UdtaBox => PayloadReader::new(payload),
+				BoxType::MetaBox => tags::meta_children(payload),
+				_ => continue,
+			};
+			for (typ, content) in children.child_boxes() {
+				push_collapsed(unit, format!("{}/{}", path, fourcc(typ)));
+				if typ == BoxType::MetaBox {
+					for (child, _) in tags::meta_children(content).child_boxes() {
+						push_collapsed(unit, format!("{}/meta/{}", path, fourcc(child)));
+					}
+				}
+			}
+		}
+	}
+
+	// a run of identical units is written once with every token marked `+`; fragments are always
+	// marked, so the layout does not depend on how many fragments the recording has
+	for run in units.chunk_by(|a, b| a == b) {
+		if run.len() == 1 && run[0][0] != "moof" {
+			tokens.extend(run[0].iter().cloned());
+		} else {
+			tokens.extend(run[0].iter().map(|token| if token.ends_with('+') { token.clone() } else { format!("{}+", token) }));
+		}
+	}
+
+	Layout { tokens }
+}
+
+/// appends a token, collapsing runs of identical siblings (e.g. `moof`/`mdat` pairs) into a single `+` token
+fn push_collapsed(tokens: &mut Vec<String>, token: String) {
+	let repeated = format!("{}+", token);
+	match tokens.last() {
+		Some(last) if *last == token || *last == repeated => *tokens.last_mut().unwrap() = repeated,
+		_ => tokens.push(token),
+	}
+}
+
+/// similarity of two layouts in 0..=1, based on their longest common subsequence
+fn similarity(a: &[String], b: &[String]) -> f64 {
+	if a.is_empty() && b.is_empty() {
+		return 1.0;
+	}
+
+	let mut previous = vec![0usize; b.len() + 1];
+	for x in a {
+		let mut current = vec![0usize; b.len() + 1];
+		for (j, y) in b.iter().enumerate() {
+			current[j + 1] = if x == y { previous[j] + 1 } else { previous[j + 1].max(current[j]) };
+		}
+		previous = current;
+	}
+
+	2.0 * previous[b.len()] as f64 / (a.len() + b.len()) as f64
+}
+
+fn load_signatures(db: &Path) -> io::Result<Vec<Signature>> {
+	match fs::read(db) {
+		Ok(data) => serde_json::from_slice(&data).map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", db.display(), e))),
+		Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+		Err(e) => Err(e),
+	}
+}
+
+/// prints the file's fingerprint and the best match from the signature database, optionally
+/// recording the file's layout in the database under `add`
+pub fn fingerprint(file: &Path, db: &Path, add: Option<&str>, show_layout: bool) -> io::Result<()> {
+	let (index, _file_size) = index_file(file, true)?;
+	let layout = layout(&index);
+	let fingerprint = layout.fingerprint();
+
+	println!("fingerprint: {} ({} layout tokens)", fingerprint, layout.tokens.len());
+	if show_layout {
+		for token in &layout.tokens {
+			println!("  {}", token);
+		}
+	}
+
+	// the encoder tag is not structural, but it is the most direct evidence of a re-mux
+	for tag in tags::read_tags(&index).iter().filter(|tag| tag.key == "©too") {
+		if let TagValue::Text { value } = &tag.value {
+			if value.starts_with("Lavf") {
+				println!("encoder tag says {}: re-muxed by FFmpeg (libavformat)", value);
+			} else {
+				println!("encoder tag says {}", value);
+			}
+		}
+	}
+
+	let mut signatures = load_signatures(db)?;
+
+	if let Some(name) = add {
+		signatures.retain(|s| !(s.name == name && s.fingerprint == fingerprint));
+		signatures.push(Signature {
+			name: name.to_string(),
+			fingerprint: fingerprint.clone(),
+			layout: layout.tokens.clone(),
+			source: file.display().to_string(),
+		});
+		fs::write(db, serde_json::to_string_pretty(&signatures)?)?;
+		log::info!("added signature '{}' to {} ({} signatures)", name, db.display(), signatures.len());
+		return Ok(());
+	}
+
+	if signatures.is_empty() {
+		println!("no signatures in {}; add some with --add <NAME>", db.display());
+		return Ok(());
+	}
+
+	let exact: Vec<&Signature> = signatures.iter().filter(|s| s.fingerprint == fingerprint).collect();
+	if !exact.is_empty() {
+		for signature in exact {
+			println!("layout matches {} (learned from {})", signature.name, signature.source);
+		}
+		return Ok(());
+	}
+
+	let (signature, score) = signatures.iter()
+		.map(|s| (s, similarity(&layout.tokens, &s.layout)))
+		.max_by(|a, b| a.1.total_cmp(&b.1))
+		.unwrap();
+
+	if score >= SIMILARITY_THRESHOLD {
+		println!("no exact match; closest layout is {} ({:.0}% similar)", signature.name, score * 100.0);
+		let added: Vec<&String> = layout.tokens.iter().filter(|t| !signature.layout.contains(t)).collect();
+		let removed: Vec<&String> = signature.layout.iter().filter(|t| !layout.tokens.contains(t)).collect();
+		if added.is_empty() && removed.is_empty() {
+			println!("  same boxes in a different order");
+		}
+		for token in added {
+			println!("  + {}", token);
+		}
+		for token in removed {
+			println!("  - {}", token);
+		}
+	} else {
+		println!("no matching layout (closest is {} at {:.0}% similar)", signature.name, score * 100.0);
+	}
+
+	Ok(())
+}
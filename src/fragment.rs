@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::index::{index_file, BoxIndexVisitor};
+use crate::mp4::BoxType;
+use crate::payload::PayloadReader;
+use crate::track::{self, read_versioned, Table};
+
+// tfhd flags
+const BASE_DATA_OFFSET_PRESENT: u32 = 0x1;
+const SAMPLE_DESCRIPTION_INDEX_PRESENT: u32 = 0x2;
+const DEFAULT_SAMPLE_DURATION_PRESENT: u32 = 0x8;
+const DEFAULT_SAMPLE_SIZE_PRESENT: u32 = 0x10;
+const DEFAULT_SAMPLE_FLAGS_PRESENT: u32 = 0x20;
+const DEFAULT_BASE_IS_MOOF: u32 = 0x2_0000;
+
+// trun flags
+const DATA_OFFSET_PRESENT: u32 = 0x1;
+const FIRST_SAMPLE_FLAGS_PRESENT: u32 = 0x4;
+const SAMPLE_DURATION_PRESENT: u32 = 0x100;
+const SAMPLE_SIZE_PRESENT: u32 = 0x200;
+const SAMPLE_FLAGS_PRESENT: u32 = 0x400;
+const SAMPLE_COMPOSITION_TIME_OFFSET_PRESENT: u32 = 0x800;
+
+/// `sample_is_non_sync_sample` bit of the sample flags
+const NON_SYNC_SAMPLE: u32 = 0x1_0000;
+
+/// Per-track defaults for movie fragments, from `trex`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrackExtends {
+	pub track_id: u32,
+	pub default_sample_description_index: u32,
+	pub default_sample_duration: u32,
+	pub default_sample_size: u32,
+	pub default_sample_flags: u32,
+}
+
+/// Contents of `tfhd`; fields are `None` unless the corresponding flag is set
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrackFragmentHeader {
+	pub track_id: u32,
+	pub flags: u32,
+	pub base_data_offset: Option<u64>,
+	pub sample_description_index: Option<u32>,
+	pub default_sample_duration: Option<u32>,
+	pub default_sample_size: Option<u32>,
+	pub default_sample_flags: Option<u32>,
+}
+
+/// A single sample of a `trun`; fields are `None` unless the run carries them per sample
+#[derive(Debug, Clone, Copy)]
+pub struct RunSample {
+	pub duration: Option<u32>,
+	pub size: Option<u32>,
+	pub flags: Option<u32>,
+}
+
+/// Samples of a `trun`
+#[derive(Debug, Clone)]
+pub enum RunSamples {
+	/// the run carries no per-sample fields, so each of its samples takes the defaults
+	Uniform(u32),
+	Listed(Table<RunSample>),
+}
+
+impl RunSamples {
+	pub fn declared(&self) -> u32 {
+		match self {
+			RunSamples::Uniform(count) => *count,
+			RunSamples::Listed(table) => table.declared,
+		}
+	}
+
+	/// number of samples whose fields are present in the box
+	pub fn len(&self) -> usize {
+		match self {
+			RunSamples::Uniform(count) => *count as usize,
+			RunSamples::Listed(table) => table.entries.len(),
+		}
+	}
+
+	pub fn is_truncated(&self) -> bool {
+		matches!(self, RunSamples::Listed(table) if table.is_truncated())
+	}
+}
+
+/// Contents of `trun`
+#[derive(Debug, Clone)]
+pub struct TrackRun {
+	pub data_offset: Option<i32>,
+	pub first_sample_flags: Option<u32>,
+	pub samples: RunSamples,
+}
+
+pub fn parse_mfhd(data: &[u8]) -> io::Result<u32> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	reader.u32()
+}
+
+pub fn parse_mehd(data: &[u8]) -> io::Result<u64> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+	read_versioned(&mut reader, version)
+}
+
+pub fn parse_tfdt(data: &[u8]) -> io::Result<u64> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+	read_versioned(&mut reader, version)
+}
+
+pub fn parse_trex(data: &[u8]) -> io::Result<TrackExtends> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+
+	Ok(TrackExtends {
+		track_id: reader.u32()?,
+		default_sample_description_index: reader.u32()?,
+		default_sample_duration: reader.u32()?,
+		default_sample_size: reader.u32()?,
+		default_sample_flags: reader.u32()?,
+	})
+}
+
+pub fn parse_tfhd(data: &[u8]) -> io::Result<TrackFragmentHeader> {
+	let mut reader = PayloadReader::new(data);
+	let (_version, flags) = reader.version_flags()?;
+	let track_id = reader.u32()?;
+	let base_data_offset = if flags & BASE_DATA_OFFSET_PRESENT != 0 { Some(reader.u64()?) } else { None };
+
+	let mut optional = |flag: u32| if flags & flag != 0 { reader.u32().map(Some) } else { Ok(None) };
+	let mut header = TrackFragmentHeader { track_id, flags, base_data_offset, ..Default::default() };
+	header.sample_description_index = optional(SAMPLE_DESCRIPTION_INDEX_PRESENT)?;
+	header.default_sample_duration = optional(DEFAULT_SAMPLE_DURATION_PRESENT)?;
+	header.default_sample_size = optional(DEFAULT_SAMPLE_SIZE_PRESENT)?;
+	header.default_sample_flags = optional(DEFAULT_SAMPLE_FLAGS_PRESENT)?;
+
+	Ok(header)
+}
+
+pub fn parse_trun(data: &[u8]) -> io::Result<TrackRun> {
+	let mut reader = PayloadReader::new(data);
+	let (_version, flags) = reader.version_flags()?;
+	let declared = reader.u32()?;
+	let data_offset = if flags & DATA_OFFSET_PRESENT != 0 { Some(reader.u32()? as i32) } else { None };
+	let first_sample_flags = if flags & FIRST_SAMPLE_FLAGS_PRESENT != 0 { Some(reader.u32()?) } else { None };
+
+	let per_sample = [SAMPLE_DURATION_PRESENT, SAMPLE_SIZE_PRESENT, SAMPLE_FLAGS_PRESENT, SAMPLE_COMPOSITION_TIME_OFFSET_PRESENT];
+	let entry_size = 4 * per_sample.iter().filter(|&&flag| flags & flag != 0).count();
+	let field = |r: &mut PayloadReader, flag: u32| if flags & flag != 0 { r.u32().map(Some) } else { Ok(None) };
+
+	let samples = match reader.remaining().checked_div(entry_size) {
+		// every field comes from the defaults, so the entries take no space and only the count is kept
+		None => RunSamples::Uniform(declared),
+		Some(available) => {
+			let available = available.min(declared as usize);
+			let mut entries = Vec::with_capacity(available);
+			for _ in 0..available {
+				entries.push(RunSample {
+					duration: field(&mut reader, SAMPLE_DURATION_PRESENT)?,
+					size: field(&mut reader, SAMPLE_SIZE_PRESENT)?,
+					flags: field(&mut reader, SAMPLE_FLAGS_PRESENT)?,
+				});
+				field(&mut reader, SAMPLE_COMPOSITION_TIME_OFFSET_PRESENT)?;
+			}
+			RunSamples::Listed(Table { declared, entries })
+		},
+	};
+
+	Ok(TrackRun { data_offset, first_sample_flags, samples })
+}
+
+/// decodes the payload of the given box, recording failures as findings
+pub(crate) fn decode<T>(index: &BoxIndexVisitor, i: usize, parse: fn(&[u8]) -> io::Result<T>, findings: &mut Vec<String>) -> Option<T> {
+	let record = &index.boxes[i];
+	match parse(record.payload.as_deref()?) {
+		Ok(value) => Some(value),
+		Err(e) => {
+			findings.push(format!("unable to decode {} at {:#x}: {}", record.name, record.offset, e));
+			None
+		},
+	}
+}
+
+/// What one `traf` contributes to a track
+struct FragmentRun {
+	track_id: u32,
+	decode_time: Option<u64>,
+	samples: u32,
+	sync_samples: u32,
+	duration: u64,
+	data_start: u64,
+	data_end: u64,
+}
+
+/// lists every movie fragment with its samples resolved against `trex` defaults, then reports sequence gaps,
+/// decode time discontinuities and truncation
+pub fn fragments(file: &Path) -> io::Result<()> {
+	let (index, file_size) = index_file(file, true)?;
+	let moofs = index.find_all(BoxType::MoofBox);
+	if moofs.is_empty() {
+		return Err(io::Error::new(ErrorKind::InvalidData, "file has no movie fragments (moof boxes)"));
+	}
+
+	let mut findings = Vec::new();
+	let timescales: HashMap<u32, (u32, String)> = track::tracks(&index).iter()
+		.filter_map(|t| Some((t.tkhd.as_ref()?.track_id, (t.mdhd.as_ref().map(|m| m.timescale).unwrap_or(0), t.label()))))
+		.collect();
+	let label = |track_id: u32| timescales.get(&track_id).map(|(_, label)| label.clone()).unwrap_or_else(|| format!("track {}", track_id));
+	let seconds = |track_id: u32, ticks: u64| match timescales.get(&track_id) {
+		Some(&(timescale, _)) if timescale > 0 => format!("{:.3} s", ticks as f64 / timescale as f64),
+		_ => format!("{} ticks", ticks),
+	};
+
+	let mvex = index.find_top_level(BoxType::MoovBox).and_then(|moov| index.find_child(moov, BoxType::MvexBox));
+	let mut defaults = HashMap::new();
+	match mvex {
+		Some(mvex) => {
+			if let Some(mehd) = index.find_child(mvex, BoxType::MehdBox).and_then(|mehd| decode(&index, mehd, parse_mehd, &mut findings)) {
+				println!("mehd: fragment duration {} (movie timescale)", mehd);
+			}
+			for trex in index.children(mvex).filter(|&i| index.boxes[i].name == BoxType::TrexBox) {
+				if let Some(trex) = decode(&index, trex, parse_trex, &mut findings) {
+					println!("trex {}: sample description {}, duration {}, size {}, flags {:#010x}", label(trex.track_id),
+						trex.default_sample_description_index, trex.default_sample_duration, trex.default_sample_size, trex.default_sample_flags);
+					defaults.insert(trex.track_id, trex);
+				}
+			}
+		},
+		None => findings.push("moov has no mvex box, so fragments cannot be attributed to the movie".to_string()),
+	}
+	println!();
+
+	let mdats: Vec<(u64, u64)> = index.find_all(BoxType::MdatBox).into_iter()
+		.map(|i| (index.boxes[i].content_offset(), index.boxes[i].end()))
+		.collect();
+
+	let mut previous_sequence: Option<u32> = None;
+	let mut next_decode_time: HashMap<u32, u64> = HashMap::new();
+	let mut totals: HashMap<u32, (u32, u64)> = HashMap::new();
+	for (n, &moof) in moofs.iter().enumerate() {
+		let record = &index.boxes[moof];
+		let sequence = index.find_child(moof, BoxType::MfhdBox).and_then(|mfhd| decode(&index, mfhd, parse_mfhd, &mut findings));
+		println!("fragment {} (moof at {:#x}, {} B): sequence {}", n + 1, record.offset, record.size,
+			sequence.map(|s| s.to_string()).unwrap_or_else(|| "?".to_string()));
+
+		if record.is_corrected() {
+			findings.push(format!("fragment {}: moof declares {} B but only {} B are present (truncated)", n + 1, record.declared_size, record.size));
+		}
+		match (previous_sequence, sequence) {
+			(Some(previous), Some(current)) if current == previous.wrapping_add(1) => {},
+			(Some(previous), Some(current)) if current > previous => findings.push(format!("fragment {}: sequence jumps from {} to {} ({} fragment{} missing)",
+				n + 1, previous, current, current - previous - 1, if current - previous == 2 { "" } else { "s" })),
+			(Some(previous), Some(current)) => findings.push(format!("fragment {}: sequence {} does not follow {}", n + 1, current, previous)),
+			(None, None) => findings.push(format!("fragment {}: no readable mfhd", n + 1)),
+			_ => {},
+		}
+		previous_sequence = sequence.or(previous_sequence);
+
+		// without explicit offsets, each traf's data follows the previous one's
+		let mut implicit_base = record.offset;
+		for traf in index.children(moof).filter(|&i| index.boxes[i].name == BoxType::TrafBox) {
+			let Some(run) = resolve_traf(&index, traf, record.offset, implicit_base, &defaults, &mut findings) else { continue };
+			implicit_base = run.data_end;
+
+			let (samples, duration) = totals.entry(run.track_id).or_default();
+			*samples = samples.saturating_add(run.samples);
+			*duration = duration.saturating_add(run.duration);
+
+			println!("  {}: decode time {}, {} samples ({} sync), {}, data {:#x}..{:#x} ({} B)", label(run.track_id),
+				run.decode_time.map(|t| format!("{} ({})", t, seconds(run.track_id, t))).unwrap_or_else(|| "?".to_string()),
+				run.samples, run.sync_samples, seconds(run.track_id, run.duration), run.data_start, run.data_end, run.data_end - run.data_start);
+
+			if !timescales.contains_key(&run.track_id) {
+				findings.push(format!("fragment {}: refers to track {}, which moov does not declare", n + 1, run.track_id));
+			}
+			if run.data_end > file_size {
+				findings.push(format!("fragment {}: {} sample data ends at {:#x}, {} B past the end of the file (truncated)",
+					n + 1, label(run.track_id), run.data_end, run.data_end - file_size));
+			} else if run.samples > 0 && !mdats.iter().any(|&(start, end)| start <= run.data_start && run.data_end <= end) {
+				findings.push(format!("fragment {}: {} sample data {:#x}..{:#x} is not inside an mdat box", n + 1, label(run.track_id), run.data_start, run.data_end));
+			}
+
+			if let Some(decode_time) = run.decode_time {
+				if let Some(&expected) = next_decode_time.get(&run.track_id) {
+					if decode_time != expected {
+						findings.push(format!("fragment {}: {} decode time {} {} the end of the previous fragment at {}",
+							n + 1, label(run.track_id), seconds(run.track_id, decode_time),
+							if decode_time > expected { "leaves a gap after" } else { "overlaps" }, seconds(run.track_id, expected)));
+					}
+				}
+				next_decode_time.insert(run.track_id, decode_time.saturating_add(run.duration));
+			}
+		}
+	}
+
+	println!();
+	let mut track_ids: Vec<&u32> = totals.keys().collect();
+	track_ids.sort();
+	for &track_id in track_ids {
+		let (samples, duration) = totals[&track_id];
+		println!("{}: {} samples in fragments, {}", label(track_id), samples, seconds(track_id, duration));
+	}
+
+	if findings.is_empty() {
+		println!("no fragment problems found");
+	} else {
+		println!("{} finding{}:", findings.len(), if findings.len() == 1 { "" } else { "s" });
+		for finding in &findings {
+			println!("  {}", finding);
+		}
+	}
+
+	Ok(())
+}
+
+/// resolves the samples of one `traf` against its `tfhd` and the track's `trex` defaults
+fn resolve_traf(index: &BoxIndexVisitor, traf: usize, moof_offset: u64, implicit_base: u64, defaults: &HashMap<u32, TrackExtends>, findings: &mut Vec<String>) -> Option<FragmentRun> {
+	let Some(tfhd) = index.find_child(traf, BoxType::TfhdBox).and_then(|i| decode(index, i, parse_tfhd, findings)) else {
+		findings.push(format!("traf at {:#x} has no readable tfhd", index.boxes[traf].offset));
+		return None;
+	};
+	let trex = defaults.get(&tfhd.track_id).copied().unwrap_or_else(|| {
+		let finding = format!("track {} has fragments but no trex defaults", tfhd.track_id);
+		if !findings.contains(&finding) {
+			findings.push(finding);
+		}
+		TrackExtends { track_id: tfhd.track_id, ..Default::default() }
+	});
+
+	let base = match tfhd.base_data_offset {
+		Some(offset) => offset,
+		None if tfhd.flags & DEFAULT_BASE_IS_MOOF != 0 => moof_offset,
+		None => implicit_base,
+	};
+
+	let mut run = FragmentRun {
+		track_id: tfhd.track_id,
+		decode_time: index.find_child(traf, BoxType::TfdtBox).and_then(|i| decode(index, i, parse_tfdt, findings)),
+		samples: 0,
+		sync_samples: 0,
+		duration: 0,
+		data_start: u64::MAX,
+		data_end: base,
+	};
+
+	let default_duration = tfhd.default_sample_duration.unwrap_or(trex.default_sample_duration);
+	let default_size = tfhd.default_sample_size.unwrap_or(trex.default_sample_size);
+	let default_flags = tfhd.default_sample_flags.unwrap_or(trex.default_sample_flags);
+
+	for trun in index.children(traf).filter(|&i| index.boxes[i].name == BoxType::TrunBox) {
+		let Some(trun) = decode(index, trun, parse_trun, findings) else { continue };
+		if trun.samples.is_truncated() {
+			findings.push(format!("track {}: trun declares {} samples but only {} fit in the box (truncated)",
+				tfhd.track_id, trun.samples.declared(), trun.samples.len()));
+		}
+
+		// runs without an offset continue where the previous one ended
+		let mut position = match trun.data_offset {
+			Some(offset) => base.saturating_add_signed(offset as i64),
+			None => run.data_end,
+		};
+		run.data_start = run.data_start.min(position);
+
+		match &trun.samples {
+			RunSamples::Listed(table) => {
+				for (i, sample) in table.entries.iter().enumerate() {
+					let flags = sample.flags.or(if i == 0 { trun.first_sample_flags } else { None }).unwrap_or(default_flags);
+					run.samples = run.samples.saturating_add(1);
+					if flags & NON_SYNC_SAMPLE == 0 {
+						run.sync_samples = run.sync_samples.saturating_add(1);
+					}
+					run.duration = run.duration.saturating_add(sample.duration.unwrap_or(default_duration) as u64);
+					position = position.saturating_add(sample.size.unwrap_or(default_size) as u64);
+				}
+			},
+			// a corrupt count can declare billions of samples, so the totals are computed rather than counted
+			&RunSamples::Uniform(count) if count > 0 => {
+				let first_flags = trun.first_sample_flags.unwrap_or(default_flags);
+				let sync = (first_flags & NON_SYNC_SAMPLE == 0) as u32
+					+ if default_flags & NON_SYNC_SAMPLE == 0 { count - 1 } else { 0 };
+				run.samples = run.samples.saturating_add(count);
+				run.sync_samples = run.sync_samples.saturating_add(sync);
+				run.duration = run.duration.saturating_add(count as u64 * default_duration as u64);
+				position = position.saturating_add(count as u64 * default_size as u64);
+			},
+			RunSamples::Uniform(_) => {},
+		}
+		run.data_end = position;
+	}
+
+	if run.data_start == u64::MAX {
+		run.data_start = run.data_end;
+	}
+
+	Some(run)
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::bits::{unescape_rbsp, BitReader};
+use crate::codec;
+use crate::index::index_file;
+use crate::mp4::BoxType;
+use crate::track;
+
+/// longest frame-type pattern printed per GOP
+const PATTERN_LIMIT: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SliceType {
+	I,
+	P,
+	B,
+}
+
+/// What the bitstream says about one sample
+#[derive(Debug, Default)]
+struct Frame {
+	/// most dependent slice type in the picture, if any slice header could be read
+	slice_type: Option<SliceType>,
+	/// H.264 IDR or H.265 IRAP picture: decoding can start here
+	random_access: bool,
+	/// H.264 IDR or H.265 IDR_W_RADL/IDR_N_LP: nothing after it refers to earlier pictures
+	idr: bool,
+	/// other pictures may refer to this one, so damage to it spreads
+	reference: bool,
+	/// H.265 RASL picture, which refers to pictures before its random access point
+	skipped_leading: bool,
+}
+
+impl Frame {
+	fn symbol(&self) -> char {
+		let symbol = match self.slice_type {
+			Some(SliceType::I) => 'I',
+			Some(SliceType::P) => 'P',
+			Some(SliceType::B) => 'B',
+			None => return '?',
+		};
+		if self.reference { symbol } else { symbol.to_ascii_lowercase() }
+	}
+
+	fn describe(&self) -> &'static str {
+		match (self.idr, self.random_access, self.slice_type) {
+			(true, _, _) => "IDR",
+			(false, true, _) => "non-IDR random access",
+			(_, _, Some(SliceType::I)) => "non-IDR I",
+			(_, _, Some(SliceType::P)) => "P",
+			(_, _, Some(SliceType::B)) => "B",
+			(_, _, None) => "undecodable",
+		}
+	}
+}
+
+/// The per-stream state needed to read slice headers
+enum Parser {
+	Avc,
+	/// H.265 slice headers depend on `num_extra_slice_header_bits` from the PPS they reference
+	Hevc { extra_slice_header_bits: HashMap<u32, u32> },
+}
+
+/// reads the PPS ID and `num_extra_slice_header_bits` from an H.265 PPS NAL unit (including its two-byte header)
+fn parse_hevc_pps(nal: &[u8]) -> io::Result<(u32, u32)> {
+	let rbsp = unescape_rbsp(nal.get(2..).unwrap_or_default());
+	let mut bits = BitReader::new(&rbsp);
+	let id = bits.ue()?;
+	bits.ue()?; // pps_seq_parameter_set_id
+	bits.skip(2)?; // dependent_slice_segments_enabled_flag, output_flag_present_flag
+	Ok((id, bits.bits(3)?))
+}
+
+fn add_hevc_pps(extra_slice_header_bits: &mut HashMap<u32, u32>, nal: &[u8]) {
+	match parse_hevc_pps(nal) {
+		Ok((id, extra)) => { extra_slice_header_bits.insert(id, extra); },
+		Err(e) => log::warn!("unable to decode PPS: {}", e),
+	}
+}
+
+impl Parser {
+	/// classifies a sample from its NAL units
+	fn frame(&mut self, nals: &[&[u8]]) -> Frame {
+		let mut frame = Frame::default();
+		for nal in nals {
+			let slice = match self {
+				Parser::Avc => {
+					let nal_type = nal[0] & 0x1f;
+					if !(1..=5).contains(&nal_type) {
+						continue;
+					}
+					frame.idr |= nal_type == 5;
+					frame.random_access |= nal_type == 5;
+					frame.reference |= nal[0] >> 5 & 3 != 0;
+					avc_slice_type(nal)
+				},
+				Parser::Hevc { extra_slice_header_bits } => {
+					if nal.len() < 2 {
+						continue;
+					}
+					let nal_type = nal[0] >> 1 & 0x3f;
+					if nal_type == 34 {
+						add_hevc_pps(extra_slice_header_bits, nal);
+						continue;
+					}
+					if nal_type > 31 {
+						continue;
+					}
+					frame.idr |= nal_type == 19 || nal_type == 20;
+					frame.random_access |= (16..=23).contains(&nal_type);
+					// sub-layer non-reference pictures have even types below 16
+					frame.reference |= nal_type >= 16 || nal_type % 2 == 1;
+					frame.skipped_leading |= nal_type == 8 || nal_type == 9;
+					hevc_slice_type(nal, nal_type, extra_slice_header_bits)
+				},
+			};
+
+			// a picture is as dependent as its most dependent slice
+			match slice {
+				Ok(Some(slice)) => frame.slice_type = Some(match (frame.slice_type, slice) {
+					(Some(SliceType::B), _) | (_, SliceType::B) => SliceType::B,
+					(Some(SliceType::P), _) | (_, SliceType::P) => SliceType::P,
+					_ => SliceType::I,
+				}),
+				Ok(None) => {},
+				Err(e) => log::debug!("unable to read slice header: {}", e),
+			}
+		}
+		frame
+	}
+}
+
+/// reads `slice_type` from an H.264 slice NAL unit
+fn avc_slice_type(nal: &[u8]) -> io::Result<Option<SliceType>> {
+	// the slice type is within the first few bytes, so only those need unescaping
+	let rbsp = unescape_rbsp(&nal[1..nal.len().min(16)]);
+	let mut bits = BitReader::new(&rbsp);
+	bits.ue()?; // first_mb_in_slice
+	Ok(match bits.ue()? % 5 {
+		0 | 3 => Some(SliceType::P), // P, SP
+		1 => Some(SliceType::B),
+		_ => Some(SliceType::I), // I, SI
+	})
+}
+
+/// reads `slice_type` from the first segment of an H.265 picture; other segments are skipped
+/// because their addresses need the full SPS
+fn hevc_slice_type(nal: &[u8], nal_type: u8, extra_slice_header_bits: &HashMap<u32, u32>) -> io::Result<Option<SliceType>> {
+	let rbsp = unescape_rbsp(&nal[2..nal.len().min(24)]);
+	let mut bits = BitReader::new(&rbsp);
+	if !bits.bit()? {
+		return Ok(None);
+	}
+	if (16..=23).contains(&nal_type) {
+		bits.skip(1)?; // no_output_of_prior_pics_flag
+	}
+	let pps = bits.ue()?;
+	let extra = *extra_slice_header_bits.get(&pps)
+		.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("slice refers to unknown PPS {}", pps)))?;
+	bits.skip(extra as usize)?;
+	Ok(match bits.ue()? {
+		0 => Some(SliceType::B),
+		1 => Some(SliceType::P),
+		_ => Some(SliceType::I),
+	})
+}
+
+/// A run of samples that decode from one starting frame
+struct Gop {
+	/// index of the first sample
+	start: usize,
+	len: usize,
+}
+
+/// parses every sample of a video track to report its GOP structure, frame types and disagreements with `stss`
+pub fn gop(file: &Path, track_id: Option<u32>) -> io::Result<()> {
+	let (index, file_size) = index_file(file, true)?;
+	let tracks = track::tracks(&index);
+	let track = match track_id {
+		Some(id) => tracks.iter().find(|t| t.tkhd.as_ref().map(|h| h.track_id) == Some(id))
+			.ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("file has no track with ID {}", id)))?,
+		None => tracks.iter().find(|t| t.handler.as_deref() == Some("vide"))
+			.ok_or_else(|| io::Error::new(ErrorKind::NotFound, "file has no video track"))?,
+	};
+
+	let entry = track.stsd.as_ref().and_then(|stsd| stsd.entries.first())
+		.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("{} has no sample description", track.label())))?;
+	let handler = track.handler.as_deref();
+	let invalid = |e: io::Error| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", track.label(), e));
+	let (mut parser, nal_length_size) = if let Some(avcc) = codec::find_config(entry, handler, BoxType::AvcCBox) {
+		(Parser::Avc, codec::parse_avcc(avcc).map_err(invalid)?.nal_length_size as usize)
+	} else if let Some(hvcc) = codec::find_config(entry, handler, BoxType::HvcCBox) {
+		let config = codec::parse_hvcc(hvcc).map_err(invalid)?;
+		let mut extra_slice_header_bits = HashMap::new();
+		for (_, nalus) in config.arrays.iter().filter(|(nal_type, _)| *nal_type == 34) {
+			nalus.iter().for_each(|pps| add_hevc_pps(&mut extra_slice_header_bits, pps));
+		}
+		(Parser::Hevc { extra_slice_header_bits }, config.nal_length_size as usize)
+	} else {
+		return Err(io::Error::new(ErrorKind::Unsupported, format!("{} is not H.264 or H.265 (no avcC or hvcC)", track.label())));
+	};
+
+	let times = track.sample_times();
+	let timescale = track.mdhd.as_ref().map(|m| m.timescale).unwrap_or(0);
+	let mut reader = File::open(file)?;
+	let mut sample = Vec::new();
+	let mut frames = Vec::new();
+	for location in track.sample_locations() {
+		if location.end() > file_size {
+			frames.push(Frame::default());
+			continue;
+		}
+		sample.resize(location.size as usize, 0);
+		reader.seek(SeekFrom::Start(location.offset))?;
+		reader.read_exact(&mut sample)?;
+		let (nals, leftover) = codec::split_nal_units(&sample, nal_length_size);
+		if leftover > 0 {
+			log::warn!("sample {}: last NAL unit runs {} B past the end of the sample", location.number, leftover);
+		}
+		frames.push(parser.frame(&nals));
+	}
+
+	println!("{}: {} samples parsed", track.label(), frames.len());
+	if track.stss.is_none() {
+		println!("no stss: every sample counts as a sync sample");
+	}
+
+	// disagreements between the container's idea of keyframes and the bitstream's
+	let mut mismatches = 0;
+	for (i, frame) in frames.iter().enumerate() {
+		let number = i as u32 + 1;
+		let sync = track.is_sync(number);
+		if frame.slice_type.is_none() {
+			continue;
+		}
+		if sync && !frame.random_access {
+			println!("sample {}: stss marks it as sync, but its bitstream frame type is {}", number, frame.describe());
+			mismatches += 1;
+		} else if !sync && frame.random_access {
+			println!("sample {}: bitstream frame type is {}, but stss does not mark it as sync", number, frame.describe());
+			mismatches += 1;
+		}
+	}
+
+	// a GOP starts wherever decoding can: at a random access picture, or at an I frame stss offers as a seek point
+	let mut gops: Vec<Gop> = Vec::new();
+	for (i, frame) in frames.iter().enumerate() {
+		let starts = frame.random_access || (frame.slice_type == Some(SliceType::I) && track.is_sync(i as u32 + 1));
+		match gops.last_mut() {
+			Some(gop) if !starts => gop.len += 1,
+			_ => gops.push(Gop { start: i, len: 1 }),
+		}
+	}
+
+	println!();
+	println!("GOPs (decode order; lowercase frames are not referenced, so damage to them does not spread):");
+	let mut open = 0;
+	for (n, gop) in gops.iter().enumerate() {
+		let members = &frames[gop.start..gop.start + gop.len];
+		let first = &members[0];
+		let first_pts = times.get(gop.start).map(|t| t.pts());
+		// pictures decoded after the first one but shown before it are leading pictures
+		let leading = times.iter().skip(gop.start + 1).take(gop.len - 1)
+			.filter(|t| first_pts.is_some_and(|pts| t.pts() < pts))
+			.count();
+		let is_open = !first.idr && (leading > 0 || members.iter().any(|f| f.skipped_leading));
+		if is_open {
+			open += 1;
+		}
+
+		let duration: u64 = times.iter().skip(gop.start).take(gop.len).map(|t| t.duration as u64).sum();
+		let mut pattern: String = members.iter().take(PATTERN_LIMIT).map(Frame::symbol).collect();
+		if gop.len > PATTERN_LIMIT {
+			pattern.push_str("...");
+		}
+		let references = members.iter().skip(1).filter(|f| f.reference).count();
+		let status = if first.slice_type.is_none() { "undecodable start" } else if is_open { "open" } else { "closed" };
+
+		println!("  {:>4}: samples {}..{} ({} frames, {:.3} s), {}, starts with {} frame{}",
+			n + 1, gop.start + 1, gop.start + gop.len, gop.len,
+			if timescale > 0 { duration as f64 / timescale as f64 } else { 0.0 },
+			status, first.describe(),
+			if leading > 0 { format!(", {} leading", leading) } else { String::new() });
+		println!("        {}", pattern);
+		if gop.len > 1 {
+			println!("        damage to sample {} spreads to all {} frames; {} more reference frames can each spread damage to the end of the GOP",
+				gop.start + 1, gop.len, references);
+		}
+	}
+
+	let count = |slice_type: SliceType| frames.iter().filter(|f| f.slice_type == Some(slice_type)).count();
+	let unknown = frames.iter().filter(|f| f.slice_type.is_none()).count();
+	println!();
+	println!("frames: {} I, {} P, {} B, {} undecodable", count(SliceType::I), count(SliceType::P), count(SliceType::B), unknown);
+	if let (Some(min), Some(max)) = (gops.iter().map(|g| g.len).min(), gops.iter().map(|g| g.len).max()) {
+		println!("{} GOPs ({} open, {} closed), length {}..{} (mean {:.1})",
+			gops.len(), open, gops.len() - open, min, max, frames.len() as f64 / gops.len() as f64);
+	}
+	println!("{} samples where stss and the bitstream disagree about keyframes", mismatches);
+
+	Ok(())
+}
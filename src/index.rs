@@ -0,0 +1,205 @@
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::mp4::{read_box, BoxHeader, BoxType, Mp4Visitor};
+
+/// Location of a single box within the file
+#[derive(Debug, Clone)]
+pub struct BoxRecord {
+	pub name: BoxType,
+	pub parent: Option<usize>,
+	pub offset: u64,
+	pub header_size: u64,
+	/// size of the box (including header), after correcting for container overflow
+	pub size: u64,
+	pub declared_size: u64,
+	/// raw content of 'data-only' boxes (other than `mdat`), if captured
+	pub payload: Option<Vec<u8>>,
+}
+
+impl BoxRecord {
+	pub fn end(&self) -> u64 {
+		self.offset + self.size
+	}
+
+	pub fn content_offset(&self) -> u64 {
+		self.offset + self.header_size
+	}
+
+	pub fn is_corrected(&self) -> bool {
+		self.size != self.declared_size
+	}
+}
+
+/// A contiguous run of bytes attributed to the innermost box containing it
+#[derive(Debug, Clone)]
+pub struct Segment {
+	pub start: u64,
+	pub end: u64,
+	/// index into `BoxIndexVisitor::boxes`, or `None` for bytes outside any parsed box
+	pub owner: Option<usize>,
+	pub is_header: bool,
+}
+
+/// Records the position of every box visited by `read_box` as a flat, pre-ordered list
+#[derive(Default)]
+pub struct BoxIndexVisitor {
+	pub boxes: Vec<BoxRecord>,
+	/// regions that `read_box` could not parse, alongside the box containing them (if any)
+	pub skipped: Vec<(Option<usize>, Range<u64>)>,
+	stack: Vec<usize>,
+	capture_payloads: bool,
+}
+
+/// indexes the given file, returning the index and the file size
+pub fn index_file(file: &Path, capture_payloads: bool) -> io::Result<(BoxIndexVisitor, u64)> {
+	match index_file_partial(file, capture_payloads)? {
+		(_, _, Some(e)) => Err(e),
+		(visitor, size, None) => Ok((visitor, size)),
+	}
+}
+
+/// indexes as much of the given file as can be parsed, returning the error that stopped `read_box` (if any)
+/// alongside the partial index
+pub fn index_file_partial(file: &Path, capture_payloads: bool) -> io::Result<(BoxIndexVisitor, u64, Option<io::Error>)> {
+	let f = File::open(file)?;
+	let size = f.metadata()?.len();
+	let reader = io::BufReader::new(f);
+
+	let mut visitor = BoxIndexVisitor::new(capture_payloads);
+	let error = read_box(reader, size, &mut visitor).err();
+
+	Ok((visitor, size, error))
+}
+
+impl BoxIndexVisitor {
+	pub fn new(capture_payloads: bool) -> Self {
+		Self {
+			capture_payloads,
+			..Default::default()
+		}
+	}
+
+	pub fn children(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+		(index + 1..self.boxes.len())
+			.take_while(move |&i| self.boxes[i].offset < self.boxes[index].end())
+			.filter(move |&i| self.boxes[i].parent == Some(index))
+	}
+
+	/// first child of `index` with the given type
+	pub fn find_child(&self, index: usize, typ: BoxType) -> Option<usize> {
+		self.children(index).find(|&i| self.boxes[i].name == typ)
+	}
+
+	/// follows a chain of child types beneath `index`, e.g. `[mdia, minf, stbl]`
+	pub fn find_path(&self, index: usize, path: &[BoxType]) -> Option<usize> {
+		path.iter().try_fold(index, |current, &typ| self.find_child(current, typ))
+	}
+
+	/// every box of the given type, anywhere in the file
+	pub fn find_all(&self, typ: BoxType) -> Vec<usize> {
+		(0..self.boxes.len()).filter(|&i| self.boxes[i].name == typ).collect()
+	}
+
+	/// number of problems encountered while parsing: corrected box sizes and skipped regions
+	pub fn diagnostics(&self) -> usize {
+		self.boxes.iter().filter(|b| b.is_corrected()).count() + self.skipped.len()
+	}
+
+	/// first top-level box of the given type
+	pub fn find_top_level(&self, typ: BoxType) -> Option<usize> {
+		(0..self.boxes.len()).find(|&i| self.boxes[i].parent.is_none() && self.boxes[i].name == typ)
+	}
+
+	/// slash-separated box types from the root down to the given box, e.g. `moov/trak/tkhd`
+	pub fn path(&self, index: usize) -> String {
+		let mut names = Vec::new();
+		let mut current = Some(index);
+		while let Some(i) = current {
+			names.push(self.boxes[i].name.to_string());
+			current = self.boxes[i].parent;
+		}
+		names.reverse();
+		names.join("/")
+	}
+
+	/// Partitions `[0, file_size)` into segments owned by the innermost box covering each byte
+	pub fn segments(&self, file_size: u64) -> Vec<Segment> {
+		let mut children = vec![Vec::new(); self.boxes.len()];
+		let mut top_level = Vec::new();
+		for (i, record) in self.boxes.iter().enumerate() {
+			match record.parent {
+				Some(parent) => children[parent].push(i),
+				None => top_level.push(i),
+			}
+		}
+
+		let mut segments = Vec::new();
+		let mut cursor = 0;
+		self.push_segments(&mut segments, &mut cursor, &children, None, &top_level, file_size);
+
+		segments
+	}
+
+	fn push_segments(&self, segments: &mut Vec<Segment>, cursor: &mut u64, children: &[Vec<usize>], owner: Option<usize>, siblings: &[usize], end: u64) {
+		for &child in siblings {
+			let record = &self.boxes[child];
+			if record.offset > *cursor {
+				segments.push(Segment { start: *cursor, end: record.offset, owner, is_header: false });
+			}
+
+			let header_end = record.content_offset().min(record.end());
+			segments.push(Segment { start: record.offset, end: header_end, owner: Some(child), is_header: true });
+			*cursor = header_end;
+
+			self.push_segments(segments, cursor, children, Some(child), &children[child], record.end());
+		}
+
+		if end > *cursor {
+			segments.push(Segment { start: *cursor, end, owner, is_header: false });
+			*cursor = end;
+		}
+	}
+}
+
+impl Mp4Visitor for BoxIndexVisitor {
+	fn start_box(&mut self, header: &BoxHeader, corrected_size: Option<u64>) -> io::Result<()> {
+		self.boxes.push(BoxRecord {
+			name: header.name,
+			parent: self.stack.last().copied(),
+			offset: header.offset,
+			header_size: header.header_size(),
+			size: corrected_size.unwrap_or(header.size),
+			declared_size: header.size,
+			payload: None,
+		});
+		self.stack.push(self.boxes.len() - 1);
+
+		Ok(())
+	}
+
+	fn data(&mut self, reader: &mut impl io::Read) -> io::Result<()> {
+		let current = self.boxes.last_mut().unwrap();
+		if self.capture_payloads && current.name != BoxType::MdatBox {
+			let mut payload = Vec::new();
+			reader.read_to_end(&mut payload)?;
+			current.payload = Some(payload);
+		}
+
+		Ok(())
+	}
+
+	fn end_box(&mut self, _typ: &BoxType) -> io::Result<()> {
+		self.stack.pop();
+
+		Ok(())
+	}
+
+	fn skipped(&mut self, offset: u64, end: u64) -> io::Result<()> {
+		self.skipped.push((self.stack.last().copied(), offset..end));
+
+		Ok(())
+	}
+}
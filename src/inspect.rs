@@ -1,81 +1,199 @@
 use std::io;
+use std::ops::Range;
 
+use crate::index::BoxIndexVisitor;
 use crate::mp4::{BoxHeader, BoxType, Mp4Visitor};
+use crate::selector::Selector;
+
+/// Options controlling how `print_tree` renders the box tree
+pub struct TreeOptions {
+	/// print slash-separated paths instead of an indented tree
+	pub paths: bool,
+	pub with_size: bool,
+	pub offsets: bool,
+	/// deepest level to print, where top-level boxes are at depth 1
+	pub max_depth: Option<usize>,
+	/// only print matching boxes, alongside their ancestors and descendants
+	pub only: Option<Selector>,
+	/// number of consecutive same-type siblings to print before collapsing the rest (0 disables collapsing)
+	pub collapse: usize,
+	pub ascii: bool,
+	pub color: bool,
+}
 
-#[derive(Default)]
-pub struct PathVisitor {
-	path: Vec<String>,
-	with_size: bool,
+const COLOR_CORRECTED: &str = "\x1b[33m";
+const COLOR_SKIPPED: &str = "\x1b[31m";
+const COLOR_DIM: &str = "\x1b[2m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy)]
+enum TreeItem<'a> {
+	Box(usize),
+	/// a region inside the parent that could not be parsed
+	Skipped(&'a Range<u64>),
+	/// a collapsed run of `count` boxes of the given type
+	More(usize, BoxType),
 }
 
-impl PathVisitor {
-	pub fn new(with_size: bool) -> Self {
-		Self {
-			with_size,
-			..Default::default()
+struct TreePrinter<'a> {
+	index: &'a BoxIndexVisitor,
+	options: &'a TreeOptions,
+	children: Vec<Vec<usize>>,
+	visible: Vec<bool>,
+}
+
+/// prints the indexed box tree according to the given options
+pub fn print_tree(index: &BoxIndexVisitor, options: &TreeOptions) {
+	let mut children = vec![Vec::new(); index.boxes.len()];
+	let mut top_level = Vec::new();
+	let mut depths = vec![1; index.boxes.len()];
+	for (i, record) in index.boxes.iter().enumerate() {
+		match record.parent {
+			Some(parent) => {
+				children[parent].push(i);
+				depths[i] = depths[parent] + 1;
+			},
+			None => top_level.push(i),
 		}
 	}
-}
 
-impl Mp4Visitor for PathVisitor {
-	fn start_box(&mut self, header: &BoxHeader, corrected_size: Option<u64>) -> io::Result<()> {
-		let size_description = if self.with_size {
-			if let Some(actual_size) = corrected_size {
-				format!(" ({} B declared, {} B corrected)", header.size, actual_size)
-			} else {
-				format!(" ({} B)", header.size)
+	let mut visible: Vec<bool> = depths.iter().map(|&d| options.max_depth.is_none_or(|max| d <= max)).collect();
+
+	if let Some(selector) = &options.only {
+		let mut in_subtree = vec![false; index.boxes.len()];
+		let mut selected = vec![false; index.boxes.len()];
+		for i in 0..index.boxes.len() {
+			// boxes are pre-ordered, so parents are always decided before their descendants
+			in_subtree[i] = index.boxes[i].parent.is_some_and(|p| in_subtree[p]) || selector.matches(index, i);
+			if in_subtree[i] {
+				selected[i] = true;
+
+				let mut ancestor = index.boxes[i].parent;
+				while let Some(a) = ancestor {
+					if selected[a] {
+						break;
+					}
+					selected[a] = true;
+					ancestor = index.boxes[a].parent;
+				}
 			}
-		} else {
-			"".into()
-		};
+		}
 
-		self.path.push(header.name.to_string());
-		println!("{}{}", self.path.join("/"), size_description);
-		Ok(())
-  }
+		for (v, s) in visible.iter_mut().zip(selected) {
+			*v &= s;
+		}
+	}
 
-  fn end_box(&mut self, _typ: &BoxType) -> io::Result<()> {
-    self.path.pop();
-    Ok(())
-  }
+	let printer = TreePrinter { index, options, children, visible };
+	printer.print_level(None, &top_level, None);
 }
 
-#[derive(Default)]
-pub struct PrintTreeVisitor {
-	depth: usize,
-	with_size: bool,
-}
+impl<'a> TreePrinter<'a> {
+	fn items(&self, owner: Option<usize>, siblings: &[usize]) -> Vec<TreeItem<'a>> {
+		let mut entries: Vec<TreeItem<'a>> = siblings.iter().copied().filter(|&i| self.visible[i]).map(TreeItem::Box).collect();
+		if !self.options.paths && (owner.is_none() || owner.is_some_and(|o| self.visible[o])) {
+			let skipped = self.index.skipped.iter().filter(|(o, _)| *o == owner);
+			entries.extend(skipped.map(|(_, range)| TreeItem::Skipped(range)));
+		}
+		// skipped regions sit between the boxes around them
+		entries.sort_by_key(|entry| match entry {
+			TreeItem::Box(i) => self.index.boxes[*i].offset,
+			TreeItem::Skipped(range) => range.start,
+			TreeItem::More(..) => u64::MAX,
+		});
+
+		let mut items = Vec::new();
+		let mut run_start = 0;
+		while run_start < entries.len() {
+			let TreeItem::Box(first) = entries[run_start] else {
+				items.push(entries[run_start]);
+				run_start += 1;
+				continue;
+			};
+			let typ = self.index.boxes[first].name;
+			let run_len = entries[run_start..].iter().take_while(|entry| matches!(entry, TreeItem::Box(i) if self.index.boxes[*i].name == typ)).count();
+
+			let shown = if self.options.collapse == 0 || self.options.paths { run_len } else { run_len.min(self.options.collapse) };
+			items.extend_from_slice(&entries[run_start..run_start + shown]);
+			if shown < run_len {
+				items.push(TreeItem::More(run_len - shown, typ));
+			}
 
-impl PrintTreeVisitor {
-	pub fn new(with_size: bool) -> Self {
-		Self {
-			with_size,
-			..Default::default()
+			run_start += run_len;
 		}
+
+		items
 	}
-}
 
-impl Mp4Visitor for PrintTreeVisitor {
-	fn start_box(&mut self, header: &BoxHeader, corrected_size: Option<u64>) -> io::Result<()> {
-		let size_description = if self.with_size {
-			if let Some(actual_size) = corrected_size {
-				format!(" ({} B declared, {} B corrected)", header.size, actual_size)
-			} else {
-				format!(" ({} B)", header.size)
-			}
+	/// prints `siblings` (and their descendants); `prefix` is `None` for the top level
+	fn print_level(&self, owner: Option<usize>, siblings: &[usize], prefix: Option<&str>) {
+		let items = self.items(owner, siblings);
+		let (branch, last_branch, pipe, blank) = if self.options.ascii {
+			("|-- ", "`-- ", "|   ", "    ")
 		} else {
-			"".into()
+			("├── ", "└── ", "│   ", "    ")
 		};
-		println!("{:indent$}{}{}", "", header.name, size_description, indent=self.depth * 2);
-		self.depth += 1;
 
-		Ok(())
+		for (n, item) in items.iter().enumerate() {
+			let is_last = n + 1 == items.len();
+			let (line_prefix, child_prefix) = match prefix {
+				_ if self.options.paths => (String::new(), String::new()),
+				None => (String::new(), String::new()),
+				Some(p) if is_last => (format!("{}{}", p, last_branch), format!("{}{}", p, blank)),
+				Some(p) => (format!("{}{}", p, branch), format!("{}{}", p, pipe)),
+			};
+
+			match item {
+				TreeItem::Box(i) => {
+					let record = &self.index.boxes[*i];
+					let label = if self.options.paths { self.index.path(*i) } else { record.name.to_string() };
+					let color = if self.index.skipped.iter().any(|(owner, _)| *owner == Some(*i)) {
+						Some(COLOR_SKIPPED)
+					} else if record.is_corrected() {
+						Some(COLOR_CORRECTED)
+					} else {
+						None
+					};
+
+					println!("{}{}{}", line_prefix, self.paint(&label, color), self.description(*i));
+					self.print_level(Some(*i), &self.children[*i], Some(&child_prefix));
+				},
+				TreeItem::Skipped(range) => {
+					let text = format!("[{} B unparsed at {:#x}]", range.end - range.start, range.start);
+					println!("{}{}", line_prefix, self.paint(&text, Some(COLOR_SKIPPED)));
+				},
+				TreeItem::More(count, typ) => {
+					let text = format!("… {} more {} box{}", count, typ, if *count == 1 { "" } else { "es" });
+					println!("{}{}", line_prefix, self.paint(&text, Some(COLOR_DIM)));
+				},
+			}
+		}
 	}
 
-	fn end_box(&mut self, _typ: &BoxType) -> io::Result<()> {
-		self.depth -= 1;
+	fn description(&self, i: usize) -> String {
+		let record = &self.index.boxes[i];
+		let mut description = String::new();
 
-		Ok(())
+		if self.options.offsets {
+			description.push_str(&format!(" @{:#x}", record.offset));
+		}
+
+		if self.options.with_size {
+			if record.is_corrected() {
+				description.push_str(&format!(" ({} B declared, {} B corrected)", record.declared_size, record.size));
+			} else {
+				description.push_str(&format!(" ({} B)", record.size));
+			}
+		}
+
+		description
+	}
+
+	fn paint(&self, text: &str, color: Option<&str>) -> String {
+		match color {
+			Some(code) if self.options.color => format!("{}{}{}", code, text, COLOR_RESET),
+			_ => text.to_string(),
+		}
 	}
 }
 
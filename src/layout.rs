@@ -0,0 +1,70 @@
+use std::io;
+use std::path::Path;
+
+use crate::check::CRITICAL_BOXES;
+use crate::index::index_file;
+use crate::mp4::BoxType;
+
+const PADDING_BOXES: [BoxType; 2] = [BoxType::FreeBox, BoxType::WideBox];
+
+/// reports the placement of top-level boxes and how much of the file is metadata versus media
+pub fn layout(file: &Path) -> io::Result<()> {
+	let (index, file_size) = index_file(file, false)?;
+
+	let mut media = 0;
+	let mut padding = 0;
+	let mut parsed = 0;
+
+	println!("{:>12} {:>12}  box", "offset", "size");
+	for record in index.boxes.iter().filter(|b| b.parent.is_none()) {
+		println!("{:>#12x} {:>12}  {}", record.offset, record.size, record.name);
+
+		parsed += record.size;
+		if record.name == BoxType::MdatBox {
+			media += record.size - record.header_size.min(record.size);
+		} else if PADDING_BOXES.contains(&record.name) {
+			padding += record.size;
+		}
+	}
+
+	let unparsed = file_size.saturating_sub(parsed);
+	let metadata = file_size - media - padding - unparsed;
+	let percent = |bytes: u64| if file_size == 0 { 0.0 } else { bytes as f64 * 100.0 / file_size as f64 };
+
+	println!();
+	println!("file size:      {:>12} B", file_size);
+	println!("media (mdat):   {:>12} B ({:.2}%)", media, percent(media));
+	println!("metadata:       {:>12} B ({:.2}%)", metadata, percent(metadata));
+	println!("padding (free): {:>12} B ({:.2}%)", padding, percent(padding));
+	if unparsed > 0 {
+		println!("unparsed:       {:>12} B ({:.2}%)", unparsed, percent(unparsed));
+	}
+	println!();
+
+	let moov = index.find_top_level(BoxType::MoovBox);
+	let first_mdat = index.find_top_level(BoxType::MdatBox);
+
+	let Some(moov) = moov else {
+		println!("no moov box found");
+		return Ok(());
+	};
+
+	match first_mdat {
+		Some(mdat) if index.boxes[moov].offset < index.boxes[mdat].offset => println!("moov precedes mdat (faststart)"),
+		Some(_) => println!("moov follows mdat (not faststart)"),
+		None => println!("no mdat box found"),
+	}
+
+	let moov_end = index.boxes[moov].end();
+	println!("{} B can be truncated from the end before moov is cut", file_size.saturating_sub(moov_end));
+
+	let critical_end = CRITICAL_BOXES.iter()
+		.flat_map(|&typ| index.find_all(typ))
+		.map(|i| index.boxes[i].end())
+		.max();
+	if let Some(critical_end) = critical_end {
+		println!("{} B can be truncated from the end before critical sample tables are cut", file_size.saturating_sub(critical_end));
+	}
+
+	Ok(())
+}
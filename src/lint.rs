@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::index::{index_file, BoxIndexVisitor};
+use crate::mp4::BoxType;
+use crate::payload::PayloadReader;
+use crate::track::{self, parse_mvhd};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+	Allow,
+	Warn,
+	Deny,
+}
+
+impl fmt::Display for Level {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.pad(match self {
+			Level::Allow => "allow",
+			Level::Warn => "warning",
+			Level::Deny => "error",
+		})
+	}
+}
+
+pub struct Rule {
+	pub id: &'static str,
+	pub default: Level,
+	pub description: &'static str,
+}
+
+pub const RULES: &[Rule] = &[
+	Rule { id: "ftyp-first", default: Level::Deny, description: "the first top-level box must be ftyp" },
+	Rule { id: "duplicate-box", default: Level::Deny, description: "boxes that may only appear once per container are repeated, or alternatives appear together" },
+	Rule { id: "unsupported-version", default: Level::Deny, description: "a FullBox declares a version this tool cannot decode" },
+	Rule { id: "next-track-id", default: Level::Deny, description: "mvhd next_track_ID must exceed every (unique, non-zero) tkhd track_ID" },
+	Rule { id: "duration-mismatch", default: Level::Warn, description: "tkhd/mdhd durations disagree with the stts sample durations" },
+	Rule { id: "dref-self-contained", default: Level::Warn, description: "data references point outside of the file" },
+	Rule { id: "illegal-parent", default: Level::Warn, description: "a box appears inside a container that may not hold it" },
+];
+
+/// A single rule violation
+pub struct Finding {
+	pub rule: &'static str,
+	pub level: Level,
+	pub path: String,
+	pub offset: u64,
+	pub message: String,
+}
+
+impl fmt::Display for Finding {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}[{}]: {} (at {} {:#x})", self.level, self.rule, self.message, self.path, self.offset)
+	}
+}
+
+/// Effective level for each rule, starting from the rule defaults
+pub struct LintLevels(HashMap<&'static str, Level>);
+
+impl Default for LintLevels {
+	fn default() -> Self {
+		Self(RULES.iter().map(|rule| (rule.id, rule.default)).collect())
+	}
+}
+
+impl LintLevels {
+	/// overrides the level for the given rule id, or for every rule if `id` is `all`
+	pub fn set(&mut self, id: &str, level: Level) -> io::Result<()> {
+		if id == "all" {
+			self.0.values_mut().for_each(|l| *l = level);
+			return Ok(());
+		}
+
+		match self.0.iter_mut().find(|(rule, _)| **rule == id) {
+			Some((_, l)) => {
+				*l = level;
+				Ok(())
+			},
+			None => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown lint rule '{}'", id))),
+		}
+	}
+
+	fn get(&self, id: &str) -> Level {
+		self.0.get(id).copied().unwrap_or(Level::Allow)
+	}
+}
+
+/// containers that each box type may legally appear in (`None` denotes the top level)
+const PARENTS: &[(BoxType, &[Option<BoxType>])] = &[
+	(BoxType::FtypBox, &[None]),
+	(BoxType::MoovBox, &[None]),
+	(BoxType::MdatBox, &[None]),
+	(BoxType::MoofBox, &[None]),
+	(BoxType::MvhdBox, &[Some(BoxType::MoovBox)]),
+	(BoxType::TrakBox, &[Some(BoxType::MoovBox)]),
+	(BoxType::MvexBox, &[Some(BoxType::MoovBox)]),
+	(BoxType::MehdBox, &[Some(BoxType::MvexBox)]),
+	(BoxType::TrexBox, &[Some(BoxType::MvexBox)]),
+	(BoxType::TkhdBox, &[Some(BoxType::TrakBox)]),
+	(BoxType::EdtsBox, &[Some(BoxType::TrakBox)]),
+	(BoxType::MdiaBox, &[Some(BoxType::TrakBox)]),
+	(BoxType::ElstBox, &[Some(BoxType::EdtsBox)]),
+	(BoxType::MdhdBox, &[Some(BoxType::MdiaBox)]),
+	(BoxType::HdlrBox, &[Some(BoxType::MdiaBox), Some(BoxType::MetaBox)]),
+	(BoxType::MinfBox, &[Some(BoxType::MdiaBox)]),
+	(BoxType::VmhdBox, &[Some(BoxType::MinfBox)]),
+	(BoxType::SmhdBox, &[Some(BoxType::MinfBox)]),
+	(BoxType::NmhdBox, &[Some(BoxType::MinfBox)]),
+	(BoxType::DinfBox, &[Some(BoxType::MinfBox), Some(BoxType::MetaBox)]),
+	(BoxType::DrefBox, &[Some(BoxType::DinfBox)]),
+	(BoxType::StblBox, &[Some(BoxType::MinfBox)]),
+	(BoxType::StsdBox, &[Some(BoxType::StblBox)]),
+	(BoxType::SttsBox, &[Some(BoxType::StblBox)]),
+	(BoxType::CttsBox, &[Some(BoxType::StblBox)]),
+	(BoxType::CslgBox, &[Some(BoxType::StblBox)]),
+	(BoxType::StssBox, &[Some(BoxType::StblBox)]),
+	(BoxType::StscBox, &[Some(BoxType::StblBox)]),
+	(BoxType::StszBox, &[Some(BoxType::StblBox)]),
+	(BoxType::StcoBox, &[Some(BoxType::StblBox)]),
+	(BoxType::Co64Box, &[Some(BoxType::StblBox)]),
+	(BoxType::SdtpBox, &[Some(BoxType::StblBox), Some(BoxType::TrafBox)]),
+	(BoxType::SgpdBox, &[Some(BoxType::StblBox), Some(BoxType::TrafBox)]),
+	(BoxType::SbgpBox, &[Some(BoxType::StblBox), Some(BoxType::TrafBox)]),
+	(BoxType::MfhdBox, &[Some(BoxType::MoofBox)]),
+	(BoxType::TrafBox, &[Some(BoxType::MoofBox)]),
+	(BoxType::TfhdBox, &[Some(BoxType::TrafBox)]),
+	(BoxType::TfdtBox, &[Some(BoxType::TrafBox)]),
+	(BoxType::TrunBox, &[Some(BoxType::TrafBox)]),
+	(BoxType::StypBox, &[None]),
+	(BoxType::SidxBox, &[None]),
+	(BoxType::SsixBox, &[None]),
+	(BoxType::PrftBox, &[None]),
+	(BoxType::MfraBox, &[None]),
+	(BoxType::TfraBox, &[Some(BoxType::MfraBox)]),
+	(BoxType::MfroBox, &[Some(BoxType::MfraBox)]),
+];
+
+/// children that may appear at most once within the given container (`None` denotes the top level)
+const SINGLETONS: &[(Option<BoxType>, &[BoxType])] = &[
+	(None, &[BoxType::FtypBox, BoxType::MoovBox]),
+	(Some(BoxType::MoovBox), &[BoxType::MvhdBox, BoxType::MvexBox]),
+	(Some(BoxType::MvexBox), &[BoxType::MehdBox]),
+	(Some(BoxType::TrakBox), &[BoxType::TkhdBox, BoxType::EdtsBox, BoxType::MdiaBox]),
+	(Some(BoxType::MdiaBox), &[BoxType::MdhdBox, BoxType::HdlrBox, BoxType::MinfBox]),
+	(Some(BoxType::MinfBox), &[BoxType::VmhdBox, BoxType::SmhdBox, BoxType::NmhdBox, BoxType::DinfBox, BoxType::StblBox]),
+	(Some(BoxType::DinfBox), &[BoxType::DrefBox]),
+	(Some(BoxType::StblBox), &[BoxType::StsdBox, BoxType::SttsBox, BoxType::CttsBox, BoxType::StssBox, BoxType::StscBox, BoxType::StszBox, BoxType::StcoBox, BoxType::Co64Box]),
+	(Some(BoxType::MoofBox), &[BoxType::MfhdBox]),
+	(Some(BoxType::TrafBox), &[BoxType::TfhdBox, BoxType::TfdtBox]),
+	(Some(BoxType::MfraBox), &[BoxType::MfroBox]),
+];
+
+/// alternative children of which a container may hold only one kind, e.g. 32- and 64-bit chunk offsets
+const EXCLUSIVE: &[(BoxType, &[BoxType])] = &[
+	(BoxType::StblBox, &[BoxType::StcoBox, BoxType::Co64Box]),
+];
+
+/// FullBox versions this tool knows how to decode
+const VERSIONS: &[(BoxType, u8)] = &[
+	(BoxType::MvhdBox, 1),
+	(BoxType::TkhdBox, 1),
+	(BoxType::MdhdBox, 1),
+	(BoxType::ElstBox, 1),
+	(BoxType::CttsBox, 1),
+	(BoxType::HdlrBox, 0),
+	(BoxType::DrefBox, 0),
+	(BoxType::StsdBox, 0),
+	(BoxType::SttsBox, 0),
+	(BoxType::StssBox, 0),
+	(BoxType::StscBox, 0),
+	(BoxType::StszBox, 0),
+	(BoxType::StcoBox, 0),
+	(BoxType::Co64Box, 0),
+	(BoxType::SidxBox, 1),
+	(BoxType::TfraBox, 1),
+	(BoxType::MfroBox, 0),
+	(BoxType::SsixBox, 0),
+	(BoxType::PrftBox, 1),
+];
+
+struct Linter<'a> {
+	index: &'a BoxIndexVisitor,
+	levels: &'a LintLevels,
+	findings: Vec<Finding>,
+}
+
+impl<'a> Linter<'a> {
+	fn report(&mut self, rule: &'static str, target: usize, message: String) {
+		let level = self.levels.get(rule);
+		if level != Level::Allow {
+			self.findings.push(Finding {
+				rule,
+				level,
+				path: self.index.path(target),
+				offset: self.index.boxes[target].offset,
+				message,
+			});
+		}
+	}
+
+	fn parent_type(&self, i: usize) -> Option<BoxType> {
+		self.index.boxes[i].parent.map(|p| self.index.boxes[p].name)
+	}
+
+	fn ftyp_first(&mut self) {
+		match self.index.boxes.first() {
+			Some(first) if first.name == BoxType::FtypBox => {},
+			Some(first) => {
+				let message = match self.index.find_top_level(BoxType::FtypBox) {
+					Some(_) => format!("ftyp is preceded by a {} box", first.name),
+					None => "file has no ftyp box".into(),
+				};
+				self.report("ftyp-first", 0, message);
+			},
+			None => {},
+		}
+	}
+
+	fn duplicate_boxes(&mut self) {
+		let containers = std::iter::once(None).chain((0..self.index.boxes.len()).map(Some));
+
+		for container in containers {
+			let container_type = container.map(|c| self.index.boxes[c].name);
+			let Some((_, singletons)) = SINGLETONS.iter().find(|(typ, _)| *typ == container_type) else {
+				continue;
+			};
+
+			let children: Vec<usize> = match container {
+				Some(c) => self.index.children(c).collect(),
+				None => (0..self.index.boxes.len()).filter(|&i| self.index.boxes[i].parent.is_none()).collect(),
+			};
+			for typ in *singletons {
+				let matching: Vec<usize> = children.iter().copied().filter(|&i| self.index.boxes[i].name == *typ).collect();
+				for &duplicate in matching.iter().skip(1) {
+					self.report("duplicate-box", duplicate, format!("{} box appears {} times in the same container", typ, matching.len()));
+				}
+			}
+		}
+
+		for &(container_type, alternatives) in EXCLUSIVE {
+			for container in self.index.find_all(container_type) {
+				let present: Vec<BoxType> = alternatives.iter().copied().filter(|&typ| self.index.find_child(container, typ).is_some()).collect();
+				if present.len() > 1 {
+					let names: Vec<String> = present.iter().map(|typ| typ.to_string()).collect();
+					self.report("duplicate-box", container, format!("{} box holds both {}, which are alternatives", container_type, names.join(" and ")));
+				}
+			}
+		}
+	}
+
+	fn versions(&mut self) {
+		for i in 0..self.index.boxes.len() {
+			let record = &self.index.boxes[i];
+			let Some((_, max_version)) = VERSIONS.iter().find(|(typ, _)| *typ == record.name) else {
+				continue;
+			};
+
+			if let Some(&version) = record.payload.as_ref().and_then(|p| p.first()) {
+				if version > *max_version {
+					self.report("unsupported-version", i, format!("{} box version {} is not supported (expected at most {})", record.name, version, max_version));
+				}
+			}
+		}
+	}
+
+	fn illegal_parents(&mut self) {
+		for i in 0..self.index.boxes.len() {
+			let name = self.index.boxes[i].name;
+			let Some((_, parents)) = PARENTS.iter().find(|(typ, _)| *typ == name) else {
+				continue;
+			};
+
+			let parent = self.parent_type(i);
+			if !parents.contains(&parent) {
+				let container = parent.map(|p| format!("a {} box", p)).unwrap_or("the top level".into());
+				self.report("illegal-parent", i, format!("{} box is not allowed in {}", name, container));
+			}
+		}
+	}
+
+	fn tracks(&mut self) {
+		let mvhd = self.index.find_all(BoxType::MvhdBox).first().copied();
+		let movie_header = mvhd.and_then(|i| self.index.boxes[i].payload.as_deref()).and_then(|p| parse_mvhd(p).ok());
+
+		let tracks = track::tracks(self.index);
+		let mut seen_ids: Vec<u32> = Vec::new();
+
+		for track in &tracks {
+			let Some(tkhd) = &track.tkhd else { continue };
+			let tkhd_box = self.index.find_child(track.trak, BoxType::TkhdBox).unwrap();
+
+			if tkhd.track_id == 0 {
+				self.report("next-track-id", tkhd_box, "track_ID must not be zero".into());
+			} else if seen_ids.contains(&tkhd.track_id) {
+				self.report("next-track-id", tkhd_box, format!("track_ID {} is used by more than one track", tkhd.track_id));
+			}
+			seen_ids.push(tkhd.track_id);
+
+			// duration consistency between stts, mdhd, and (in the absence of an edit list) tkhd
+			let (Some(mdhd), Some(stts_duration)) = (&track.mdhd, track.stts_duration()) else { continue };
+			let mdhd_box = self.index.find_path(track.trak, &[BoxType::MdiaBox, BoxType::MdhdBox]).unwrap();
+
+			if mdhd.duration != stts_duration {
+				self.report("duration-mismatch", mdhd_box, format!("{} duration {} does not match the stts total of {}", track.label(), mdhd.duration, stts_duration));
+			}
+
+			let has_edits = self.index.find_child(track.trak, BoxType::EdtsBox).is_some();
+			if let (Some(mvhd), false) = (&movie_header, has_edits) {
+				if mdhd.timescale != 0 {
+					let expected = (stts_duration as u128 * mvhd.timescale as u128 / mdhd.timescale as u128) as u64;
+					if tkhd.duration.abs_diff(expected) > 1 {
+						self.report("duration-mismatch", tkhd_box, format!("{} tkhd duration {} does not match the stts total of {} in the movie timescale", track.label(), tkhd.duration, expected));
+					}
+				}
+			}
+		}
+
+		if let (Some(mvhd), Some(mvhd_box)) = (&movie_header, mvhd) {
+			// 0xffffffff instructs writers to search for an unused id
+			if let Some(&max_id) = seen_ids.iter().max() {
+				if mvhd.next_track_id != u32::MAX && mvhd.next_track_id <= max_id {
+					self.report("next-track-id", mvhd_box, format!("next_track_ID {} conflicts with existing track_ID {}", mvhd.next_track_id, max_id));
+				}
+			}
+		}
+	}
+
+	fn data_references(&mut self) {
+		for i in self.index.find_all(BoxType::DrefBox) {
+			let Some(payload) = self.index.boxes[i].payload.as_deref() else { continue };
+
+			match parse_dref_flags(payload) {
+				Ok(entries) if entries.is_empty() => self.report("dref-self-contained", i, "dref contains no data references".into()),
+				Ok(entries) => {
+					for (n, (typ, flags)) in entries.into_iter().enumerate() {
+						if flags & 0x1 == 0 {
+							self.report("dref-self-contained", i, format!("data reference {} ({}) points to media outside of this file", n + 1, typ));
+						}
+					}
+				},
+				Err(e) => self.report("dref-self-contained", i, format!("unable to decode dref box: {}", e)),
+			}
+		}
+	}
+}
+
+/// type and flags of each entry in a `dref` box
+fn parse_dref_flags(data: &[u8]) -> io::Result<Vec<(BoxType, u32)>> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let entry_count = reader.u32()?;
+
+	let mut entries = Vec::new();
+	for _ in 0..entry_count {
+		let size = reader.u32()? as usize;
+		let typ = BoxType::from(reader.u32()?);
+		let (_version, flags) = reader.version_flags()?;
+		reader.skip(size.saturating_sub(12))?;
+		entries.push((typ, flags));
+	}
+
+	Ok(entries)
+}
+
+/// runs every enabled rule against the given file
+pub fn lint(file: &Path, levels: &LintLevels) -> io::Result<Vec<Finding>> {
+	let (index, _size) = index_file(file, true)?;
+
+	let mut linter = Linter {
+		index: &index,
+		levels,
+		findings: Vec::new(),
+	};
+
+	linter.ftyp_first();
+	linter.duplicate_boxes();
+	linter.versions();
+	linter.tracks();
+	linter.data_references();
+	linter.illegal_parents();
+
+	let mut findings = linter.findings;
+	findings.sort_by_key(|f| f.offset);
+	Ok(findings)
+}
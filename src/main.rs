@@ -1,24 +1,51 @@
+mod bits;
+mod check;
+mod codec;
+mod coverage;
+mod damage;
+mod extract_track;
+mod fingerprint;
+mod fragment;
+mod gop;
+mod index;
 mod inspect;
+mod layout;
+mod lint;
 mod mangle;
 mod moov_transplant;
 mod mp4;
+mod payload;
+mod playability;
+mod provenance;
+mod retag;
+mod samples;
+mod segment;
+mod selector;
 mod strip;
-
-use std::fs::File;
-use std::io;
+mod survey;
+mod tags;
+mod timeline;
+mod times;
+mod track;
+
+use std::fs::{self, File};
+use std::io::{self, IsTerminal};
 use std::path::PathBuf;
-use std::process::{self, Command, Stdio};
-use std::time::{Duration, Instant};
-use std::thread;
+use std::process;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 use env_logger::{Env, Builder};
 use ::mp4::Mp4Reader;
 
-use crate::inspect::{ExtractVisitor, PrintTreeVisitor, PathVisitor};
+use crate::inspect::{ExtractVisitor, TreeOptions};
+use crate::mangle::{Headers, Region};
 use crate::mp4::{BoxType, read_box};
 use crate::moov_transplant::{MoovLocatorVisitor, MoovTransplantVisitor};
+use crate::playability::BackendKind;
+use crate::retag::{TagEdit, ValueKind};
+use crate::selector::Selector;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -87,70 +114,91 @@ enum BoxTypeArg {
 	Covr,
 	Desc,
 	Wide,
+	Sidx,
+	Styp,
+	Mfra,
+	Tfra,
+	Mfro,
+	Ssix,
+	Prft,
 }
 
-impl Into<BoxType> for BoxTypeArg {
-	fn into(self) -> BoxType {
-		match self {
-			Self::Ftyp => BoxType::FtypBox,
-			Self::Mvhd => BoxType::MvhdBox,
-			Self::Mfhd => BoxType::MfhdBox,
-			Self::Free => BoxType::FreeBox,
-			Self::Mdat => BoxType::MdatBox,
-			Self::Moov => BoxType::MoovBox,
-			Self::Mvex => BoxType::MvexBox,
-			Self::Mehd => BoxType::MehdBox,
-			Self::Trex => BoxType::TrexBox,
-			Self::Emsg => BoxType::EmsgBox,
-			Self::Moof => BoxType::MoofBox,
-			Self::Tkhd => BoxType::TkhdBox,
-			Self::Tfhd => BoxType::TfhdBox,
-			Self::Tfdt => BoxType::TfdtBox,
-			Self::Edts => BoxType::EdtsBox,
-			Self::Mdia => BoxType::MdiaBox,
-			Self::Elst => BoxType::ElstBox,
-			Self::Mdhd => BoxType::MdhdBox,
-			Self::Hdlr => BoxType::HdlrBox,
-			Self::Minf => BoxType::MinfBox,
-			Self::Vmhd => BoxType::VmhdBox,
-			Self::Stbl => BoxType::StblBox,
-			Self::Stsd => BoxType::StsdBox,
-			Self::Stts => BoxType::SttsBox,
-			Self::Ctts => BoxType::CttsBox,
-			Self::Stss => BoxType::StssBox,
-			Self::Stsc => BoxType::StscBox,
-			Self::Stsz => BoxType::StszBox,
-			Self::Stco => BoxType::StcoBox,
-			Self::Co64 => BoxType::Co64Box,
-			Self::Trak => BoxType::TrakBox,
-			Self::Traf => BoxType::TrafBox,
-			Self::Trun => BoxType::TrunBox,
-			Self::Udta => BoxType::UdtaBox,
-			Self::Meta => BoxType::MetaBox,
-			Self::Dinf => BoxType::DinfBox,
-			Self::Dref => BoxType::DrefBox,
-			Self::Url => BoxType::UrlBox,
-			Self::Smhd => BoxType::SmhdBox,
-			Self::Avc1 => BoxType::Avc1Box,
-			Self::AvcC => BoxType::AvcCBox,
-			Self::Hev1 => BoxType::Hev1Box,
-			Self::HvcC => BoxType::HvcCBox,
-			Self::Mp4a => BoxType::Mp4aBox,
-			Self::Esds => BoxType::EsdsBox,
-			Self::Tx3g => BoxType::Tx3gBox,
-			Self::Vpcc => BoxType::VpccBox,
-			Self::Vp09 => BoxType::Vp09Box,
-			Self::Data => BoxType::DataBox,
-			Self::Ilst => BoxType::IlstBox,
-			Self::Name => BoxType::NameBox,
-			Self::Day => BoxType::DayBox,
-			Self::Covr => BoxType::CovrBox,
-			Self::Desc => BoxType::DescBox,
-			Self::Wide => BoxType::WideBox,
+impl From<BoxTypeArg> for BoxType {
+	fn from(arg: BoxTypeArg) -> BoxType {
+		match arg {
+			BoxTypeArg::Ftyp => BoxType::FtypBox,
+			BoxTypeArg::Mvhd => BoxType::MvhdBox,
+			BoxTypeArg::Mfhd => BoxType::MfhdBox,
+			BoxTypeArg::Free => BoxType::FreeBox,
+			BoxTypeArg::Mdat => BoxType::MdatBox,
+			BoxTypeArg::Moov => BoxType::MoovBox,
+			BoxTypeArg::Mvex => BoxType::MvexBox,
+			BoxTypeArg::Mehd => BoxType::MehdBox,
+			BoxTypeArg::Trex => BoxType::TrexBox,
+			BoxTypeArg::Emsg => BoxType::EmsgBox,
+			BoxTypeArg::Moof => BoxType::MoofBox,
+			BoxTypeArg::Tkhd => BoxType::TkhdBox,
+			BoxTypeArg::Tfhd => BoxType::TfhdBox,
+			BoxTypeArg::Tfdt => BoxType::TfdtBox,
+			BoxTypeArg::Edts => BoxType::EdtsBox,
+			BoxTypeArg::Mdia => BoxType::MdiaBox,
+			BoxTypeArg::Elst => BoxType::ElstBox,
+			BoxTypeArg::Mdhd => BoxType::MdhdBox,
+			BoxTypeArg::Hdlr => BoxType::HdlrBox,
+			BoxTypeArg::Minf => BoxType::MinfBox,
+			BoxTypeArg::Vmhd => BoxType::VmhdBox,
+			BoxTypeArg::Stbl => BoxType::StblBox,
+			BoxTypeArg::Stsd => BoxType::StsdBox,
+			BoxTypeArg::Stts => BoxType::SttsBox,
+			BoxTypeArg::Ctts => BoxType::CttsBox,
+			BoxTypeArg::Stss => BoxType::StssBox,
+			BoxTypeArg::Stsc => BoxType::StscBox,
+			BoxTypeArg::Stsz => BoxType::StszBox,
+			BoxTypeArg::Stco => BoxType::StcoBox,
+			BoxTypeArg::Co64 => BoxType::Co64Box,
+			BoxTypeArg::Trak => BoxType::TrakBox,
+			BoxTypeArg::Traf => BoxType::TrafBox,
+			BoxTypeArg::Trun => BoxType::TrunBox,
+			BoxTypeArg::Udta => BoxType::UdtaBox,
+			BoxTypeArg::Meta => BoxType::MetaBox,
+			BoxTypeArg::Dinf => BoxType::DinfBox,
+			BoxTypeArg::Dref => BoxType::DrefBox,
+			BoxTypeArg::Url => BoxType::UrlBox,
+			BoxTypeArg::Smhd => BoxType::SmhdBox,
+			BoxTypeArg::Avc1 => BoxType::Avc1Box,
+			BoxTypeArg::AvcC => BoxType::AvcCBox,
+			BoxTypeArg::Hev1 => BoxType::Hev1Box,
+			BoxTypeArg::HvcC => BoxType::HvcCBox,
+			BoxTypeArg::Mp4a => BoxType::Mp4aBox,
+			BoxTypeArg::Esds => BoxType::EsdsBox,
+			BoxTypeArg::Tx3g => BoxType::Tx3gBox,
+			BoxTypeArg::Vpcc => BoxType::VpccBox,
+			BoxTypeArg::Vp09 => BoxType::Vp09Box,
+			BoxTypeArg::Data => BoxType::DataBox,
+			BoxTypeArg::Ilst => BoxType::IlstBox,
+			BoxTypeArg::Name => BoxType::NameBox,
+			BoxTypeArg::Day => BoxType::DayBox,
+			BoxTypeArg::Covr => BoxType::CovrBox,
+			BoxTypeArg::Desc => BoxType::DescBox,
+			BoxTypeArg::Wide => BoxType::WideBox,
+			BoxTypeArg::Sidx => BoxType::SidxBox,
+			BoxTypeArg::Styp => BoxType::StypBox,
+			BoxTypeArg::Mfra => BoxType::MfraBox,
+			BoxTypeArg::Tfra => BoxType::TfraBox,
+			BoxTypeArg::Mfro => BoxType::MfroBox,
+			BoxTypeArg::Ssix => BoxType::SsixBox,
+			BoxTypeArg::Prft => BoxType::PrftBox,
 		}
 	}
 }
 
+#[derive(Clone, ValueEnum)]
+enum ColorChoice {
+	Auto,
+	Always,
+	Never,
+}
+
 #[derive(Subcommand)]
 enum AppCommand {
 	/// Attempt to read box/atom structure from the given MP4 file
@@ -168,6 +216,17 @@ enum AppCommand {
 		output: PathBuf,
 	},
 
+	/// Write a track's samples as a raw elementary stream (Annex B for H.264/H.265, ADTS for AAC)
+	ExtractTrack {
+		/// ID of the track to extract
+		#[arg(short, long)]
+		track: u32,
+		/// path to input video file
+		input: PathBuf,
+		/// path to target output file
+		output: PathBuf,
+	},
+
 	/// intentionally corrupt a given file
 	#[command(subcommand)]
 	Mangle(MangleCommand),
@@ -184,6 +243,24 @@ enum AppCommand {
 		output: PathBuf,
 	},
 
+	/// Walks a directory tree and writes per-file structural statistics as CSV
+	Survey {
+		/// only survey files with the given extension (may be repeated; default: all files)
+		#[arg(short, long = "extension")]
+		extensions: Vec<String>,
+
+		/// write CSV to the given file instead of stdout
+		#[arg(short, long)]
+		output: Option<PathBuf>,
+
+		/// path to dataset directory
+		dir: PathBuf,
+	},
+
+	/// Edit iTunes-style metadata tags (`moov/udta/meta/ilst`) in place
+	#[command(subcommand)]
+	Tags(TagsCommand),
+
 	/// "Recovers" a corrupted video by splicing in a new moov box/atom contained in the specified file
 	MoovTransplant {
 		/// path to source file containing source moov atom
@@ -197,13 +274,180 @@ enum AppCommand {
 
 #[derive(Subcommand)]
 enum InspectCommand {
-	/// Tests playability using mpv
+	/// Tests playability with mpv, ffmpeg, ffprobe or the native static check
 	/// Exits 0 if the file is playable or 1 if the file is unplayable
 	IsPlayable {
-		/// amount of time to test playability (in milliseconds)
-		/// input file is considered playable if this time elapses and mpv hasn't exited
-		#[arg(short = 't', long = "timeout", default_value = "300")]
-		timeout_ms: u64,
+		/// how to test the file
+		#[arg(short, long, value_enum, default_value = "mpv")]
+		backend: BackendKind,
+
+		/// executable to run instead of the backend's default program (e.g. a fake player script)
+		#[arg(long, value_name = "PATH")]
+		player: Option<PathBuf>,
+
+		/// amount of time to test playability (in milliseconds); defaults to 10 s plus twice the declared duration
+		/// a player still running when this time elapses only passes if it has reached the end of the file
+		#[arg(short = 't', long = "timeout")]
+		timeout_ms: Option<u64>,
+
+		/// print the verdict as JSON
+		#[arg(long)]
+		json: bool,
+
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Tests playability statically by checking for the required boxes/atoms and consistent sample tables
+	/// Exits 0 if the file is playable or 1 if the file is unplayable
+	Check {
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Check the file against ISO BMFF conformance rules
+	/// Exits 0 if no rule at error level was violated or 1 otherwise
+	Lint {
+		/// silence the given rule (or `all` rules)
+		#[arg(short = 'A', long, value_name = "RULE")]
+		allow: Vec<String>,
+
+		/// report the given rule (or `all` rules) as a warning
+		#[arg(short = 'W', long, value_name = "RULE")]
+		warn: Vec<String>,
+
+		/// report the given rule (or `all` rules) as an error
+		#[arg(short = 'D', long, value_name = "RULE")]
+		deny: Vec<String>,
+
+		/// list available rules and their default levels
+		#[arg(long)]
+		list: bool,
+
+		/// path to target file
+		#[arg(required_unless_present = "list")]
+		file: Option<PathBuf>,
+	},
+
+	/// Map every sample to its byte range and report how much of mdat the sample tables reference
+	Coverage {
+		/// maximum number of gaps/overlaps to list
+		#[arg(short, long, default_value = "20")]
+		limit: usize,
+
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Report the placement of top-level boxes/atoms and the proportion of metadata to media
+	Layout {
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Print iTunes-style metadata tags (title, date, cover art, ...) stored in moov/udta/meta/ilst
+	Tags {
+		/// print tags as JSON
+		#[arg(long)]
+		json: bool,
+
+		/// write embedded cover art to the given path (additional images are numbered)
+		#[arg(long, value_name = "PATH")]
+		export_cover: Option<PathBuf>,
+
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Decode codec configuration records (avcC, hvcC, esds, vpcC) from the sample descriptions
+	Codec {
+		/// only show the track with this ID
+		#[arg(short, long)]
+		track: Option<u32>,
+
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// List movie fragments (moof/traf/trun) and report sequence gaps, timing gaps and truncation
+	Fragments {
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Parse slice headers to report GOP structure, I/P/B frame counts and keyframes that disagree with stss
+	Gop {
+		/// ID of the track to analyze (defaults to the first video track)
+		#[arg(short, long)]
+		track: Option<u32>,
+
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Hash the box layout (ignoring sizes) and compare it against a database of known recorders
+	Fingerprint {
+		/// signature database (JSON)
+		#[arg(long, default_value = "signatures.json")]
+		db: PathBuf,
+
+		/// record this file's layout in the database under the given name instead of matching it
+		#[arg(long, value_name = "NAME")]
+		add: Option<String>,
+
+		/// print the canonical layout the fingerprint is computed from
+		#[arg(long)]
+		show_layout: bool,
+
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Summarize location, device, software and creation time from Apple and Android metadata
+	Provenance {
+		/// print the record as JSON
+		#[arg(long)]
+		json: bool,
+
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// List the timing, size, location and sync status of every sample in a track
+	Samples {
+		/// ID of the track to list
+		#[arg(short, long)]
+		track: u32,
+
+		/// print rows as CSV (the summary is logged instead of printed)
+		#[arg(long)]
+		csv: bool,
+
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Check sidx references, styp/prft/ssix boxes and the mfra random access table of a segmented (DASH/CMAF) file
+	Segments {
+		/// show which fragment a seek to this many seconds lands on, using mfra when it is intact
+		#[arg(long)]
+		seek: Option<f64>,
+
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Decode edit lists (elst) and show where each track lands on the presentation timeline
+	Timeline {
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// Decode creation/modification times from mvhd, tkhd, mdhd and date tags, and flag inconsistencies
+	Times {
+		/// Differences (in seconds) up to this size are not reported
+		#[arg(long, default_value = "60")]
+		tolerance: u64,
 
 		/// path to target file
 		file: PathBuf,
@@ -221,25 +465,116 @@ enum InspectCommand {
 		/// Print box sizes
 		#[arg(long)]
 		with_size: bool,
+
+		/// Print the offset of each box within the file
+		#[arg(long)]
+		offsets: bool,
+
+		/// Only descend this many levels (top-level boxes are at depth 1)
+		#[arg(short = 'd', long)]
+		max_depth: Option<usize>,
+
+		/// Only print boxes matching the selector (e.g. `trak`, `moov//stsz`, `/moov/mvhd`), with their ancestors and descendants
+		#[arg(long)]
+		only: Option<Selector>,
+
+		/// Number of consecutive boxes of the same type to print before collapsing the rest (0 disables collapsing)
+		#[arg(long, default_value = "5")]
+		collapse: usize,
+
+		/// Draw the tree with ASCII instead of Unicode connectors
+		#[arg(long)]
+		ascii: bool,
+
+		/// Highlight boxes with corrected sizes (yellow) or unparsable contents (red)
+		#[arg(long, value_enum, default_value = "auto")]
+		color: ColorChoice,
 	},
 
 	/// Perform deep inspection on the given MP4 file by parsing and printing debug-formatted output (only works with a mostly-well-formed MP4 file)
 	Debug {
 		/// path to target file
 		file: PathBuf,
-	}
+	},
+
+	/// Map the bytes altered by a mangle onto the original file's box/atom tree
+	Damage {
+		/// path to the unmodified file
+		original: PathBuf,
+		/// path to the mangled copy
+		#[arg(required_unless_present = "log")]
+		mangled: Option<PathBuf>,
+		/// take the altered ranges from a mangle log instead of comparing against the mangled copy
+		#[arg(long, value_name = "PATH", conflicts_with = "mangled")]
+		log: Option<PathBuf>,
+	},
+}
+
+#[derive(Subcommand)]
+enum TagsCommand {
+	/// replaces all values of a tag, creating it if necessary
+	Set {
+		/// how to interpret the value
+		#[arg(short = 't', long = "type", value_enum, default_value = "auto")]
+		kind: ValueKind,
+		/// path to target file
+		file: PathBuf,
+		/// four-character key (e.g. `©nam`, `trkn`) or tag name (e.g. `title`)
+		key: String,
+		/// new value (a file path for images)
+		value: String,
+	},
+
+	/// appends a value to a tag (e.g. an additional cover image), creating it if necessary
+	Add {
+		/// how to interpret the value
+		#[arg(short = 't', long = "type", value_enum, default_value = "auto")]
+		kind: ValueKind,
+		/// path to target file
+		file: PathBuf,
+		/// four-character key (e.g. `©nam`, `covr`) or tag name (e.g. `title`)
+		key: String,
+		/// additional value (a file path for images)
+		value: String,
+	},
+
+	/// removes a tag and all of its values
+	Remove {
+		/// path to target file
+		file: PathBuf,
+		/// four-character key (e.g. `©nam`) or tag name (e.g. `title`)
+		key: String,
+	},
 }
 
 #[derive(Subcommand)]
 enum MangleCommand {
 	/// flips random bits in the given file
 	Flip {
-		/// Use percentage (0 to 100) of file size rather than an absolute number of bits (overrides count)
+		/// Use percentage (0 to 100) of the targeted bytes rather than an absolute number of bits (overrides count)
 		#[arg(short, long, group = "amount")]
 		percent: Option<f64>,
 		/// number of random bits to flip
 		#[arg(short, long, group = "amount")]
 		count: Option<u64>,
+		/// only change bytes inside boxes matching this selector (e.g. `moov`, `moov//stsz`, `mdat`)
+		#[arg(long, value_name = "SELECTOR")]
+		within: Option<Selector>,
+		/// whether box headers (size and type) may be changed
+		#[arg(long, value_enum, default_value_t = Headers::Include)]
+		headers: Headers,
+		/// seed for the random number generator, so the same corruption can be reproduced
+		#[arg(long)]
+		seed: Option<u64>,
+		/// write a JSON log of the seed, parameters and every changed offset with its original bytes
+		#[arg(long, value_name = "PATH")]
+		log: Option<PathBuf>,
+		/// write the mangled copy here instead of modifying FILE in place
+		#[arg(short, long, value_name = "PATH")]
+		output: Option<PathBuf>,
+		/// overwrite OUTPUT if it already exists
+		#[arg(long, requires = "output")]
+		force: bool,
 		/// path to target file
 		#[arg(requires = "amount")]
 		file: PathBuf,
@@ -247,7 +582,7 @@ enum MangleCommand {
 
 	/// sets a block of data to zeroes
 	Blank {
-		/// Use percentage (0 to 100) of file size rather than an absolute number of blocks (overrides count)
+		/// Use percentage (0 to 100) of the targeted blocks rather than an absolute number of blocks (overrides count)
 		#[arg(short, long, group = "amount")]
 		percent: Option<f64>,
 		/// number of random blocks to blank out
@@ -256,6 +591,24 @@ enum MangleCommand {
 		/// size of block in bytes
 		#[arg(short, long, default_value = "4096")]
 		block_size: u64,
+		/// only change bytes inside boxes matching this selector (e.g. `moov`, `moov//stsz`, `mdat`)
+		#[arg(long, value_name = "SELECTOR")]
+		within: Option<Selector>,
+		/// whether box headers (size and type) may be changed
+		#[arg(long, value_enum, default_value_t = Headers::Include)]
+		headers: Headers,
+		/// seed for the random number generator, so the same corruption can be reproduced
+		#[arg(long)]
+		seed: Option<u64>,
+		/// write a JSON log of the seed, parameters and every changed offset with its original bytes
+		#[arg(long, value_name = "PATH")]
+		log: Option<PathBuf>,
+		/// write the mangled copy here instead of modifying FILE in place
+		#[arg(short, long, value_name = "PATH")]
+		output: Option<PathBuf>,
+		/// overwrite OUTPUT if it already exists
+		#[arg(long, requires = "output")]
+		force: bool,
 		/// path to target file
 		#[arg(requires = "amount")]
 		file: PathBuf,
@@ -269,14 +622,77 @@ enum MangleCommand {
 		/// length of data to truncate in bytes
 		#[arg(short, long, group = "amount")]
 		bytes: Option<u64>,
+		/// write a JSON log of the parameters and the offset the file was cut at
+		#[arg(long, value_name = "PATH")]
+		log: Option<PathBuf>,
+		/// also save the truncated bytes in the log, so the truncation can be undone
+		#[arg(long, requires = "log")]
+		save_tail: bool,
+		/// write the mangled copy here instead of modifying FILE in place
+		#[arg(short, long, value_name = "PATH")]
+		output: Option<PathBuf>,
+		/// overwrite OUTPUT if it already exists
+		#[arg(long, requires = "output")]
+		force: bool,
 		/// path to target file
 		#[arg(requires = "amount")]
 		file: PathBuf,
-	}
+	},
+
+	/// sets a fixed range of data to zeroes, in each contiguous targeted range
+	ZeroRange {
+		/// number of bytes to skip at the start of each range
+		#[arg(long, default_value = "0")]
+		offset: u64,
+		/// number of bytes to zero (the rest of each range by default)
+		#[arg(short, long)]
+		length: Option<u64>,
+		/// only change bytes inside boxes matching this selector (e.g. `moov`, `moov//stsz`, `mdat`)
+		#[arg(long, value_name = "SELECTOR")]
+		within: Option<Selector>,
+		/// whether box headers (size and type) may be changed
+		#[arg(long, value_enum, default_value_t = Headers::Include)]
+		headers: Headers,
+		/// write a JSON log of the parameters and every changed offset with its original bytes
+		#[arg(long, value_name = "PATH")]
+		log: Option<PathBuf>,
+		/// write the mangled copy here instead of modifying FILE in place
+		#[arg(short, long, value_name = "PATH")]
+		output: Option<PathBuf>,
+		/// overwrite OUTPUT if it already exists
+		#[arg(long, requires = "output")]
+		force: bool,
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// applies the corruption recorded in a mangle log to another copy of the file
+	Replay {
+		/// mangle log written by `--log`
+		log: PathBuf,
+		/// write the mangled copy here instead of modifying FILE in place
+		#[arg(short, long, value_name = "PATH")]
+		output: Option<PathBuf>,
+		/// overwrite OUTPUT if it already exists
+		#[arg(long, requires = "output")]
+		force: bool,
+		/// path to target file
+		file: PathBuf,
+	},
+
+	/// restores a mangled file from the original bytes saved in its mangle log
+	Undo {
+		/// mangle log written by `--log` when the file was mangled
+		log: PathBuf,
+		/// path to the mangled file
+		file: PathBuf,
+	},
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>>{
-	let cli = Cli::parse();
+	// the raw matches are kept for argument positions, which lint needs to apply overrides in order
+	let matches = Cli::command().get_matches();
+	let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
 	let env = Env::default()
 		.filter("LOG_LEVEL")
@@ -291,35 +707,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>>{
 
 	match cli.command {
 		AppCommand::Inspect(inspect_command) => match inspect_command {
-			InspectCommand::IsPlayable { timeout_ms, file } => {
-				log::trace!("spawning mpv");
-				let mut mpv = Command::new("mpv").arg(file)
-					.stderr(if log::log_enabled!(log::Level::Debug) { Stdio::inherit() } else { Stdio::null() })
-					.stdout(if log::log_enabled!(log::Level::Trace) { Stdio::inherit() } else { Stdio::null() })
-					.spawn()?;
-
-				let start_time = Instant::now();
-				let poll_interval = Duration::from_millis(100.min(timeout_ms));
-				let timeout = Duration::from_millis(timeout_ms);
-
-				let is_playable = loop {
-					if let Some(exit_status) = mpv.try_wait()? {
-						log::trace!("mpv exited");
-						// mpv exited; reflect exit status
-						break exit_status.success();
-					}
+			InspectCommand::IsPlayable { backend, player, timeout_ms, json, file } => {
+				let timeout = timeout_ms.map(Duration::from_millis).unwrap_or_else(|| playability::default_timeout(&file));
+				let verdict = playability::backend(backend, player, timeout).test(&file)?;
+				playability::print_verdict(&verdict, json)?;
 
-					if start_time.elapsed() >= timeout {
-						log::trace!("killing mpv");
-						// video is playing; terminate the process
-						mpv.kill()?;
-						break true;
-					}
+				process::exit(if verdict.playable {
+					log::info!("file is playable");
+					0
+				} else {
+					log::info!("file is not playable");
+					1
+				});
+			},
 
-					thread::sleep(poll_interval);
-				};
+			InspectCommand::Check { file } => {
+				let result = check::check(&file)?;
+
+				for error in &result.errors {
+					println!("{}", error);
+				}
+
+				for (label, errors) in &result.tracks {
+					if errors.is_empty() {
+						println!("{}: ok", label);
+					} else {
+						println!("{}: FAIL", label);
+						for error in errors {
+							println!("  - {}", error);
+						}
+					}
+				}
 
-				process::exit(if is_playable {
+				process::exit(if result.is_playable() {
 					log::info!("file is playable");
 					0
 				} else {
@@ -328,17 +748,87 @@ fn main() -> Result<(), Box<dyn std::error::Error>>{
 				});
 			},
 
-			InspectCommand::Tree { file, paths, with_size } => {
-				let f = File::open(file)?;
-				let size = f.metadata()?.len();
-				let reader = io::BufReader::new(f);
+			InspectCommand::Lint { allow, warn, deny, list, file } => {
+				if list {
+					for rule in lint::RULES {
+						println!("{:<24} {:<8} {}", rule.id, rule.default, rule.description);
+					}
+					return Ok(());
+				}
 
-				if paths {
-					let mut visitor = PathVisitor::new(with_size);
-					read_box(reader, size, &mut visitor)?;
-				} else {
-					let mut visitor = PrintTreeVisitor::new(with_size);
-					read_box(reader, size, &mut visitor)?;
+				// later flags win, so `-D x -A x` allows x
+				let lint_matches = matches.subcommand_matches("inspect").and_then(|m| m.subcommand_matches("lint"));
+				let mut overrides = Vec::new();
+				for (arg, ids, level) in [("allow", allow, lint::Level::Allow), ("warn", warn, lint::Level::Warn), ("deny", deny, lint::Level::Deny)] {
+					let positions = lint_matches.and_then(|m| m.indices_of(arg)).into_iter().flatten();
+					overrides.extend(positions.zip(ids).map(|(position, id)| (position, id, level)));
+				}
+				overrides.sort_by_key(|(position, _, _)| *position);
+
+				let mut levels = lint::LintLevels::default();
+				for (_, id, level) in overrides {
+					levels.set(&id, level)?;
+				}
+
+				let findings = lint::lint(&file.unwrap(), &levels)?;
+				for finding in &findings {
+					println!("{}", finding);
+				}
+
+				let errors = findings.iter().filter(|f| f.level == lint::Level::Deny).count();
+				let warnings = findings.len() - errors;
+				println!("{} error{}, {} warning{}", errors, if errors == 1 { "" } else { "s" }, warnings, if warnings == 1 { "" } else { "s" });
+
+				process::exit(if errors == 0 { 0 } else { 1 });
+			},
+
+			InspectCommand::Coverage { limit, file } => coverage::coverage(&file, limit)?,
+
+			InspectCommand::Layout { file } => layout::layout(&file)?,
+
+			InspectCommand::Tags { json, export_cover, file } => {
+				let (index, _size) = index::index_file(&file, true)?;
+				let tags = tags::read_tags(&index);
+
+				tags::print_tags(&tags, json)?;
+				if let Some(path) = export_cover {
+					tags::export_covers(&tags, &path)?;
+				}
+			},
+
+			InspectCommand::Codec { track, file } => codec::codec(&file, track)?,
+
+			InspectCommand::Fragments { file } => fragment::fragments(&file)?,
+
+			InspectCommand::Gop { track, file } => gop::gop(&file, track)?,
+
+			InspectCommand::Fingerprint { db, add, show_layout, file } => fingerprint::fingerprint(&file, &db, add.as_deref(), show_layout)?,
+
+			InspectCommand::Provenance { json, file } => {
+				let (index, _size) = index::index_file(&file, true)?;
+				provenance::print_provenance(&provenance::provenance(&tags::read_tags(&index)), json)?;
+			},
+
+			InspectCommand::Samples { track, csv, file } => samples::samples(&file, track, csv)?,
+
+			InspectCommand::Segments { seek, file } => segment::segments(&file, seek)?,
+
+			InspectCommand::Timeline { file } => timeline::timeline(&file)?,
+
+			InspectCommand::Times { tolerance, file } => times::times(&file, tolerance)?,
+
+			InspectCommand::Tree { file, paths, with_size, offsets, max_depth, only, collapse, ascii, color } => {
+				let (index, _size, error) = index::index_file_partial(&file, false)?;
+
+				let color = match color {
+					ColorChoice::Always => true,
+					ColorChoice::Never => false,
+					ColorChoice::Auto => io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+				};
+
+				inspect::print_tree(&index, &TreeOptions { paths, with_size, offsets, max_depth, only, collapse, ascii, color });
+				if let Some(e) = error {
+					return Err(e.into());
 				}
 			},
 
@@ -350,6 +840,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>>{
 				let mp4 = Mp4Reader::read_header(reader, size)?;
 				println!("{:#?}", mp4);
 			},
+
+			InspectCommand::Damage { original, mangled, log } => match (mangled, log) {
+				(_, Some(log)) => damage::damage_from_log(&original, &log)?,
+				(Some(mangled), None) => damage::damage_map(&original, &mangled)?,
+				(None, None) => unreachable!("clap requires a mangled file or a log"),
+			},
 		},
 
 		AppCommand::Extract { box_type, input, output } => {
@@ -364,14 +860,63 @@ fn main() -> Result<(), Box<dyn std::error::Error>>{
 			read_box(reader, in_file_size, &mut visitor)?;
 		},
 
+		AppCommand::ExtractTrack { track, input, output } => extract_track::extract_track(&input, track, &output)?,
+
 		AppCommand::Mangle(mangle_command) => match mangle_command {
-			MangleCommand::Flip { percent, count, file } => mangle::flip_bits(&file, (percent.map(|p| p / 100.0), count).try_into()?)?,
-			MangleCommand::Blank { percent, count, block_size, file } => mangle::blank_blocks(&file, (percent.map(|p| p / 100.0), count).try_into()?, block_size)?,
-			MangleCommand::Truncate { percent, bytes, file } => mangle::truncate(&file, (percent.map(|p| p / 100.0), bytes).try_into()?)?,
+			MangleCommand::Flip { percent, count, within, headers, seed, log, output, force, file } => {
+				let amount = (percent.map(|p| p / 100.0), count).try_into()?;
+				// the output is an exact copy, so the region is found in the input before anything is written
+				let region = Region::select(&file, within.as_ref(), headers)?;
+				let target = mangle::target(&file, output.as_deref(), force)?;
+				mangle::flip_bits(target, amount, &region, seed, log.as_deref())?
+			},
+			MangleCommand::Blank { percent, count, block_size, within, headers, seed, log, output, force, file } => {
+				let amount = (percent.map(|p| p / 100.0), count).try_into()?;
+				// checked before the copy, like the truncation amount
+				mangle::check_block_size(block_size)?;
+				let region = Region::select(&file, within.as_ref(), headers)?;
+				let target = mangle::target(&file, output.as_deref(), force)?;
+				mangle::blank_blocks(target, amount, block_size, &region, seed, log.as_deref())?
+			},
+			MangleCommand::ZeroRange { offset, length, within, headers, log, output, force, file } => {
+				let region = Region::select(&file, within.as_ref(), headers)?;
+				let target = mangle::target(&file, output.as_deref(), force)?;
+				mangle::zero_range(target, offset, length, &region, log.as_deref())?
+			},
+			MangleCommand::Truncate { percent, bytes, log, save_tail, output, force, file } => {
+				let amount = (percent.map(|p| p / 100.0), bytes).try_into()?;
+				// checked against the input so an impossible amount leaves no copy behind
+				mangle::truncated_bytes(amount, fs::metadata(&file)?.len())?;
+				let target = mangle::target(&file, output.as_deref(), force)?;
+				mangle::truncate(target, amount, log.as_deref(), save_tail)?
+			},
+			MangleCommand::Replay { log, output, force, file } => mangle::replay(&log, mangle::target(&file, output.as_deref(), force)?)?,
+			MangleCommand::Undo { log, file } => mangle::undo(&log, &file)?,
 		}
 
 		AppCommand::Strip { ignore, input, output } => strip::strip(&input, &output, ignore.into_iter().map(|x| x.into()).collect())?,
 
+		AppCommand::Survey { extensions, output, dir } => {
+			if let Some(output) = output {
+				let mut writer = io::BufWriter::new(File::create(output)?);
+				survey::survey(&dir, &extensions, &mut writer)?;
+			} else {
+				survey::survey(&dir, &extensions, &mut io::stdout().lock())?;
+			}
+		},
+
+		AppCommand::Tags(tags_command) => match tags_command {
+			TagsCommand::Set { kind, file, key, value } => {
+				let key = retag::parse_key(&key)?;
+				retag::edit_tags(&file, &TagEdit::Set(key, retag::parse_value(key, &value, kind)?))?;
+			},
+			TagsCommand::Add { kind, file, key, value } => {
+				let key = retag::parse_key(&key)?;
+				retag::edit_tags(&file, &TagEdit::Add(key, retag::parse_value(key, &value, kind)?))?;
+			},
+			TagsCommand::Remove { file, key } => retag::edit_tags(&file, &TagEdit::Remove(retag::parse_key(&key)?))?,
+		},
+
 		AppCommand::MoovTransplant { input_moov, input_subject, output } => {
 			let moov_file = File::open(input_moov)?;
 			let moov_file_size = moov_file.metadata()?.len();
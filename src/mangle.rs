@@ -1,11 +1,21 @@
+use std::collections::HashSet;
 use std::fmt;
-use std::fs::OpenOptions;
-use std::io;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::prelude::FileExt;
 use std::path::Path;
 
-use rand::{self, distributions::Uniform, prelude::Distribution};
+use rand::{self, distributions::Uniform, prelude::Distribution, rngs::StdRng, SeedableRng};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
+use crate::fingerprint::{fnv1a, FNV1A_BASIS};
+use crate::index::index_file;
+use crate::selector::Selector;
+
+#[derive(Clone, Copy)]
 pub enum Amount {
 	Percent(f64),
 	Count(u64),
@@ -32,7 +42,7 @@ impl TryFrom<(Option<f64>, Option<u64>)> for Amount {
 	type Error = AmountError;
 	fn try_from(value: (Option<f64>, Option<u64>)) -> Result<Self, Self::Error> {
 		match value {
-			(Some(pct), _) => if pct < 0.0 || pct > 1.0 { Err(AmountError::PercentRange) } else { Ok(Self::Percent(pct)) },
+			(Some(pct), _) => if !(0.0..=1.0).contains(&pct) { Err(AmountError::PercentRange) } else { Ok(Self::Percent(pct)) },
 			(_, Some(ct)) => Ok(Self::Count(ct)),
 			_ => Err(AmountError::MissingValue),
 		}
@@ -44,100 +54,674 @@ impl TryFrom<(Option<u64>, Option<f64>)> for Amount {
 	fn try_from(value: (Option<u64>, Option<f64>)) -> Result<Self, Self::Error> {
 		match value {
 			(Some(ct), _) => Ok(Self::Count(ct)),
-			(_, Some(pct)) => if pct < 0.0 || pct > 1.0 { Err(AmountError::PercentRange) } else { Ok(Self::Percent(pct)) },
+			(_, Some(pct)) => if !(0.0..=1.0).contains(&pct) { Err(AmountError::PercentRange) } else { Ok(Self::Percent(pct)) },
 			_ => Err(AmountError::MissingValue),
 		}
 	}
 }
 
-/// flips random bits in the given file
-pub fn flip_bits(file: &Path, bits_amount: Amount) -> io::Result<()> {
+/// The corruption applied by one mangle run, detailed enough to replay it on another copy
+#[derive(Serialize, Deserialize)]
+pub struct MangleLog {
+	pub seed: u64,
+	#[serde(flatten)]
+	pub mode: Mode,
+	/// size of the file before it was mangled
+	pub file_size: u64,
+	/// 64-bit FNV-1a hash of the file before it was mangled
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub original_hash: Option<String>,
+	/// selector the changes were restricted to
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub within: Option<String>,
+	#[serde(default)]
+	pub headers: Headers,
+	/// every changed range, in the order the changes were made
+	pub touches: Vec<Touch>,
+	/// whether the run keeps its touches; without a log to write they are not held in memory
+	#[serde(skip)]
+	keep: bool,
+}
+
+/// A mangle mode with its resolved parameters
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum Mode {
+	Flip { bits: u64 },
+	Blank { blocks: u64, block_size: u64 },
+	Truncate { bytes: u64 },
+	#[serde(rename = "zero-range")]
+	ZeroRange { offset: u64, length: Option<u64> },
+}
+
+/// Whether box headers (size and type fields) may be hit
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Headers {
+	/// headers and payloads alike
+	#[default]
+	Include,
+	/// payloads only
+	Exclude,
+	/// headers only
+	Only,
+}
+
+/// The bytes a mangle may change, as sorted, non-overlapping ranges
+pub struct Region {
+	ranges: Vec<Range<u64>>,
+	/// selected bytes before each range, followed by the total
+	before: Vec<u64>,
+	within: Option<String>,
+	headers: Headers,
+}
+
+/// The selected ranges cut into blocks of a fixed size, the last block of each range possibly shorter
+struct Blocks<'a> {
+	ranges: &'a [Range<u64>],
+	block_size: u64,
+	/// blocks before each range, followed by the total
+	before: Vec<u64>,
+}
+
+impl Blocks<'_> {
+	fn len(&self) -> u64 {
+		self.before.last().copied().unwrap_or(0)
+	}
+
+	/// byte range of the `n`th block
+	fn get(&self, n: u64) -> Range<u64> {
+		let i = self.before.partition_point(|&before| before <= n) - 1;
+		let range = &self.ranges[i];
+		let start = range.start + (n - self.before[i]) * self.block_size;
+		start..start.saturating_add(self.block_size).min(range.end)
+	}
+}
+
+/// running totals of `sizes`, starting from 0
+fn running_totals(sizes: impl Iterator<Item = u64>) -> Vec<u64> {
+	std::iter::once(0).chain(sizes.scan(0, |total, size| {
+		*total += size;
+		Some(*total)
+	})).collect()
+}
+
+impl Region {
+	/// the bytes inside boxes matching `within` (the whole file if `None`), filtered by whether they belong to box headers
+	pub fn select(file: &Path, within: Option<&Selector>, headers: Headers) -> io::Result<Self> {
+		let description = within.map(|w| w.to_string());
+		let ranges = if within.is_none() && headers == Headers::Include {
+			let whole_file = 0..fs::metadata(file)?.len();
+			vec![whole_file]
+		} else {
+			Self::box_ranges(file, within, headers)?
+		};
+
+		let before = running_totals(ranges.iter().map(|r| r.end - r.start));
+		let region = Self { ranges, before, within: description, headers };
+		if region.len() == 0 {
+			return Err(io::Error::new(ErrorKind::InvalidInput, format!("no bytes of {} are selected", file.display())));
+		}
+		if region.within.is_some() || headers != Headers::Include {
+			log::info!("targeting {} B in {} range{}", region.len(), region.ranges.len(), if region.ranges.len() == 1 { "" } else { "s" });
+		}
+		Ok(region)
+	}
+
+	/// merged byte ranges of the matching boxes, with or without their headers
+	fn box_ranges(file: &Path, within: Option<&Selector>, headers: Headers) -> io::Result<Vec<Range<u64>>> {
+		let (index, file_size) = index_file(file, false)?;
+		// boxes are pre-ordered, so every parent is resolved before its children
+		let mut inside = vec![false; index.boxes.len()];
+		for i in 0..index.boxes.len() {
+			inside[i] = within.is_none_or(|w| w.matches(&index, i)) || index.boxes[i].parent.is_some_and(|p| inside[p]);
+		}
+
+		let mut ranges: Vec<Range<u64>> = Vec::new();
+		for segment in index.segments(file_size) {
+			let selected = match segment.owner {
+				Some(owner) => inside[owner],
+				None => within.is_none(),
+			};
+			let kept = match headers {
+				Headers::Include => true,
+				Headers::Exclude => !segment.is_header,
+				Headers::Only => segment.is_header,
+			};
+			if !selected || !kept || segment.start >= segment.end {
+				continue;
+			}
+			match ranges.last_mut() {
+				Some(last) if last.end == segment.start => last.end = segment.end,
+				_ => ranges.push(segment.start..segment.end),
+			}
+		}
+
+		Ok(ranges)
+	}
+
+	fn len(&self) -> u64 {
+		self.before.last().copied().unwrap_or(0)
+	}
+
+	/// file offset of the `n`th selected byte
+	fn offset(&self, n: u64) -> u64 {
+		assert!(n < self.len(), "byte {} lies beyond the selected region", n);
+		let i = self.before.partition_point(|&before| before <= n) - 1;
+		self.ranges[i].start + (n - self.before[i])
+	}
+
+	/// the selected ranges cut into blocks of `block_size`; only the number of blocks per range is kept, so a small
+	/// block size over a large file costs no memory
+	fn blocks(&self, block_size: u64) -> Blocks<'_> {
+		let before = running_totals(self.ranges.iter().map(|r| (r.end - r.start).div_ceil(block_size)));
+		Blocks { ranges: &self.ranges, block_size, before }
+	}
+}
+
+/// A range changed by a mangle, with the bytes it held beforehand
+#[derive(Serialize, Deserialize)]
+pub struct Touch {
+	pub offset: u64,
+	/// index of the flipped bit, counting from the least significant
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub bit: Option<u8>,
+	/// bytes the range held before this change; empty for a truncation whose tail was not saved
+	#[serde(with = "hex")]
+	pub original: Vec<u8>,
+}
+
+/// (de)serializes bytes as a lowercase hex string
+mod hex {
+	use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+		let s = String::deserialize(deserializer)?;
+		if !s.is_ascii() || s.len() % 2 != 0 {
+			return Err(D::Error::custom("expected an even number of hex digits"));
+		}
+		(0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(D::Error::custom)).collect()
+	}
+}
+
+impl MangleLog {
+	/// starts a log for a run on `f`, hashing the untouched file and keeping the touches only if the log will be written
+	fn new(seed: u64, mode: Mode, region: Option<&Region>, f: &File, file_size: u64, keep: bool) -> io::Result<Self> {
+		let original_hash = if keep { Some(file_hash(f)?) } else { None };
+		Ok(Self {
+			seed,
+			mode,
+			file_size,
+			original_hash,
+			within: region.and_then(|r| r.within.clone()),
+			headers: region.map(|r| r.headers).unwrap_or_default(),
+			touches: Vec::new(),
+			keep,
+		})
+	}
+
+	/// applies one change to the file, recording the bytes it overwrites or removes if the log is kept
+	fn touch(&mut self, f: &File, offset: u64, len: u64, bit: Option<u8>) -> io::Result<()> {
+		let mut original = vec![0; len as usize];
+		f.read_exact_at(&mut original, offset)?;
+		let touch = Touch { offset, bit, original };
+		apply(f, &self.mode, &touch)?;
+		if self.keep {
+			self.touches.push(touch);
+		}
+		Ok(())
+	}
+
+	fn write(&self, log: &Path) -> io::Result<()> {
+		fs::write(log, serde_json::to_string_pretty(self)?)?;
+		log::info!("wrote mangle log to {}", log.display());
+		Ok(())
+	}
+}
+
+/// makes one logged change: flips the touch's bit, zeroes its range or cuts the file at its offset
+fn apply(f: &File, mode: &Mode, touch: &Touch) -> io::Result<()> {
+	match mode {
+		Mode::Flip { .. } => {
+			let bit = touch.bit.filter(|&b| b < 8).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "bit flip without a valid bit index"))?;
+			let mut buf: [u8; 1] = Default::default();
+			f.read_exact_at(&mut buf, touch.offset)?;
+			buf[0] ^= 1 << bit;
+			f.write_all_at(&buf, touch.offset)
+		},
+		Mode::Blank { .. } | Mode::ZeroRange { .. } => f.write_all_at(&vec![0; touch.original.len()], touch.offset),
+		Mode::Truncate { .. } => f.set_len(touch.offset),
+	}
+}
+
+/// reverts one logged change: flips the touch's bit back or writes its original bytes back in place
+fn revert(f: &File, mode: &Mode, touch: &Touch) -> io::Result<()> {
+	match mode {
+		Mode::Flip { .. } => apply(f, mode, touch),
+		Mode::Blank { .. } | Mode::ZeroRange { .. } | Mode::Truncate { .. } => f.write_all_at(&touch.original, touch.offset),
+	}
+}
+
+/// 64-bit FNV-1a hash of the whole file
+pub fn file_hash(f: &File) -> io::Result<String> {
+	let mut hash = FNV1A_BASIS;
+	let mut buf = vec![0; 1 << 16];
+	let mut offset = 0;
+	loop {
+		let n = f.read_at(&mut buf, offset)?;
+		if n == 0 {
+			break;
+		}
+		hash = fnv1a(hash, &buf[..n]);
+		offset += n as u64;
+	}
+	Ok(format!("{:016x}", hash))
+}
+
+/// seeds a generator with the given seed, or a random one that is logged so the run can be repeated
+fn seeded_rng(seed: Option<u64>) -> (u64, StdRng) {
+	let seed = seed.unwrap_or_else(rand::random);
+	log::info!("using seed {}", seed);
+	(seed, StdRng::seed_from_u64(seed))
+}
+
+/// the file a mangle should modify: `file` itself, or a fresh copy of it at `output`, which must not exist unless `force` is set
+pub fn target<'a>(file: &'a Path, output: Option<&'a Path>, force: bool) -> io::Result<&'a Path> {
+	let Some(output) = output else { return Ok(file) };
+
+	// compare inodes rather than paths so hard links, symlinks and `./` prefixes are caught too
+	let input_meta = fs::metadata(file)?;
+	if let Ok(output_meta) = fs::metadata(output) {
+		if output_meta.dev() == input_meta.dev() && output_meta.ino() == input_meta.ino() {
+			return Err(io::Error::new(ErrorKind::InvalidInput,
+				format!("refusing to mangle: output {} is the input file {}", output.display(), file.display())));
+		}
+	}
+	if !force && fs::symlink_metadata(output).is_ok() {
+		return Err(io::Error::new(ErrorKind::AlreadyExists,
+			format!("refusing to overwrite {}; pass --force to replace it", output.display())));
+	}
+
+	// a reflink shares the input's extents outright; failing that, holes are kept and only data is written
+	match reflink(file, output) {
+		Ok(()) => log::debug!("cloned {} to {}", file.display(), output.display()),
+		Err(e) => {
+			log::debug!("reflink to {} failed ({}), falling back to a sparse copy", output.display(), e);
+			if let Err(e) = sparse_copy(file, output) {
+				log::debug!("sparse copy to {} failed ({}), falling back to a plain copy", output.display(), e);
+				fs::copy(file, output)?;
+			}
+		},
+	}
+	log::info!("copied {} to {} ({} B)", file.display(), output.display(), input_meta.len());
+
+	Ok(output)
+}
+
+/// clones `input` into `output` with the FICLONE ioctl, which only filesystems with reflinks (btrfs, XFS, ...) support
+#[cfg(target_os = "linux")]
+fn reflink(input: &Path, output: &Path) -> io::Result<()> {
+	use std::os::fd::AsRawFd;
+
+	let reader = File::open(input)?;
+	let writer = File::create(output)?;
+	// SAFETY: both descriptors are open for the duration of the call, and FICLONE takes the source descriptor by value
+	if unsafe { libc::ioctl(writer.as_raw_fd(), libc::FICLONE as _, reader.as_raw_fd()) } == -1 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_input: &Path, _output: &Path) -> io::Result<()> {
+	Err(io::Error::new(ErrorKind::Unsupported, "reflinks are only cloned on Linux"))
+}
+
+/// copies `input` to `output` chunk by chunk, seeking over all-zero chunks so they stay holes
+fn sparse_copy(input: &Path, output: &Path) -> io::Result<()> {
+	let mut reader = File::open(input)?;
+	let mut writer = File::create(output)?;
+	let mut buf = vec![0; 1 << 16];
+	let mut len = 0;
+	loop {
+		let n = reader.read(&mut buf)?;
+		if n == 0 {
+			break;
+		}
+		if buf[..n].iter().all(|&b| b == 0) {
+			writer.seek(SeekFrom::Current(n as i64))?;
+		} else {
+			writer.write_all(&buf[..n])?;
+		}
+		len += n as u64;
+	}
+	writer.set_len(len)
+}
+
+fn open(file: &Path) -> io::Result<(File, u64)> {
 	log::info!("opening file {}", file.to_str().unwrap_or("???"));
 	let f = OpenOptions::new().read(true).write(true).open(file)?;
 	let file_size_bytes = f.metadata()?.len();
+	Ok((f, file_size_bytes))
+}
+
+/// flips random bits in the given file
+pub fn flip_bits(file: &Path, bits_amount: Amount, region: &Region, seed: Option<u64>, log: Option<&Path>) -> io::Result<()> {
+	let (f, file_size_bytes) = open(file)?;
 
-	let mut rng = rand::thread_rng();
-	let size_dist = Uniform::new(0, file_size_bytes);
+	let (seed, mut rng) = seeded_rng(seed);
+	let size_dist = Uniform::new(0, region.len());
 	let bit_dist = Uniform::new(0, 8);
 
 	let num_bits = match bits_amount {
 		Amount::Count(x) => x,
-		Amount::Percent(pct) => (pct * file_size_bytes as f64 * 8.0).ceil() as u64,
+		Amount::Percent(pct) => (pct * region.len() as f64 * 8.0).ceil() as u64,
 	};
+	let mut record = MangleLog::new(seed, Mode::Flip { bits: num_bits }, Some(region), &f, file_size_bytes, log.is_some())?;
 
 	for _ in 0..num_bits {
-		// read byte at random byte offset
-		let byte_offset = size_dist.sample(&mut rng);
-		let mut buf: [u8; 1] = Default::default();
-		f.read_exact_at(&mut buf, byte_offset)?;
-
-		let orig = buf[0];
-
-		// flip bit at random bit offset
+		let byte_offset = region.offset(size_dist.sample(&mut rng));
 		let bit_offset = bit_dist.sample(&mut rng);
-		buf[0] ^= 1 << bit_offset;
-		log::debug!("Flipped bit {} at offset 0x{:x}: 0x{:02x} => 0x{:02x}", 8 - bit_offset, byte_offset, orig, buf[0]);
+		record.touch(&f, byte_offset, 1, Some(bit_offset))?;
 
-		// write byte back to file
-		f.write_at(&buf, byte_offset)?;
+		if log::log_enabled!(log::Level::Debug) {
+			let mut flipped = [0u8; 1];
+			f.read_exact_at(&mut flipped, byte_offset)?;
+			let orig = flipped[0] ^ (1 << bit_offset);
+			log::debug!("Flipped bit {} at offset 0x{:x}: 0x{:02x} => 0x{:02x}", 8 - bit_offset, byte_offset, orig, flipped[0]);
+		}
 	}
 
 	log::info!("Flipped {} bit{}", num_bits, if num_bits == 1 { "" } else { "s" });
 
+	match log {
+		Some(log) => record.write(log),
+		None => Ok(()),
+	}
+}
+
+/// rejects a block size that could not blank anything
+pub fn check_block_size(block_size_bytes: u64) -> io::Result<()> {
+	if block_size_bytes == 0 {
+		return Err(io::Error::new(ErrorKind::InvalidInput, "block size must be at least 1 byte"));
+	}
 	Ok(())
 }
 
 /// zeroes random contiguous blocks of data
-pub fn blank_blocks(file: &Path, blocks_amount: Amount, block_size_bytes: u64) -> io::Result<()> {
-	log::info!("opening file {}", file.to_str().unwrap_or("???"));
-	let f = OpenOptions::new().read(true).write(true).open(file)?;
-	let file_size_bytes = f.metadata()?.len();
+pub fn blank_blocks(file: &Path, blocks_amount: Amount, block_size_bytes: u64, region: &Region, seed: Option<u64>, log: Option<&Path>) -> io::Result<()> {
+	let (f, file_size_bytes) = open(file)?;
 
-	let bad_block = vec![0; block_size_bytes as usize];
-	let file_size_blocks = file_size_bytes / block_size_bytes;
+	check_block_size(block_size_bytes)?;
+	// blocks never straddle two selected ranges, so the last block of each range may be short
+	let blocks = region.blocks(block_size_bytes);
 
-	let mut rng = rand::thread_rng();
-	let block_dist = Uniform::new(0, file_size_blocks);
+	let (seed, mut rng) = seeded_rng(seed);
+	let block_dist = Uniform::new(0, blocks.len());
 
 	let num_blocks = match blocks_amount {
 		Amount::Count(x) => x,
-		Amount::Percent(pct) => (pct * file_size_blocks as f64).ceil() as u64,
+		Amount::Percent(pct) => (pct * blocks.len() as f64).ceil() as u64,
 	};
+	let mut record = MangleLog::new(seed, Mode::Blank { blocks: num_blocks, block_size: block_size_bytes }, Some(region), &f, file_size_bytes, log.is_some())?;
 
 	for _ in 0..num_blocks {
-		let block_offset = block_dist.sample(&mut rng);
-
-		let end = if block_offset == file_size_blocks - 1 {
-			// don't go beyond end of last block
-			file_size_bytes % block_size_bytes
-		} else {
-			block_size_bytes
-		} as usize;
+		let block = blocks.get(block_dist.sample(&mut rng));
 
-		log::debug!("Blanked block {} ({} bytes starting at offset 0x{:x})", block_offset, block_size_bytes, block_offset * block_size_bytes);
+		log::debug!("Blanked {} bytes starting at offset 0x{:x}", block.end - block.start, block.start);
 
-		f.write_at(&bad_block[0..end], block_offset * block_size_bytes)?;
+		record.touch(&f, block.start, block.end - block.start, None)?;
 	}
 
 	log::info!("Blanked {} block{}", num_blocks, if num_blocks == 1 { "" } else { "s" });
 
-	Ok(())
+	match log {
+		Some(log) => record.write(log),
+		None => Ok(()),
+	}
 }
 
-/// truncates the given number of bytes from the end of the file.
-pub fn truncate(file: &Path, bytes_amount: Amount) -> io::Result<()> {
-	log::info!("opening file {}", file.to_str().unwrap_or("???"));
-	let f = OpenOptions::new().read(true).write(true).open(file)?;
-	let file_size_bytes = f.metadata()?.len();
-
-	let num_bytes = match bytes_amount {
+/// the number of bytes `amount` cuts from a file of `file_size` bytes, which cannot exceed the file
+pub fn truncated_bytes(amount: Amount, file_size: u64) -> io::Result<u64> {
+	let num_bytes = match amount {
 		Amount::Count(x) => x,
-		Amount::Percent(pct) => (pct * file_size_bytes as f64).ceil() as u64,
+		Amount::Percent(pct) => (pct * file_size as f64).ceil() as u64,
 	};
+	if num_bytes > file_size {
+		return Err(io::Error::new(ErrorKind::InvalidInput, format!("cannot truncate {} B from a {} B file", num_bytes, file_size)));
+	}
+	Ok(num_bytes)
+}
 
-	f.set_len(file_size_bytes - num_bytes)?;
+/// truncates the given number of bytes from the end of the file, saving them in the log only if `save_tail` is set
+pub fn truncate(file: &Path, bytes_amount: Amount, log: Option<&Path>, save_tail: bool) -> io::Result<()> {
+	let (f, file_size_bytes) = open(file)?;
+	let num_bytes = truncated_bytes(bytes_amount, file_size_bytes)?;
+
+	// nothing is drawn at random, so the seed is recorded as 0
+	let mut record = MangleLog::new(0, Mode::Truncate { bytes: num_bytes }, None, &f, file_size_bytes, log.is_some())?;
+	let cut = file_size_bytes - num_bytes;
+	if save_tail {
+		record.touch(&f, cut, num_bytes, None)?;
+	} else {
+		// the tail can be as large as the file, so by default only where it was cut is logged
+		let touch = Touch { offset: cut, bit: None, original: Vec::new() };
+		apply(&f, &record.mode, &touch)?;
+		if record.keep {
+			record.touches.push(touch);
+		}
+	}
 
 	log::info!("Truncated {} byte{}", num_bytes, if num_bytes == 1 { "" } else { "s" });
 
+	match log {
+		Some(log) => record.write(log),
+		None => Ok(()),
+	}
+}
+
+/// zeroes `length` bytes (or everything) from `offset` onwards in each contiguous selected range
+pub fn zero_range(file: &Path, offset: u64, length: Option<u64>, region: &Region, log: Option<&Path>) -> io::Result<()> {
+	let (f, file_size_bytes) = open(file)?;
+
+	// nothing is drawn at random, so the seed is recorded as 0
+	let mut record = MangleLog::new(0, Mode::ZeroRange { offset, length }, Some(region), &f, file_size_bytes, log.is_some())?;
+	let (mut zeroed, mut ranges) = (0, 0);
+	for range in &region.ranges {
+		let start = range.start.saturating_add(offset).min(range.end);
+		let end = length.map_or(range.end, |l| start.saturating_add(l).min(range.end));
+		if start < end {
+			log::debug!("Zeroed {} bytes starting at offset 0x{:x}", end - start, start);
+			record.touch(&f, start, end - start, None)?;
+			zeroed += end - start;
+			ranges += 1;
+		}
+	}
+
+	log::info!("Zeroed {} byte{} in {} range{}", zeroed, if zeroed == 1 { "" } else { "s" }, ranges, if ranges == 1 { "" } else { "s" });
+
+	match log {
+		Some(log) => record.write(log),
+		None => Ok(()),
+	}
+}
+
+pub fn read_log(log: &Path) -> io::Result<MangleLog> {
+	let data = fs::read(log)?;
+	serde_json::from_slice(&data).map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", log.display(), e)))
+}
+
+/// applies the corruption recorded in a mangle log to another copy of the file
+pub fn replay(log: &Path, file: &Path) -> io::Result<()> {
+	let record = read_log(log)?;
+	let (f, file_size_bytes) = open(file)?;
+	if file_size_bytes != record.file_size {
+		log::warn!("{} is {} B but the logged file was {} B", file.display(), file_size_bytes, record.file_size);
+	}
+
+	let mut mismatched = 0;
+	for touch in &record.touches {
+		let len = touch.original.len() as u64;
+		let size = f.metadata()?.len();
+		if touch.offset + len > size {
+			return Err(io::Error::new(ErrorKind::InvalidInput,
+				format!("logged change at offset 0x{:x} ({} B) lies beyond the end of the {} B file", touch.offset, len, size)));
+		}
+
+		let mut current = vec![0; touch.original.len()];
+		f.read_exact_at(&mut current, touch.offset)?;
+		if current != touch.original {
+			mismatched += 1;
+		}
+		apply(&f, &record.mode, touch)?;
+	}
+
+	if mismatched > 0 {
+		log::warn!("{} of {} logged ranges held different bytes than the logged file; {} may not be a copy of it",
+			mismatched, record.touches.len(), file.display());
+	}
+	log::info!("Replayed {} change{} from {}", record.touches.len(), if record.touches.len() == 1 { "" } else { "s" }, log.display());
+
 	Ok(())
 }
+
+/// restores a mangled file to its original state from the bytes saved in its mangle log, then checks it against the logged hash
+pub fn undo(log: &Path, file: &Path) -> io::Result<()> {
+	let record = read_log(log)?;
+	let (f, file_size_bytes) = open(file)?;
+
+	// refuse before writing anything if the file cannot be the one the log describes
+	let expected_size = match record.mode {
+		Mode::Truncate { bytes } => {
+			if record.touches.iter().map(|t| t.original.len() as u64).sum::<u64>() != bytes {
+				return Err(io::Error::new(ErrorKind::InvalidData,
+					format!("{} does not hold the {} B truncated tail; only runs with --save-tail can be undone", log.display(), bytes)));
+			}
+			record.file_size.saturating_sub(bytes)
+		},
+		Mode::Flip { .. } | Mode::Blank { .. } | Mode::ZeroRange { .. } => record.file_size,
+	};
+	if file_size_bytes != expected_size {
+		return Err(io::Error::new(ErrorKind::InvalidInput,
+			format!("{} is {} B but the mangled file would be {} B", file.display(), file_size_bytes, expected_size)));
+	}
+
+	// every logged byte must still hold what the mangle left there, or reverting would corrupt the file instead
+	let not_mangled = |offset: u64, found: u8, expected: u8| io::Error::new(ErrorKind::InvalidInput,
+		format!("byte at offset 0x{:x} of {} is 0x{:02x}, not the 0x{:02x} the mangle left; was it already restored?", offset, file.display(), found, expected));
+	match record.mode {
+		Mode::Flip { .. } => {
+			// a byte flipped more than once is left as its newest flip made it
+			let mut checked = HashSet::new();
+			let mut byte = [0u8; 1];
+			for touch in record.touches.iter().rev() {
+				let (Some(bit), &[original]) = (touch.bit, touch.original.as_slice()) else {
+					return Err(io::Error::new(ErrorKind::InvalidData,
+						format!("{}: bit flip at offset 0x{:x} needs a bit index and exactly one original byte", log.display(), touch.offset)));
+				};
+				if checked.insert(touch.offset) {
+					f.read_exact_at(&mut byte, touch.offset)?;
+					let expected = original ^ (1 << (bit & 7));
+					if byte[0] != expected {
+						return Err(not_mangled(touch.offset, byte[0], expected));
+					}
+				}
+			}
+		},
+		// zeroed ranges are read back a chunk at a time, since they can span most of the file
+		Mode::Blank { .. } | Mode::ZeroRange { .. } => {
+			let mut buf = vec![0u8; 1 << 16];
+			for touch in &record.touches {
+				let end = touch.offset.saturating_add(touch.original.len() as u64);
+				let mut offset = touch.offset;
+				while offset < end {
+					let chunk = &mut buf[..(end - offset).min(1 << 16) as usize];
+					f.read_exact_at(chunk, offset)?;
+					if let Some(i) = chunk.iter().position(|&b| b != 0) {
+						return Err(not_mangled(offset + i as u64, chunk[i], 0));
+					}
+					offset += chunk.len() as u64;
+				}
+			}
+		},
+		Mode::Truncate { .. } => {},
+	}
+
+	// later changes may overwrite earlier ones, so they are reverted newest first
+	for touch in record.touches.iter().rev() {
+		revert(&f, &record.mode, touch)?;
+	}
+	log::info!("Reverted {} change{} from {}", record.touches.len(), if record.touches.len() == 1 { "" } else { "s" }, log.display());
+
+	match &record.original_hash {
+		Some(expected) => {
+			let hash = file_hash(&f)?;
+			if &hash != expected {
+				return Err(io::Error::new(ErrorKind::InvalidData,
+					format!("restored file hashes to {} but the original hashed to {}", hash, expected)));
+			}
+			log::info!("restored file matches the original (FNV-1a {})", hash);
+		},
+		None => log::warn!("{} has no original hash; the restored file cannot be verified", log.display()),
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn mp4_box(typ: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+		[&(payload.len() as u32 + 8).to_be_bytes()[..], typ, payload].concat()
+	}
+
+	/// selects from a file holding `contents`, removing it afterwards, as (start, end) pairs
+	fn select(name: &str, contents: &[u8], within: Option<&str>, headers: Headers) -> io::Result<Vec<(u64, u64)>> {
+		let path = std::env::temp_dir().join(format!("mp4-mangle-test-{}-{}.mp4", std::process::id(), name));
+		fs::write(&path, contents).unwrap();
+		let within: Option<Selector> = within.map(|w| w.parse().unwrap());
+		let region = Region::select(&path, within.as_ref(), headers);
+		fs::remove_file(&path).unwrap();
+		region.map(|region| region.ranges.iter().map(|r| (r.start, r.end)).collect())
+	}
+
+	#[test]
+	fn empty_region_is_rejected() {
+		assert_eq!(select("empty", &[], None, Headers::Include).unwrap_err().kind(), ErrorKind::InvalidInput);
+	}
+
+	#[test]
+	fn headers_are_excluded_or_selected_alone() {
+		// ftyp at 0..16, moov at 16..36 holding free at 24..36, mdat at 36..48
+		let file = [mp4_box(b"ftyp", b"isom\0\0\0\0"), mp4_box(b"moov", &mp4_box(b"free", b"abcd")), mp4_box(b"mdat", b"data")].concat();
+
+		assert_eq!(select("all", &file, None, Headers::Include).unwrap(), [(0, 48)]);
+		assert_eq!(select("exclude", &file, None, Headers::Exclude).unwrap(), [(8, 16), (32, 36), (44, 48)]);
+		assert_eq!(select("only", &file, None, Headers::Only).unwrap(), [(0, 8), (16, 32), (36, 44)]);
+		assert_eq!(select("moov", &file, Some("moov"), Headers::Include).unwrap(), [(16, 36)]);
+		assert_eq!(select("moov-exclude", &file, Some("moov"), Headers::Exclude).unwrap(), [(32, 36)]);
+		assert_eq!(select("mdat-only", &file, Some("mdat"), Headers::Only).unwrap(), [(36, 44)]);
+		assert_eq!(select("free-only", &file, Some("moov//free"), Headers::Only).unwrap(), [(24, 32)]);
+	}
+
+	#[test]
+	fn last_block_of_each_range_may_be_short() {
+		let ranges = vec![0..10, 20..25, 30..34];
+		let before = running_totals(ranges.iter().map(|r| r.end - r.start));
+		let region = Region { ranges, before, within: None, headers: Headers::Include };
+		assert_eq!(region.len(), 19);
+		assert_eq!(region.offset(10), 20);
+
+		let blocks = region.blocks(4);
+		assert_eq!(blocks.len(), 6);
+		assert_eq!((0..blocks.len()).map(|n| blocks.get(n)).collect::<Vec<_>>(), [0..4, 4..8, 8..10, 20..24, 24..25, 30..34]);
+
+		let blocks = region.blocks(u64::MAX);
+		assert_eq!((0..blocks.len()).map(|n| blocks.get(n)).collect::<Vec<_>>(), [0..10, 20..25, 30..34]);
+	}
+}
@@ -4,6 +4,7 @@ use std::fmt;
 macro_rules! boxtype {
 	($( $name:ident => $value:expr ),*) => {
 		#[derive(Clone, Copy, PartialEq, Eq)]
+		#[allow(clippy::enum_variant_names)]
 		pub enum BoxType {
 			$( $name, )*
 			UnknownBox(u32),
@@ -13,7 +14,7 @@ macro_rules! boxtype {
 			pub fn validate(self) -> bool {
 				if let BoxType::UnknownBox(t) = self {
 					for byte in t.to_ne_bytes() {
-						if byte < 0x61 /* 'a' */ || byte > 0x7A /* 'z' */ {
+						if !(0x61 /* 'a' */..=0x7A /* 'z' */).contains(&byte) {
 							return false
 						}
 					}
@@ -110,7 +111,35 @@ boxtype! {
 	SbgpBox => 0x73626770,
 	GminBox => 0x676d696e,
 	NmhdBox => 0x6e6d6864,
-	GnreBox => 0x676e7265
+	GnreBox => 0x676e7265,
+	WaveBox => 0x77617665,
+
+	// segment indexing (DASH/CMAF)
+	SidxBox => 0x73696478,
+	StypBox => 0x73747970,
+	MfraBox => 0x6d667261,
+	TfraBox => 0x74667261,
+	MfroBox => 0x6d66726f,
+	SsixBox => 0x73736978,
+	PrftBox => 0x70726674
+}
+
+impl BoxType {
+	/// converts a (possibly space-padded) four-character code such as `stsz`, `url` or `©nam` into a box type
+	pub fn from_fourcc(name: &str) -> Option<Self> {
+		let mut bytes = [b' '; 4];
+		let chars: Vec<char> = name.chars().collect();
+		if chars.is_empty() || chars.len() > 4 {
+			return None;
+		}
+
+		for (byte, c) in bytes.iter_mut().zip(chars) {
+			// Latin-1 characters (e.g. the © in iTunes tags) map directly onto single bytes
+			*byte = u8::try_from(c as u32).ok()?;
+		}
+
+		Some(Self::from(u32::from_be_bytes(bytes)))
+	}
 }
 
 impl fmt::Debug for BoxType {
@@ -129,16 +158,14 @@ pub struct BoxHeader {
 	pub name: BoxType,
 	pub size: u64,
 	pub longsize: bool,
+	/// absolute position of the first header byte within the stream
+	pub offset: u64,
 }
 
 impl BoxHeader {
-	pub fn set_size(&mut self, content_size: u64) {
-		self.size = content_size + 8;
-
-		if self.size > u32::MAX as u64 {
-			// writing will need to use longsize variant
-			self.size += 8;
-		}
+	/// number of bytes occupied by the header itself
+	pub fn header_size(&self) -> u64 {
+		if self.longsize { 16 } else { 8 }
 	}
 }
 
@@ -194,7 +221,7 @@ impl BoxData {
 		match self {
 			Self::Empty => Ok(0),
 			Self::Raw(bytes) => {
-				writer.write_all(&bytes)?;
+				writer.write_all(bytes)?;
 				Ok(bytes.len() as u64)
 			},
 			Self::Children(children) => {
@@ -229,6 +256,8 @@ pub trait Mp4Visitor {
 	fn start_box(&mut self, _header: &BoxHeader, _corrected_size: Option<u64>) -> io::Result<()> { Ok(()) }
 	fn data(&mut self, _reader: &mut impl Read) -> io::Result<()> { Ok(()) }
 	fn end_box(&mut self, _typ: &BoxType) -> io::Result<()> { Ok(()) }
+	/// called when the bytes from `offset` to `end` could not be parsed as boxes and were skipped
+	fn skipped(&mut self, _offset: u64, _end: u64) -> io::Result<()> { Ok(()) }
 }
 
 pub fn read_box<R: Read + Seek>(mut reader: R, end: u64, visitor: &mut impl Mp4Visitor) -> io::Result<R> {
@@ -270,11 +299,12 @@ pub fn read_box<R: Read + Seek>(mut reader: R, end: u64, visitor: &mut impl Mp4V
 	// Hence, read them iteratively and recursively to catch all of them.
 	while current < end {
 		log::debug!("reading box header");
-		let header = read_header(&mut reader)?;
+		let header = read_header(&mut reader, current)?;
 
 		// validate header: we expect the header to be 4 ASCII chars
 		if !header.name.validate() {
 			log::warn!("unable to find valid box header at offset {:#x}; skipping remaining contents", current);
+			visitor.skipped(current, end)?;
 			reader.seek(SeekFrom::Start(end))?;
 			return Ok(reader);
 		}
@@ -337,6 +367,18 @@ pub fn read_box<R: Read + Seek>(mut reader: R, end: u64, visitor: &mut impl Mp4V
 				| BoxType::GminBox
 				| BoxType::NmhdBox
 				| BoxType::GnreBox
+				| BoxType::MfhdBox
+				| BoxType::TfhdBox
+				| BoxType::TfdtBox
+				| BoxType::TrunBox
+				| BoxType::MehdBox
+				| BoxType::TrexBox
+				| BoxType::SidxBox
+				| BoxType::StypBox
+				| BoxType::TfraBox
+				| BoxType::MfroBox
+				| BoxType::SsixBox
+				| BoxType::PrftBox
 				=> {
 					// limit visitor's reader to just the contents of this box
 					let content_start = reader.stream_position()?;
@@ -365,7 +407,7 @@ pub fn read_box<R: Read + Seek>(mut reader: R, end: u64, visitor: &mut impl Mp4V
 	Ok(reader)
 }
 
-fn read_header<R: Read>(reader: &mut R) -> io::Result<BoxHeader> {
+fn read_header<R: Read>(reader: &mut R, offset: u64) -> io::Result<BoxHeader> {
 	let mut buf = [0u8; 8];
 	match reader.read(&mut buf) {
 		Ok(sz) => {
@@ -407,12 +449,14 @@ fn read_header<R: Read>(reader: &mut R) -> io::Result<BoxHeader> {
 			},
 
 			longsize: true,
+			offset,
 		})
 	} else {
 		Ok(BoxHeader {
 			name: BoxType::from(typ),
 			size: size as u64,
 			longsize: false,
+			offset,
 		})
 	}
 }
@@ -0,0 +1,90 @@
+use std::io::{self, ErrorKind};
+
+use crate::mp4::BoxType;
+
+/// Big-endian cursor over the raw content of a box
+pub struct PayloadReader<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> PayloadReader<'a> {
+	pub fn new(data: &'a [u8]) -> Self {
+		Self { data, pos: 0 }
+	}
+
+	pub fn remaining(&self) -> usize {
+		self.data.len() - self.pos
+	}
+
+	pub fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+		if len > self.remaining() {
+			return Err(io::Error::new(ErrorKind::UnexpectedEof, format!("needed {} B but only {} B remain", len, self.remaining())));
+		}
+
+		let slice = &self.data[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(slice)
+	}
+
+	pub fn rest(&mut self) -> &'a [u8] {
+		let slice = &self.data[self.pos..];
+		self.pos = self.data.len();
+		slice
+	}
+
+	pub fn skip(&mut self, len: usize) -> io::Result<()> {
+		self.bytes(len).map(|_| ())
+	}
+
+	pub fn u16(&mut self) -> io::Result<u16> {
+		Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+	}
+
+	pub fn u32(&mut self) -> io::Result<u32> {
+		Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+	}
+
+	pub fn u64(&mut self) -> io::Result<u64> {
+		Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+	}
+
+	/// reads the next child box from a payload made up of consecutive boxes, returning its type and content
+	pub fn child_box(&mut self) -> io::Result<(BoxType, &'a [u8])> {
+		let size = self.u32()? as u64;
+		let typ = BoxType::from(self.u32()?);
+
+		let content_size = match size {
+			// box extends to the end of its container
+			0 => self.remaining() as u64,
+			1 => self.u64()?.checked_sub(16).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "64-bit box size too small"))?,
+			2..=7 => return Err(io::Error::new(ErrorKind::InvalidData, format!("box size {} is smaller than its header", size))),
+			_ => size - 8,
+		};
+
+		Ok((typ, self.bytes(content_size as usize)?))
+	}
+
+	/// iterates over the child boxes in the remainder of the payload, stopping at the first malformed one
+	pub fn child_boxes(mut self) -> impl Iterator<Item = (BoxType, &'a [u8])> {
+		std::iter::from_fn(move || {
+			if self.remaining() < 8 {
+				return None;
+			}
+
+			match self.child_box() {
+				Ok(child) => Some(child),
+				Err(e) => {
+					log::warn!("stopped reading child boxes: {}", e);
+					None
+				},
+			}
+		})
+	}
+
+	/// reads the version (1 B) and flags (3 B) that prefix every FullBox
+	pub fn version_flags(&mut self) -> io::Result<(u8, u32)> {
+		let word = self.u32()?;
+		Ok(((word >> 24) as u8, word & 0x00ff_ffff))
+	}
+}
@@ -0,0 +1,646 @@
+use std::ffi::OsStr;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::check;
+use crate::index::{index_file, BoxIndexVisitor};
+use crate::mp4::BoxType;
+use crate::track::{self, parse_mvhd};
+
+/// most diagnostic lines kept from a player's output
+const MAX_REASONS: usize = 20;
+
+/// share of the declared duration that must be reached for playback to count as full
+const FULL_PLAYBACK_PERCENT: f64 = 99.0;
+
+/// time allowed for starting the player and opening the file, on top of the time it takes to play it
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// longest declared duration the default timeout is derived from, so a corrupt mvhd cannot make it unbounded
+const MAX_TIMED_DURATION_S: f64 = 6.0 * 3600.0;
+
+/// Which playability test to run
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum BackendKind {
+	/// play the file with mpv without video or audio output
+	Mpv,
+	/// decode every frame with `ffmpeg -f null`
+	Ffmpeg,
+	/// only probe the container and stream headers with ffprobe
+	Ffprobe,
+	/// check the sample tables without launching anything
+	Native,
+}
+
+/// How much of the file played
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "grade", rename_all = "lowercase")]
+pub enum Grade {
+	Full,
+	Partial { percent: f64 },
+	Fails,
+}
+
+impl Grade {
+	/// grades playback from the position reached, falling back to the pass/fail verdict when either time is unknown
+	fn new(playable: bool, position: Option<f64>, duration: Option<f64>) -> Self {
+		match (position, duration) {
+			(Some(position), Some(duration)) if duration > 0.0 => {
+				let percent = (position / duration * 100.0).min(100.0);
+				if percent >= FULL_PLAYBACK_PERCENT {
+					Grade::Full
+				} else if percent > 0.0 {
+					Grade::Partial { percent }
+				} else {
+					Grade::Fails
+				}
+			},
+			_ if playable => Grade::Full,
+			_ => Grade::Fails,
+		}
+	}
+}
+
+impl std::fmt::Display for Grade {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Grade::Full => write!(f, "full"),
+			Grade::Partial { percent } => write!(f, "partial ({:.1}%)", percent),
+			Grade::Fails => write!(f, "fails"),
+		}
+	}
+}
+
+/// Outcome of a playability test
+#[derive(Debug, Serialize)]
+pub struct Verdict {
+	pub backend: &'static str,
+	pub playable: bool,
+	#[serde(flatten)]
+	pub grade: Grade,
+	/// playback position reached, in seconds
+	pub position_s: Option<f64>,
+	/// duration declared by the file's mvhd, in seconds
+	pub duration_s: Option<f64>,
+	pub decode_errors: usize,
+	/// kinds of stream that were opened, e.g. `video` and `audio`
+	pub streams: Vec<String>,
+	/// diagnostics explaining the verdict, e.g. decoder errors or missing boxes
+	pub reasons: Vec<String>,
+	/// exit code of the external program, if it exited on its own
+	pub exit_code: Option<i32>,
+	/// the program was still running when the timeout elapsed
+	pub timed_out: bool,
+	pub elapsed_ms: u64,
+}
+
+/// A way of deciding whether a file plays
+pub trait Backend {
+	fn name(&self) -> &'static str;
+	fn test(&self, file: &Path) -> io::Result<Verdict>;
+}
+
+/// Runs mpv headless; a player that is still running at the timeout only counts as playing the file if it was
+/// seen to reach the end
+pub struct Mpv {
+	pub program: PathBuf,
+	pub timeout: Duration,
+}
+
+/// Decodes the whole file with ffmpeg, discarding the output
+pub struct Ffmpeg {
+	pub program: PathBuf,
+	pub timeout: Duration,
+}
+
+/// Probes the file with ffprobe, which only reads headers
+pub struct Ffprobe {
+	pub program: PathBuf,
+	pub timeout: Duration,
+}
+
+/// Applies `inspect check` without any external program
+pub struct Native;
+
+/// builds the backend of the given kind, running `player` instead of the backend's usual executable if given
+pub fn backend(kind: BackendKind, player: Option<PathBuf>, timeout: Duration) -> Box<dyn Backend> {
+	let program = |default: &str| player.clone().unwrap_or_else(|| PathBuf::from(default));
+	match kind {
+		BackendKind::Mpv => Box::new(Mpv { program: program("mpv"), timeout }),
+		BackendKind::Ffmpeg => Box::new(Ffmpeg { program: program("ffmpeg"), timeout }),
+		BackendKind::Ffprobe => Box::new(Ffprobe { program: program("ffprobe"), timeout }),
+		BackendKind::Native => {
+			if player.is_some() {
+				log::warn!("the native backend does not run a player; ignoring --player");
+			}
+			Box::new(Native)
+		},
+	}
+}
+
+/// What an external program did before it exited or was killed
+struct Run {
+	/// `None` if the program was killed at the timeout
+	status: Option<ExitStatus>,
+	stdout: String,
+	stderr: String,
+	elapsed: Duration,
+}
+
+/// What a backend learned while the file played
+#[derive(Default)]
+struct Progress {
+	position: Option<f64>,
+	decode_errors: usize,
+	streams: Vec<String>,
+}
+
+impl Run {
+	fn verdict(&self, backend: &'static str, playable: bool, reasons: Vec<String>, progress: Progress, duration: Option<f64>) -> Verdict {
+		Verdict {
+			backend,
+			playable,
+			grade: Grade::new(playable, progress.position, duration),
+			position_s: progress.position,
+			duration_s: duration,
+			decode_errors: progress.decode_errors,
+			streams: progress.streams,
+			reasons,
+			exit_code: self.status.and_then(|s| s.code()),
+			timed_out: self.status.is_none(),
+			elapsed_ms: self.elapsed.as_millis() as u64,
+		}
+	}
+
+	/// non-empty lines of standard error
+	fn error_lines(&self) -> Vec<&str> {
+		self.stderr.lines().map(str::trim).filter(|l| !l.is_empty()).collect()
+	}
+}
+
+/// keeps the first `MAX_REASONS` lines, noting how many were dropped
+fn capped(lines: &[&str]) -> Vec<String> {
+	let mut reasons: Vec<String> = lines.iter().take(MAX_REASONS).map(|l| l.to_string()).collect();
+	if lines.len() > MAX_REASONS {
+		reasons.push(format!("... and {} more lines", lines.len() - MAX_REASONS));
+	}
+	reasons
+}
+
+/// the timeout used when none is given: long enough to play the declared duration in real time twice over
+pub fn default_timeout(file: &Path) -> Duration {
+	let duration = declared_duration(file).filter(|d| d.is_finite()).unwrap_or(0.0).clamp(0.0, MAX_TIMED_DURATION_S);
+	STARTUP_TIMEOUT + Duration::from_secs_f64(2.0 * duration)
+}
+
+/// whether a program killed at the timeout had got through the file; without a known position there is no telling
+fn finished_before_timeout(progress: &Progress, duration: Option<f64>) -> bool {
+	progress.position.is_some() && Grade::new(false, progress.position, duration) == Grade::Full
+}
+
+/// the duration declared by the file's mvhd, in seconds
+fn declared_duration(file: &Path) -> Option<f64> {
+	let (index, _file_size) = index_file(file, true).ok()?;
+	movie_duration(&index)
+}
+
+fn movie_duration(index: &BoxIndexVisitor) -> Option<f64> {
+	let mvhd = index.find_top_level(BoxType::MoovBox)
+		.and_then(|moov| index.find_child(moov, BoxType::MvhdBox))
+		.and_then(|mvhd| index.boxes[mvhd].payload.as_deref())
+		.and_then(|payload| parse_mvhd(payload).ok())?;
+	(mvhd.timescale > 0).then(|| mvhd.duration as f64 / mvhd.timescale as f64)
+}
+
+/// Output collected from a pipe on a separate thread
+struct Drain {
+	output: Arc<Mutex<Vec<u8>>>,
+	thread: JoinHandle<()>,
+}
+
+impl Drain {
+	fn new<R: Read + Send + 'static>(pipe: Option<R>) -> Self {
+		let output = Arc::new(Mutex::new(Vec::new()));
+		let sink = Arc::clone(&output);
+		let thread = thread::spawn(move || {
+			let Some(mut pipe) = pipe else { return };
+			let mut buf = [0u8; 4096];
+			while let Ok(len @ 1..) = pipe.read(&mut buf) {
+				sink.lock().unwrap().extend_from_slice(&buf[..len]);
+			}
+		});
+		Self { output, thread }
+	}
+
+	/// the output so far; waits for the pipe to close only if `finished`, since a killed program's
+	/// children may keep it open
+	fn collect(self, finished: bool) -> String {
+		if finished {
+			let _ = self.thread.join();
+		}
+		let output = self.output.lock().unwrap();
+		String::from_utf8_lossy(&output).into_owned()
+	}
+}
+
+/// spawns `program` and waits for it to exit, killing it once `timeout` elapses; `poll` is called while it runs
+fn run(program: &Path, args: &[&OsStr], timeout: Duration, mut poll: impl FnMut()) -> io::Result<Run> {
+	log::trace!("spawning {}", program.display());
+	let mut child = Command::new(program).args(args)
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| match e.kind() {
+			ErrorKind::NotFound => io::Error::new(ErrorKind::NotFound, format!("{} not found; install it or pass --player", program.display())),
+			_ => e,
+		})?;
+
+	// drain the pipes on their own threads so a chatty program cannot block on a full pipe
+	let stdout = Drain::new(child.stdout.take());
+	let stderr = Drain::new(child.stderr.take());
+
+	let start_time = Instant::now();
+	let poll_interval = Duration::from_millis(100).min(timeout);
+	let status = loop {
+		if let Some(status) = child.try_wait()? {
+			log::trace!("{} exited with {}", program.display(), status);
+			break Some(status);
+		}
+
+		if start_time.elapsed() >= timeout {
+			log::trace!("killing {}", program.display());
+			child.kill()?;
+			child.wait()?;
+			break None;
+		}
+
+		poll();
+		thread::sleep(poll_interval);
+	};
+	let elapsed = start_time.elapsed();
+
+	let run = Run {
+		status,
+		stdout: stdout.collect(status.is_some()),
+		stderr: stderr.collect(status.is_some()),
+		elapsed,
+	};
+	for line in run.stderr.lines() {
+		log::debug!("{}: {}", program.display(), line);
+	}
+	Ok(run)
+}
+
+/// Talks to mpv over its JSON IPC socket
+#[cfg(unix)]
+struct MpvIpc {
+	reader: io::BufReader<std::os::unix::net::UnixStream>,
+	next_request: u64,
+}
+
+#[cfg(unix)]
+impl MpvIpc {
+	fn connect(socket: &Path) -> io::Result<Self> {
+		let stream = std::os::unix::net::UnixStream::connect(socket)?;
+		stream.set_read_timeout(Some(Duration::from_millis(500)))?;
+		Ok(Self { reader: io::BufReader::new(stream), next_request: 1 })
+	}
+
+	/// sends a command and returns the `data` of its reply, skipping the events mpv interleaves with replies
+	fn command(&mut self, command: serde_json::Value) -> io::Result<serde_json::Value> {
+		use std::io::{BufRead, Write};
+
+		let request_id = self.next_request;
+		self.next_request += 1;
+		let mut line = serde_json::json!({ "command": command, "request_id": request_id }).to_string();
+		line.push('\n');
+		self.reader.get_mut().write_all(line.as_bytes())?;
+
+		loop {
+			let mut reply = String::new();
+			if self.reader.read_line(&mut reply)? == 0 {
+				return Err(io::Error::new(ErrorKind::UnexpectedEof, "mpv closed the IPC socket"));
+			}
+			let reply: serde_json::Value = serde_json::from_str(&reply)?;
+			if reply["request_id"] == request_id {
+				return match reply["error"].as_str() {
+					Some("success") => Ok(reply["data"].clone()),
+					error => Err(io::Error::other(format!("mpv: {}", error.unwrap_or("malformed reply")))),
+				};
+			}
+		}
+	}
+
+	fn property(&mut self, name: &str) -> Option<serde_json::Value> {
+		self.command(serde_json::json!(["get_property", name])).ok()
+	}
+
+	/// records the position and open streams, asking mpv to quit once it reaches the end of the file
+	fn poll(&mut self, progress: &mut Progress) {
+		if let Some(position) = self.property("time-pos").and_then(|v| v.as_f64()) {
+			progress.position = Some(progress.position.unwrap_or(0.0).max(position));
+		}
+		if let Some(tracks) = self.property("track-list").and_then(|v| v.as_array().cloned()) {
+			for track in tracks.iter().filter(|t| t["selected"] == true) {
+				if let Some(kind) = track["type"].as_str() {
+					if !progress.streams.iter().any(|s| s == kind) {
+						progress.streams.push(kind.to_string());
+					}
+				}
+			}
+		}
+		if self.property("eof-reached") == Some(serde_json::Value::Bool(true)) {
+			let _ = self.command(serde_json::json!(["quit"]));
+		}
+	}
+}
+
+impl Backend for Mpv {
+	fn name(&self) -> &'static str {
+		"mpv"
+	}
+
+	/// plays the file as fast as possible, polling the position over IPC; players without IPC (e.g. a fake
+	/// player script) are graded on their exit status alone
+	fn test(&self, file: &Path) -> io::Result<Verdict> {
+		let socket = std::env::temp_dir().join(format!("mp4-cli-mpv-{}.sock", std::process::id()));
+		let ipc_arg = format!("--input-ipc-server={}", socket.display());
+		let args = [OsStr::new("--vo=null"), OsStr::new("--ao=null"), OsStr::new("--ao-null-untimed"), OsStr::new("--untimed"),
+			OsStr::new("--keep-open=yes"), OsStr::new("--msg-level=all=error"), OsStr::new(&ipc_arg), file.as_os_str()];
+
+		let mut progress = Progress::default();
+		#[cfg(unix)]
+		let run = {
+			let mut ipc: Option<MpvIpc> = None;
+			run(&self.program, &args, self.timeout, || {
+				if ipc.is_none() {
+					ipc = MpvIpc::connect(&socket).ok();
+				}
+				if let Some(ipc) = ipc.as_mut() {
+					ipc.poll(&mut progress);
+				}
+			})?
+		};
+		#[cfg(not(unix))]
+		let run = run(&self.program, &args, self.timeout, || {})?;
+		let _ = std::fs::remove_file(&socket);
+
+		let errors = run.error_lines();
+		progress.decode_errors = errors.len();
+		let mut reasons = capped(&errors);
+		let duration = declared_duration(file);
+		let playable = match run.status {
+			None if finished_before_timeout(&progress, duration) => {
+				reasons.push(format!("still playing after {} ms, having reached the end", self.timeout.as_millis()));
+				true
+			},
+			None => {
+				reasons.push(format!("still playing after {} ms without reaching the end", self.timeout.as_millis()));
+				false
+			},
+			Some(status) => status.success(),
+		};
+		Ok(run.verdict(self.name(), playable, reasons, progress, duration))
+	}
+}
+
+/// reads the position, stream types and error count from ffmpeg's `-progress` output and level-tagged log
+fn parse_ffmpeg_output(run: &Run) -> (Progress, Vec<&str>) {
+	let mut progress = Progress::default();
+	for line in run.stdout.lines() {
+		if let Some(micros) = line.strip_prefix("out_time_us=").and_then(|v| v.trim().parse::<i64>().ok()) {
+			progress.position = Some(micros.max(0) as f64 / 1_000_000.0);
+		}
+	}
+
+	// input streams are listed as "Stream #0:1[0x2](und): Audio: aac ..." and the opened ones as "Stream #0:1 -> #0:1 (...)"
+	let mut kinds = Vec::new();
+	let mut errors = Vec::new();
+	for line in run.stderr.lines().map(str::trim) {
+		if line.starts_with("[error]") || line.starts_with("[fatal]") || line.contains("] [error]") || line.contains("] [fatal]") {
+			errors.push(line);
+		}
+		let Some(stream) = line.split("Stream #").nth(1) else { continue };
+		let id: String = stream.chars().take_while(|c| c.is_ascii_digit() || *c == ':').collect();
+		if stream[id.len()..].trim_start().starts_with("->") {
+			if let Some((_, kind)) = kinds.iter().find(|(i, _)| *i == id) {
+				if !progress.streams.contains(kind) {
+					progress.streams.push(String::clone(kind));
+				}
+			}
+		} else if let Some(kind) = ["Video", "Audio", "Subtitle", "Data"].iter().find(|k| stream.contains(&format!(": {}:", k))) {
+			kinds.push((id, kind.to_lowercase()));
+		}
+	}
+	progress.decode_errors = errors.len();
+	(progress, errors)
+}
+
+impl Backend for Ffmpeg {
+	fn name(&self) -> &'static str {
+		"ffmpeg"
+	}
+
+	fn test(&self, file: &Path) -> io::Result<Verdict> {
+		let args = [OsStr::new("-nostdin"), OsStr::new("-nostats"), OsStr::new("-loglevel"), OsStr::new("level+info"),
+			OsStr::new("-progress"), OsStr::new("pipe:1"), OsStr::new("-i"), file.as_os_str(), OsStr::new("-f"), OsStr::new("null"), OsStr::new("-")];
+		let run = run(&self.program, &args, self.timeout, || {})?;
+		let (progress, errors) = parse_ffmpeg_output(&run);
+		let mut reasons = capped(&errors);
+		let duration = declared_duration(file);
+		let playable = match run.status {
+			None if finished_before_timeout(&progress, duration) => {
+				reasons.push(format!("still decoding after {} ms, having reached the end", self.timeout.as_millis()));
+				true
+			},
+			None => {
+				reasons.push(format!("still decoding after {} ms without reaching the end", self.timeout.as_millis()));
+				false
+			},
+			// ffmpeg exits successfully after concealing decode errors, which are still worth reporting
+			Some(status) => status.success(),
+		};
+		Ok(run.verdict(self.name(), playable, reasons, progress, duration))
+	}
+}
+
+impl Backend for Ffprobe {
+	fn name(&self) -> &'static str {
+		"ffprobe"
+	}
+
+	/// only reads headers, so the grade is full or fails
+	fn test(&self, file: &Path) -> io::Result<Verdict> {
+		let args = [OsStr::new("-v"), OsStr::new("error"), OsStr::new("-show_entries"), OsStr::new("stream=codec_type"), OsStr::new("-of"), OsStr::new("csv=p=0"), file.as_os_str()];
+		let run = run(&self.program, &args, self.timeout, || {})?;
+		let errors = run.error_lines();
+		let mut reasons = capped(&errors);
+		let streams: Vec<String> = run.stdout.lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect();
+		let success = run.status.is_some_and(|s| s.success());
+		if run.status.is_none() {
+			reasons.push(format!("probe did not finish within {} ms", self.timeout.as_millis()));
+		} else if success && streams.is_empty() {
+			reasons.push("no streams found".to_string());
+		}
+
+		let progress = Progress { position: None, decode_errors: errors.len(), streams };
+		let playable = success && !progress.streams.is_empty();
+		Ok(run.verdict(self.name(), playable, reasons, progress, declared_duration(file)))
+	}
+}
+
+impl Backend for Native {
+	fn name(&self) -> &'static str {
+		"native"
+	}
+
+	/// the position is how far the longest track gets before its first sample that lies outside the file
+	fn test(&self, file: &Path) -> io::Result<Verdict> {
+		let start_time = Instant::now();
+		let result = check::check(file)?;
+
+		let mut reasons = result.errors.clone();
+		let mut decode_errors = result.errors.len();
+		for (label, errors) in &result.tracks {
+			if errors.is_empty() {
+				reasons.push(format!("{}: ok", label));
+			}
+			reasons.extend(errors.iter().map(|e| format!("{}: {}", label, e)));
+			decode_errors += errors.len();
+		}
+
+		let (index, file_size) = index_file(file, true)?;
+		let mut progress = Progress { decode_errors, ..Default::default() };
+		for track in track::tracks(&index) {
+			let timescale = track.mdhd.as_ref().map(|m| m.timescale).unwrap_or(0);
+			let reachable = track.sample_times().iter().zip(track.sample_locations())
+				.take_while(|(_, location)| location.end() <= file_size)
+				.map(|(time, _)| time.dts + time.duration as u64)
+				.last();
+			let Some(end) = reachable.filter(|_| timescale > 0) else { continue };
+
+			progress.position = Some(progress.position.unwrap_or(0.0).max(end as f64 / timescale as f64));
+			let kind = match track.handler.as_deref() {
+				Some("vide") => "video",
+				Some("soun") => "audio",
+				Some("text" | "sbtl" | "subt") => "subtitle",
+				_ => "data",
+			};
+			if !progress.streams.iter().any(|s| s == kind) {
+				progress.streams.push(kind.to_string());
+			}
+		}
+
+		let duration = movie_duration(&index);
+		Ok(Verdict {
+			backend: self.name(),
+			playable: result.is_playable(),
+			grade: Grade::new(result.is_playable(), progress.position, duration),
+			position_s: progress.position,
+			duration_s: duration,
+			decode_errors: progress.decode_errors,
+			streams: progress.streams,
+			reasons,
+			exit_code: None,
+			timed_out: false,
+			elapsed_ms: start_time.elapsed().as_millis() as u64,
+		})
+	}
+}
+
+/// prints a verdict as text or JSON
+pub fn print_verdict(verdict: &Verdict, json: bool) -> serde_json::Result<()> {
+	if json {
+		println!("{}", serde_json::to_string_pretty(verdict)?);
+		return Ok(());
+	}
+
+	println!("{}: {} ({} ms{}{})", verdict.backend, verdict.grade, verdict.elapsed_ms,
+		verdict.exit_code.map(|c| format!(", exit code {}", c)).unwrap_or_default(),
+		if verdict.timed_out { ", timed out" } else { "" });
+	match (verdict.position_s, verdict.duration_s) {
+		(Some(position), Some(duration)) => println!("  reached {:.3} s of {:.3} s", position, duration),
+		(Some(position), None) => println!("  reached {:.3} s (duration unknown)", position),
+		(None, Some(duration)) => println!("  position unknown ({:.3} s declared)", duration),
+		(None, None) => {},
+	}
+	println!("  {} decode error{}, streams opened: {}", verdict.decode_errors, if verdict.decode_errors == 1 { "" } else { "s" },
+		if verdict.streams.is_empty() { "none".to_string() } else { verdict.streams.join(", ") });
+	for reason in &verdict.reasons {
+		println!("  {}", reason);
+	}
+	Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use std::fs;
+	use std::os::unix::fs::PermissionsExt;
+	use std::sync::Mutex;
+
+	use super::*;
+
+	/// serializes the tests, since a stub written while another test forks can fail to exec with ETXTBSY
+	static STUBS: Mutex<()> = Mutex::new(());
+
+	/// runs the backend with a shell script standing in for the player
+	fn run_stub(kind: BackendKind, name: &str, script: &str, timeout: Duration) -> Verdict {
+		let _guard = STUBS.lock().unwrap_or_else(|e| e.into_inner());
+		let stub = std::env::temp_dir().join(format!("mp4-cli-stub-{}-{}", std::process::id(), name));
+		fs::write(&stub, format!("#!/bin/sh\n{}\n", script)).unwrap();
+		fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+		// the stubs ignore their input, and a missing file just leaves the duration unknown
+		let verdict = backend(kind, Some(stub.clone()), timeout).test(Path::new("missing.mp4")).unwrap();
+		fs::remove_file(&stub).unwrap();
+		verdict
+	}
+
+	#[test]
+	fn player_exit_status_decides_the_verdict() {
+		let verdict = run_stub(BackendKind::Mpv, "exit-ok", "exit 0", Duration::from_secs(5));
+		assert!(verdict.playable);
+		assert_eq!(verdict.grade, Grade::Full);
+		assert_eq!(verdict.exit_code, Some(0));
+		assert!(!verdict.timed_out);
+
+		let verdict = run_stub(BackendKind::Mpv, "exit-fail", "exit 3", Duration::from_secs(5));
+		assert!(!verdict.playable);
+		assert_eq!(verdict.grade, Grade::Fails);
+		assert_eq!(verdict.exit_code, Some(3));
+	}
+
+	#[test]
+	fn player_killed_at_timeout() {
+		let verdict = run_stub(BackendKind::Mpv, "timeout", "exec sleep 5", Duration::from_millis(200));
+		assert!(verdict.timed_out);
+		assert!(!verdict.playable);
+		assert_eq!(verdict.exit_code, None);
+		assert!(verdict.elapsed_ms < 5000);
+		assert!(verdict.reasons.iter().any(|r| r.starts_with("still playing after")));
+	}
+
+	#[test]
+	fn player_stderr_is_reported() {
+		let verdict = run_stub(BackendKind::Mpv, "stderr", "echo 'Error while decoding frame!' >&2\nexit 1", Duration::from_secs(5));
+		assert!(!verdict.playable);
+		assert_eq!(verdict.decode_errors, 1);
+		assert_eq!(verdict.reasons, ["Error while decoding frame!"]);
+
+		// ffmpeg conceals decode errors and exits successfully, so they only show up in the error count
+		let verdict = run_stub(BackendKind::Ffmpeg, "ffmpeg-stderr",
+			"echo 'out_time_us=1500000'\necho '[h264 @ 0x1] [error] concealing 12 DC errors' >&2\necho '[info] done' >&2", Duration::from_secs(5));
+		assert!(verdict.playable);
+		assert_eq!(verdict.decode_errors, 1);
+		assert_eq!(verdict.position_s, Some(1.5));
+		assert_eq!(verdict.reasons, ["[h264 @ 0x1] [error] concealing 12 DC errors"]);
+	}
+}
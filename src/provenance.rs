@@ -0,0 +1,206 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::tags::{Tag, TagValue};
+use crate::times::{format_unix, parse_iso8601};
+
+/// tags consulted for each field, in order of preference
+const LOCATION_KEYS: &[&str] = &["com.apple.quicktime.location.ISO6709", "©xyz"];
+const MAKE_KEYS: &[&str] = &["com.apple.quicktime.make", "com.android.manufacturer", "©mak"];
+const MODEL_KEYS: &[&str] = &["com.apple.quicktime.model", "com.android.model", "©mod"];
+const SOFTWARE_KEYS: &[&str] = &["com.apple.quicktime.software", "com.android.version", "©swr", "©too"];
+const CREATION_KEYS: &[&str] = &["com.apple.quicktime.creationdate", "©day"];
+const IDENTIFIER_KEYS: &[&str] = &["com.apple.quicktime.content.identifier"];
+
+/// A normalized value along with the tag it was taken from
+#[derive(Debug, Serialize)]
+pub struct Field<T> {
+	pub value: T,
+	pub source: String,
+	/// the tag value before normalization, when it differs
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub raw: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Location {
+	pub latitude: f64,
+	pub longitude: f64,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub altitude: Option<f64>,
+}
+
+impl fmt::Display for Location {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:.6}, {:.6}", self.latitude, self.longitude)?;
+		if let Some(altitude) = self.altitude {
+			write!(f, ", {} m", altitude)?;
+		}
+		Ok(())
+	}
+}
+
+/// Device and capture details gathered from the Apple (`mdta` keys) and Android/QuickTime (`udta`) metadata schemes
+#[derive(Debug, Default, Serialize)]
+pub struct Provenance {
+	pub location: Option<Field<Location>>,
+	pub make: Option<Field<String>>,
+	pub model: Option<Field<String>>,
+	pub software: Option<Field<String>>,
+	pub creation_time: Option<Field<String>>,
+	pub content_identifier: Option<Field<String>>,
+}
+
+/// parses an ISO 6709 point such as `+37.7749-122.4194+012.345/`, including the degree-minute(-second) forms
+pub fn parse_iso6709(s: &str) -> Option<Location> {
+	let s = s.trim().trim_end_matches('/');
+	let s = s.split("CRS").next()?;
+
+	// split into signed components: latitude, longitude and optional altitude
+	let mut components = Vec::new();
+	for (i, c) in s.char_indices() {
+		if c == '+' || c == '-' {
+			components.push(i);
+		}
+	}
+	if components.first() != Some(&0) || !(2..=3).contains(&components.len()) {
+		return None;
+	}
+	components.push(s.len());
+
+	let part = |n: usize| &s[components[n]..components[n + 1]];
+	Some(Location {
+		latitude: sexagesimal(part(0), 2)?,
+		longitude: sexagesimal(part(1), 3)?,
+		altitude: if components.len() == 4 { Some(part(2).parse().ok()?) } else { None },
+	})
+}
+
+/// converts `±DD.D`, `±DDMM.M` or `±DDMMSS.S` (with `degree_digits` digits of degrees) into decimal degrees
+fn sexagesimal(s: &str, degree_digits: usize) -> Option<f64> {
+	// byte offsets below assume one byte per digit
+	if !s.is_ascii() {
+		return None;
+	}
+	let (sign, digits) = s.split_at(1);
+	let sign = if sign == "-" { -1.0 } else { 1.0 };
+	let integer_digits = digits.find('.').unwrap_or(digits.len());
+
+	let value = match integer_digits.saturating_sub(degree_digits) {
+		0 => digits.parse::<f64>().ok()?,
+		2 => digits[..degree_digits].parse::<f64>().ok()? + digits[degree_digits..].parse::<f64>().ok()? / 60.0,
+		4 => digits[..degree_digits].parse::<f64>().ok()?
+			+ digits[degree_digits..degree_digits + 2].parse::<f64>().ok()? / 60.0
+			+ digits[degree_digits + 2..].parse::<f64>().ok()? / 3600.0,
+		_ => return None,
+	};
+
+	Some(sign * value)
+}
+
+/// picks the first tag with one of the given keys, warning when other sources disagree with it
+fn pick<'a>(tags: &'a [Tag], keys: &[&str], field: &str) -> Option<(&'a Tag, String)> {
+	let candidates: Vec<(&Tag, String)> = keys.iter()
+		.flat_map(|key| tags.iter().filter(move |tag| tag.key == *key))
+		.filter_map(|tag| match &tag.value {
+			TagValue::Text { value } => Some((tag, value.trim().to_string())),
+			TagValue::Integer { value } => Some((tag, value.to_string())),
+			TagValue::Float { value } => Some((tag, value.to_string())),
+			_ => None,
+		})
+		.filter(|(_, value)| !value.is_empty())
+		.collect();
+
+	let (first, value) = candidates.first()?;
+	for (other, other_value) in &candidates[1..] {
+		if other_value != value {
+			log::warn!("conflicting {}: {} says '{}' but {} says '{}'", field, first.key, value, other.key, other_value);
+		}
+	}
+
+	Some((first, value.clone()))
+}
+
+fn text_field(tags: &[Tag], keys: &[&str], field: &str) -> Option<Field<String>> {
+	pick(tags, keys, field).map(|(tag, value)| Field { value, source: tag.key.clone(), raw: None })
+}
+
+pub fn provenance(tags: &[Tag]) -> Provenance {
+	let location = pick(tags, LOCATION_KEYS, "location").and_then(|(tag, raw)| match parse_iso6709(&raw) {
+		Some(value) => Some(Field { value, source: tag.key.clone(), raw: Some(raw) }),
+		None => {
+			log::warn!("unable to parse {} location '{}'", tag.key, raw);
+			None
+		},
+	});
+
+	// a date-only or zone-less value does not pin down an instant, so it is kept as written
+	let creation_time = pick(tags, CREATION_KEYS, "creation time").map(|(tag, raw)| match parse_iso8601(&raw) {
+		Some(secs) => Field { value: format_unix(secs), source: tag.key.clone(), raw: Some(raw) },
+		None => {
+			log::warn!("unable to normalize {} creation time '{}'", tag.key, raw);
+			Field { value: raw, source: tag.key.clone(), raw: None }
+		},
+	});
+
+	Provenance {
+		location,
+		make: text_field(tags, MAKE_KEYS, "make"),
+		model: text_field(tags, MODEL_KEYS, "model"),
+		software: text_field(tags, SOFTWARE_KEYS, "software"),
+		creation_time,
+		content_identifier: text_field(tags, IDENTIFIER_KEYS, "content identifier"),
+	}
+}
+
+pub fn print_provenance(record: &Provenance, json: bool) -> serde_json::Result<()> {
+	if json {
+		println!("{}", serde_json::to_string_pretty(record)?);
+		return Ok(());
+	}
+
+	fn line<T: fmt::Display>(label: &str, field: &Option<Field<T>>) {
+		match field {
+			Some(field) => println!("{:<19} {} (from {})", format!("{}:", label), field.value, field.source),
+			None => println!("{:<19} unknown", format!("{}:", label)),
+		}
+	}
+
+	line("location", &record.location);
+	line("make", &record.make);
+	line("model", &record.model);
+	line("software", &record.software);
+	line("creation time", &record.creation_time);
+	line("content identifier", &record.content_identifier);
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn point(s: &str) -> Option<(f64, f64, Option<f64>)> {
+		parse_iso6709(s).map(|l| ((l.latitude * 1e6).round() / 1e6, (l.longitude * 1e6).round() / 1e6, l.altitude))
+	}
+
+	#[test]
+	fn iso6709_points() {
+		assert_eq!(point("+37.7749-122.4194+012.345/"), Some((37.7749, -122.4194, Some(12.345))));
+		assert_eq!(point("+37.7749-122.4194/"), Some((37.7749, -122.4194, None)));
+		assert_eq!(point("+4830.5+00212.25/"), Some((48.508333, 2.204167, None)));
+		assert_eq!(point("-334530-0581215/"), Some((-33.758333, -58.204167, None)));
+		assert_eq!(point("+40.6894-074.0447+010.000CRSWGS_84/"), Some((40.6894, -74.0447, Some(10.0))));
+	}
+
+	#[test]
+	fn malformed_iso6709_points() {
+		assert_eq!(point(""), None);
+		assert_eq!(point("37.7749-122.4194/"), None);
+		assert_eq!(point("+37.7749/"), None);
+		assert_eq!(point("+1-2+3-4/"), None);
+		assert_eq!(point("+37é-122/"), None);
+		assert_eq!(point("+377749000-122/"), None);
+	}
+}
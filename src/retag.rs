@@ -0,0 +1,501 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::os::unix::prelude::FileExt;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::index::index_file;
+use crate::mp4::{BoxData, BoxType, Mp4Box};
+use crate::payload::PayloadReader;
+use crate::tags::{self, TagValue};
+
+/// How a command-line value should be stored in a `data` box
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ValueKind {
+	/// infer from the key (`covr` takes an image path, `trkn`/`disk` take "n/m", ...)
+	Auto,
+	Text,
+	Integer,
+	/// "n", "n/m" or "n of m"
+	Index,
+	/// path to a JPEG, PNG or BMP file
+	Image,
+}
+
+/// A change to the `ilst` items of a file
+pub enum TagEdit {
+	/// replace every value of the item
+	Set(BoxType, TagValue),
+	/// append a value to the item, creating it if necessary
+	Add(BoxType, TagValue),
+	/// drop the item entirely
+	Remove(BoxType),
+}
+
+/// keys whose integer values have a conventional width
+const INTEGER_WIDTHS: &[(&str, usize)] = &[
+	("tmpo", 2),
+	("cpil", 1),
+	("pgap", 1),
+	("hdvd", 1),
+	("stik", 1),
+	("rtng", 1),
+];
+
+/// resolves a four-character key (`©nam`) or one of its descriptive names (`title`)
+pub fn parse_key(key: &str) -> io::Result<BoxType> {
+	if let Some((fourcc, _)) = tags::KEY_NAMES.iter().find(|(_, name)| name.eq_ignore_ascii_case(key)) {
+		return Ok(BoxType::from_fourcc(fourcc).unwrap());
+	}
+
+	match BoxType::from_fourcc(key) {
+		Some(typ) if key.chars().count() == 4 => Ok(typ),
+		_ => Err(io::Error::new(ErrorKind::InvalidInput, format!("'{}' is neither a four-character key nor a known tag name", key))),
+	}
+}
+
+/// interprets a command-line value for the given key
+pub fn parse_value(key: BoxType, value: &str, kind: ValueKind) -> io::Result<TagValue> {
+	let kind = match (kind, tags::fourcc(key).as_str()) {
+		(ValueKind::Auto, "covr") => ValueKind::Image,
+		(ValueKind::Auto, "trkn" | "disk") => ValueKind::Index,
+		(ValueKind::Auto, "gnre") => return genre_value(value),
+		(ValueKind::Auto, k) if INTEGER_WIDTHS.iter().any(|(key, _)| *key == k) => ValueKind::Integer,
+		(ValueKind::Auto, _) => ValueKind::Text,
+		(kind, _) => kind,
+	};
+
+	let invalid = |what: &str| io::Error::new(ErrorKind::InvalidInput, format!("'{}' is not a valid {}", value, what));
+
+	Ok(match kind {
+		ValueKind::Auto | ValueKind::Text => TagValue::Text { value: value.to_string() },
+		ValueKind::Integer => TagValue::Integer { value: value.parse().map_err(|_| invalid("integer"))? },
+		ValueKind::Index => {
+			let (number, total) = value.split_once('/')
+				.or_else(|| value.split_once(" of "))
+				.unwrap_or((value, "0"));
+			TagValue::Index {
+				number: number.trim().parse().map_err(|_| invalid("index"))?,
+				total: total.trim().parse().map_err(|_| invalid("index"))?,
+			}
+		},
+		ValueKind::Image => {
+			let data = fs::read(value)?;
+			let format = if data.starts_with(&[0xff, 0xd8]) {
+				"jpeg"
+			} else if data.starts_with(b"\x89PNG") {
+				"png"
+			} else if data.starts_with(b"BM") {
+				"bmp"
+			} else {
+				return Err(invalid("JPEG, PNG or BMP image"));
+			};
+			TagValue::Image { format, data }
+		},
+	})
+}
+
+/// `gnre` stores a 1-based ID3v1 genre number
+fn genre_value(value: &str) -> io::Result<TagValue> {
+	let id = match tags::GENRES.iter().position(|genre| genre.eq_ignore_ascii_case(value)) {
+		Some(i) => i as u16 + 1,
+		None => value.parse().map_err(|_| io::Error::new(ErrorKind::InvalidInput, format!("'{}' is not an ID3v1 genre; use ©gen for free-form genres", value)))?,
+	};
+
+	Ok(TagValue::Binary { type_indicator: tags::TYPE_IMPLICIT, data: id.to_be_bytes().to_vec() })
+}
+
+/// serializes a value as the content of a `data` box
+fn encode_data(key: BoxType, value: &TagValue) -> Vec<u8> {
+	let (type_indicator, bytes) = match value {
+		TagValue::Text { value } => (tags::TYPE_UTF8, value.as_bytes().to_vec()),
+		TagValue::Integer { value } => {
+			let width = INTEGER_WIDTHS.iter()
+				.find(|(k, _)| *k == tags::fourcc(key))
+				.map(|(_, width)| *width)
+				.unwrap_or_else(|| [1, 2, 4].into_iter().find(|&w| fits(*value, w)).unwrap_or(8));
+			(tags::TYPE_SIGNED_INT, value.to_be_bytes()[8 - width..].to_vec())
+		},
+		TagValue::Float { value } => (tags::TYPE_FLOAT64, value.to_be_bytes().to_vec()),
+		TagValue::Index { number, total } => {
+			let mut bytes = vec![0, 0];
+			bytes.extend_from_slice(&number.to_be_bytes());
+			bytes.extend_from_slice(&total.to_be_bytes());
+			// trkn carries two trailing reserved bytes that disk does not
+			if key != BoxType::from_fourcc("disk").unwrap() {
+				bytes.extend_from_slice(&[0, 0]);
+			}
+			(tags::TYPE_IMPLICIT, bytes)
+		},
+		TagValue::Image { format, data } => (match *format {
+			"png" => tags::TYPE_PNG,
+			"bmp" => tags::TYPE_BMP,
+			_ => tags::TYPE_JPEG,
+		}, data.clone()),
+		TagValue::Binary { type_indicator, data } => (*type_indicator, data.clone()),
+	};
+
+	let data = Mp4Box {
+		name: BoxType::DataBox,
+		data: BoxData::Raw([&type_indicator.to_be_bytes()[..], &[0; 4], &bytes].concat()),
+		force_longsize: false,
+	};
+
+	let mut buf = Vec::new();
+	data.write_to(&mut buf).unwrap();
+	buf
+}
+
+/// whether `value` survives being stored as a signed integer of `width` bytes
+fn fits(value: i64, width: usize) -> bool {
+	let bits = width as u32 * 8;
+	value >= -(1 << (bits - 1)) && value < 1 << (bits - 1)
+}
+
+/// A box payload made up of an optional prefix (e.g. FullBox version and flags), child boxes and
+/// any trailing bytes that do not form a box (e.g. QuickTime's 32-bit zero terminator)
+struct Container {
+	prefix: Vec<u8>,
+	children: Vec<Mp4Box>,
+	trailer: Vec<u8>,
+}
+
+impl Container {
+	fn parse(payload: &[u8], prefix_len: usize) -> io::Result<Self> {
+		let mut reader = PayloadReader::new(payload);
+		let prefix = reader.bytes(prefix_len)?.to_vec();
+
+		let mut children = Vec::new();
+		while reader.remaining() >= 8 {
+			let (name, content) = reader.child_box()?;
+			children.push(Mp4Box { name, data: BoxData::Raw(content.to_vec()), force_longsize: false });
+		}
+
+		Ok(Self { prefix, children, trailer: reader.rest().to_vec() })
+	}
+
+	fn child(&mut self, typ: BoxType) -> Option<&mut Mp4Box> {
+		self.children.iter_mut().find(|c| c.name == typ)
+	}
+
+	fn into_payload(self) -> Vec<u8> {
+		let mut payload = self.prefix;
+		for child in &self.children {
+			child.write_to(&mut payload).unwrap();
+		}
+		payload.extend_from_slice(&self.trailer);
+		payload
+	}
+}
+
+fn raw(b: &Mp4Box) -> &[u8] {
+	match &b.data {
+		BoxData::Raw(bytes) => bytes,
+		_ => &[],
+	}
+}
+
+/// handler for a newly created iTunes-style `meta` box
+fn mdir_handler() -> Mp4Box {
+	let mut payload = vec![0; 8]; // version, flags, pre_defined
+	payload.extend_from_slice(b"mdir");
+	payload.extend_from_slice(b"appl");
+	payload.extend_from_slice(&[0; 8]); // reserved
+	payload.push(0); // empty name
+
+	Mp4Box { name: BoxType::HdlrBox, data: BoxData::Raw(payload), force_longsize: false }
+}
+
+/// applies the edit to the `meta/ilst` subtree of a `udta` payload, creating missing boxes on the way
+fn edit_udta(udta_payload: &[u8], edit: &TagEdit) -> io::Result<Vec<u8>> {
+	let mut udta = Container::parse(udta_payload, 0)?;
+
+	if udta.child(BoxType::MetaBox).is_none() {
+		let meta = Container { prefix: vec![0; 4], children: vec![mdir_handler()], trailer: Vec::new() };
+		udta.children.push(Mp4Box { name: BoxType::MetaBox, data: BoxData::Raw(meta.into_payload()), force_longsize: false });
+	}
+	let meta_box = udta.child(BoxType::MetaBox).unwrap();
+	let meta_payload = raw(meta_box);
+	let prefix_len = meta_payload.len() - tags::meta_children(meta_payload).remaining();
+	let mut meta = Container::parse(meta_payload, prefix_len)?;
+
+	if meta.child(BoxType::IlstBox).is_none() {
+		meta.children.push(Mp4Box { name: BoxType::IlstBox, data: BoxData::Raw(Vec::new()), force_longsize: false });
+	}
+	let ilst_box = meta.child(BoxType::IlstBox).unwrap();
+	let mut ilst = Container::parse(raw(ilst_box), 0)?;
+
+	edit_ilst(&mut ilst.children, edit)?;
+
+	ilst_box.data = BoxData::Raw(ilst.into_payload());
+	meta_box.data = BoxData::Raw(meta.into_payload());
+
+	Ok(udta.into_payload())
+}
+
+/// splices `udta` over the given range of the original `moov` bytes (appending it if there is no range) and
+/// patches the moov size; every other byte of moov is kept as it was
+fn splice_moov(moov: &[u8], header_size: usize, range: Option<Range<usize>>, udta: &[u8]) -> io::Result<Vec<u8>> {
+	let range = range.unwrap_or(moov.len()..moov.len());
+	let mut spliced = Vec::with_capacity(moov.len() - range.len() + udta.len());
+	spliced.extend_from_slice(&moov[..range.start]);
+	spliced.extend_from_slice(udta);
+	spliced.extend_from_slice(&moov[range.end..]);
+
+	let size = spliced.len() as u64;
+	if header_size == 16 {
+		spliced[8..16].copy_from_slice(&size.to_be_bytes());
+	} else {
+		let size = u32::try_from(size).map_err(|_| io::Error::new(ErrorKind::InvalidData, "edited moov box no longer fits a 32-bit size"))?;
+		spliced[..4].copy_from_slice(&size.to_be_bytes());
+	}
+
+	Ok(spliced)
+}
+
+fn edit_ilst(items: &mut Vec<Mp4Box>, edit: &TagEdit) -> io::Result<()> {
+	match edit {
+		TagEdit::Set(key, value) => {
+			let data = encode_data(*key, value);
+			match items.iter().position(|item| item.name == *key) {
+				Some(first) => {
+					items[first].data = BoxData::Raw(data);
+					let mut i = 0;
+					items.retain(|item| {
+						i += 1;
+						i - 1 == first || item.name != *key
+					});
+				},
+				None => items.push(Mp4Box { name: *key, data: BoxData::Raw(data), force_longsize: false }),
+			}
+		},
+		TagEdit::Add(key, value) => {
+			let data = encode_data(*key, value);
+			match items.iter_mut().find(|item| item.name == *key) {
+				Some(item) => {
+					let mut content = raw(item).to_vec();
+					content.extend_from_slice(&data);
+					item.data = BoxData::Raw(content);
+				},
+				None => items.push(Mp4Box { name: *key, data: BoxData::Raw(data), force_longsize: false }),
+			}
+		},
+		TagEdit::Remove(key) => {
+			let before = items.len();
+			items.retain(|item| item.name != *key);
+			if items.len() == before {
+				return Err(io::Error::new(ErrorKind::NotFound, format!("file has no {} item", tags::fourcc(*key))));
+			}
+		},
+	}
+
+	Ok(())
+}
+
+/// adds `delta` to every chunk offset at or beyond `from` in an stco/co64 payload, returning the number of offsets changed
+fn shift_chunk_offsets(payload: &mut [u8], width: usize, from: u64, delta: i64) -> io::Result<usize> {
+	let mut count = 0;
+	for entry in payload.get_mut(8..).unwrap_or_default().chunks_exact_mut(width) {
+		let offset = if width == 4 {
+			u32::from_be_bytes(entry.try_into().unwrap()) as u64
+		} else {
+			u64::from_be_bytes(entry.try_into().unwrap())
+		};
+		if offset < from {
+			continue;
+		}
+
+		let shifted = offset.checked_add_signed(delta)
+			.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "chunk offset out of range after shifting"))?;
+		if width == 4 {
+			let shifted = u32::try_from(shifted)
+				.map_err(|_| io::Error::new(ErrorKind::InvalidData, "shifted chunk offset no longer fits in stco"))?;
+			entry.copy_from_slice(&shifted.to_be_bytes());
+		} else {
+			entry.copy_from_slice(&shifted.to_be_bytes());
+		}
+		count += 1;
+	}
+	Ok(count)
+}
+
+/// replaces `file` with a copy whose bytes in `replaced` are swapped for `replacement`
+///
+/// The copy is written next to the original and renamed over it, so a crash leaves either the old or
+/// the new file intact.
+fn replace_range(file: &Path, replaced: Range<u64>, replacement: &[u8]) -> io::Result<()> {
+	let name = file.file_name().ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "path has no file name"))?;
+	let temp = file.with_file_name(format!(".{}.retag", name.to_string_lossy()));
+
+	let result = (|| {
+		let mut input = File::open(file)?;
+		let mut output = File::create(&temp)?;
+		output.set_permissions(input.metadata()?.permissions())?;
+
+		io::copy(&mut (&mut input).take(replaced.start), &mut output)?;
+		output.write_all(replacement)?;
+		input.seek(SeekFrom::Start(replaced.end))?;
+		io::copy(&mut input, &mut output)?;
+		output.sync_all()
+	})();
+
+	match result.and_then(|_| fs::rename(&temp, file)) {
+		Ok(()) => Ok(()),
+		Err(e) => {
+			let _ = fs::remove_file(&temp);
+			Err(e)
+		},
+	}
+}
+
+/// rewrites `moov` with the tag edit applied
+///
+/// Only the `udta` box is rebuilt; the rest of moov is copied byte for byte. The new `moov` is
+/// written over the old one and any `free` boxes directly following it. When it no longer fits
+/// (or leaves a gap too small for a `free` box), the file is rewritten with the rest of the data
+/// moved and chunk offsets updated to match.
+pub fn edit_tags(file: &Path, edit: &TagEdit) -> io::Result<()> {
+	let (index, file_size) = index_file(file, false)?;
+	let moov_index = index.find_top_level(BoxType::MoovBox)
+		.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "file has no moov box"))?;
+	let moov_record = &index.boxes[moov_index];
+	let moov_offset = moov_record.offset;
+	let moov_end = moov_record.end();
+
+	// padding immediately after moov can be reused
+	let mut available_end = moov_end;
+	for record in index.boxes.iter().filter(|b| b.parent.is_none() && b.offset >= moov_end) {
+		if record.offset != available_end || record.name != BoxType::FreeBox {
+			break;
+		}
+		available_end = record.end();
+	}
+	let at_end = available_end >= file_size;
+
+	let mut f = OpenOptions::new().read(true).write(true).open(file)?;
+	let mut moov = vec![0; (moov_end - moov_offset) as usize];
+	f.read_exact_at(&mut moov, moov_offset)?;
+
+	let relative = |offset: u64| (offset - moov_offset) as usize;
+	let udta_record = index.find_child(moov_index, BoxType::UdtaBox).map(|udta| &index.boxes[udta]);
+	let udta_range = udta_record.map(|record| relative(record.offset)..relative(record.end()));
+	let udta_payload = match udta_record {
+		Some(record) => &moov[relative(record.content_offset())..relative(record.end())],
+		None => &[],
+	};
+
+	let udta = Mp4Box { name: BoxType::UdtaBox, data: BoxData::Raw(edit_udta(udta_payload, edit)?), force_longsize: false };
+	let mut udta_bytes = Vec::new();
+	udta.write_to(&mut udta_bytes)?;
+
+	let mut new_moov = splice_moov(&moov, moov_record.header_size as usize, udta_range.clone(), &udta_bytes)?;
+	let new_size = new_moov.len() as u64;
+	let available = available_end - moov_offset;
+
+	if at_end {
+		f.seek(SeekFrom::Start(moov_offset))?;
+		f.write_all(&new_moov)?;
+		f.set_len(moov_offset + new_size)?;
+		log::info!("rewrote trailing moov box ({} B -> {} B)", moov_end - moov_offset, new_size);
+	} else if new_size == available || new_size + 8 <= available {
+		let padding = available - new_size;
+		if padding > 0 {
+			let mut free = Vec::with_capacity(padding as usize);
+			free.extend_from_slice(&(padding as u32).to_be_bytes());
+			free.extend_from_slice(b"free");
+			free.resize(padding as usize, 0);
+			new_moov.extend_from_slice(&free);
+		}
+
+		f.seek(SeekFrom::Start(moov_offset))?;
+		f.write_all(&new_moov)?;
+		log::info!("rewrote moov box ({} B -> {} B) leaving {} B of free padding; media data did not move", moov_end - moov_offset, new_size, padding);
+	} else {
+		// chunk offset tables keep their place in moov, except that those after udta move with the size change
+		let delta = new_size as i64 - (moov_end - moov_offset) as i64;
+		let udta_growth = udta_bytes.len() as i64 - udta_range.as_ref().map_or(0, |r| r.len()) as i64;
+		let udta_end = udta_range.map_or(moov.len(), |r| r.end);
+
+		let mut shifted = 0;
+		let descendants = (moov_index + 1..index.boxes.len()).take_while(|&i| index.boxes[i].offset < moov_end);
+		for i in descendants {
+			let record = &index.boxes[i];
+			let width = match record.name {
+				BoxType::StcoBox => 4,
+				BoxType::Co64Box => 8,
+				_ => continue,
+			};
+			let start = relative(record.content_offset());
+			let end = relative(record.end());
+			let (start, end) = if start >= udta_end {
+				((start as i64 + udta_growth) as usize, (end as i64 + udta_growth) as usize)
+			} else {
+				(start, end)
+			};
+			shifted += shift_chunk_offsets(&mut new_moov[start..end], width, moov_end, delta)?;
+		}
+
+		drop(f);
+		replace_range(file, moov_offset..moov_end, &new_moov)?;
+		log::warn!("not enough free padding after moov: moved {} B of data by {} B and updated {} chunk offsets", file_size - moov_end, delta, shifted);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn mp4_box(typ: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+		[&(payload.len() as u32 + 8).to_be_bytes()[..], typ, payload].concat()
+	}
+
+	#[test]
+	fn edit_keeps_unknown_moov_boxes() {
+		let ftyp = mp4_box(b"ftyp", b"isom\0\0\0\0isom");
+		let mvhd = mp4_box(b"mvhd", &[0; 100]);
+		let iods = mp4_box(b"iods", &[0, 0, 0, 0, 0x10, 0x80, 0x80, 0x80, 0x07, 0x00, 0x4f, 0xff, 0xff]);
+		let tkhd = mp4_box(b"tkhd", &[0; 84]);
+		let tref = mp4_box(b"tref", &mp4_box(b"chap", &2u32.to_be_bytes()));
+
+		// the single chunk starts right after the mdat header, which follows moov
+		let stco_len = 8 + 12;
+		let moov_len = 8 + mvhd.len() + iods.len() + 8 + tkhd.len() + tref.len() + 8 + 8 + 8 + stco_len;
+		let chunk_offset = (ftyp.len() + moov_len + 8) as u32;
+		let stco = mp4_box(b"stco", &[&[0; 4][..], &1u32.to_be_bytes(), &chunk_offset.to_be_bytes()].concat());
+		let stbl = mp4_box(b"stbl", &stco);
+		let mdia = mp4_box(b"mdia", &mp4_box(b"minf", &stbl));
+		let trak = mp4_box(b"trak", &[tkhd, tref, mdia].concat());
+		let moov = mp4_box(b"moov", &[mvhd, iods, trak].concat());
+		assert_eq!(moov.len(), moov_len);
+		let mdat = mp4_box(b"mdat", b"sample data");
+		let original = [&ftyp[..], &moov, &mdat].concat();
+
+		let path = std::env::temp_dir().join(format!("mp4-retag-test-{}.mp4", std::process::id()));
+		fs::write(&path, &original).unwrap();
+		edit_tags(&path, &TagEdit::Set(parse_key("title").unwrap(), TagValue::Text { value: "hi".into() })).unwrap();
+		let edited = fs::read(&path).unwrap();
+
+		let (index, _) = index_file(&path, true).unwrap();
+		let tags = tags::read_tags(&index);
+		fs::remove_file(&path).unwrap();
+
+		let new_moov_len = u32::from_be_bytes(edited[ftyp.len()..ftyp.len() + 4].try_into().unwrap()) as usize;
+		let delta = (new_moov_len - moov_len) as u32;
+
+		// moov keeps every byte apart from its size and the shifted chunk offset, with udta appended
+		let mut expected_moov = moov.clone();
+		expected_moov[..4].copy_from_slice(&(new_moov_len as u32).to_be_bytes());
+		expected_moov[moov_len - 4..].copy_from_slice(&(chunk_offset + delta).to_be_bytes());
+		let new_moov = &edited[ftyp.len()..ftyp.len() + new_moov_len];
+		assert_eq!(&new_moov[..moov_len], &expected_moov[..]);
+		assert_eq!(&new_moov[moov_len + 4..moov_len + 8], b"udta");
+
+		assert_eq!(&edited[..ftyp.len()], &ftyp[..]);
+		assert_eq!(&edited[ftyp.len() + new_moov_len..], &mdat[..]);
+		assert!(tags.iter().any(|tag| tag.key == "©nam" && matches!(&tag.value, TagValue::Text { value } if value == "hi")));
+	}
+}
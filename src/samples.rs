@@ -0,0 +1,157 @@
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+
+use crate::index::{index_file, BoxIndexVisitor};
+use crate::mp4::BoxType;
+use crate::track::{self, parse_mvhd, SampleTime, Track};
+
+const HEADER: &str = "sample,dts,pts,duration,composition_offset,size,chunk,offset,keyframe";
+
+fn seconds(ticks: i64, timescale: u32) -> f64 {
+	if timescale == 0 { 0.0 } else { ticks as f64 / timescale as f64 }
+}
+
+/// prints one row per sample of the track with the given ID, followed by a summary of its timing
+///
+/// With `csv`, rows go to stdout as CSV and the summary is logged so that the output stays machine-readable.
+pub fn samples(file: &Path, track_id: u32, csv: bool) -> io::Result<()> {
+	let (index, _file_size) = index_file(file, true)?;
+	let tracks = track::tracks(&index);
+	let track = tracks.iter()
+		.find(|t| t.tkhd.as_ref().map(|h| h.track_id) == Some(track_id))
+		.ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("file has no track with ID {}", track_id)))?;
+
+	let times = track.sample_times();
+	let locations = track.sample_locations();
+	let sample_count = track.stsz.as_ref().map(|stsz| stsz.sample_count()).unwrap_or(0) as usize;
+	let rows = sample_count.max(times.len());
+
+	let mut stdout = io::stdout().lock();
+	if csv {
+		writeln!(stdout, "{}", HEADER)?;
+	} else {
+		writeln!(stdout, "{:>8} {:>12} {:>12} {:>8} {:>8} {:>8} {:>7} {:>12}  key", "sample", "dts", "pts", "duration", "offset", "size", "chunk", "byte offset")?;
+	}
+
+	for i in 0..rows {
+		let number = i as u32 + 1;
+		let time = times.get(i);
+		let location = locations.get(i);
+		let size = track.stsz.as_ref().and_then(|stsz| match stsz.sample_size {
+			0 => stsz.sizes.entries.get(i).copied(),
+			size if i < sample_count => Some(size),
+			_ => None,
+		});
+		let keyframe = track.is_sync(number);
+
+		let field = |value: Option<String>| value.unwrap_or_default();
+		let dts = field(time.map(|t| t.dts.to_string()));
+		let pts = field(time.map(|t| t.pts().to_string()));
+		let duration = field(time.map(|t| t.duration.to_string()));
+		let offset = field(time.map(|t| t.composition_offset.to_string()));
+		let size = field(size.map(|s| s.to_string()));
+		let chunk = field(location.map(|l| l.chunk.to_string()));
+
+		if csv {
+			let byte_offset = field(location.map(|l| l.offset.to_string()));
+			writeln!(stdout, "{},{},{},{},{},{},{},{},{}", number, dts, pts, duration, offset, size, chunk, byte_offset, keyframe)?;
+		} else {
+			let byte_offset = field(location.map(|l| format!("{:#x}", l.offset)));
+			writeln!(stdout, "{:>8} {:>12} {:>12} {:>8} {:>8} {:>8} {:>7} {:>12}  {}", number, dts, pts, duration, offset, size, chunk, byte_offset, if keyframe { "*" } else { "" })?;
+		}
+	}
+	drop(stdout);
+
+	let summary = summarize(&index, track, &times, sample_count, locations.len());
+	for line in summary {
+		if csv {
+			log::info!("{}", line);
+		} else {
+			println!("{}", line);
+		}
+	}
+
+	Ok(())
+}
+
+/// describes the track's frame rate, composition offsets and durations
+fn summarize(index: &BoxIndexVisitor, track: &Track, times: &[SampleTime], sample_count: usize, located: usize) -> Vec<String> {
+	let mut lines = Vec::new();
+	let timescale = track.mdhd.as_ref().map(|m| m.timescale).unwrap_or(0);
+
+	lines.push(format!("{}: {} samples in stsz, {} in stts, {} located in chunks, timescale {}", track.label(), sample_count, times.len(), located, timescale));
+	if let Some(ctts) = &track.ctts {
+		let ctts_samples: u64 = ctts.entries.iter().map(|e| e.sample_count as u64).sum();
+		if ctts_samples as usize != times.len() {
+			lines.push(format!("ctts covers {} samples but stts covers {}", ctts_samples, times.len()));
+		}
+	}
+	if let Some(stss) = &track.stss {
+		lines.push(format!("{} sync samples", stss.entries.len()));
+	}
+
+	// frame rate, ignoring the final sample whose duration is often a placeholder
+	let deltas: Vec<u32> = times.iter().take(times.len().saturating_sub(1).max(1)).map(|t| t.duration).collect();
+	match (deltas.iter().min(), deltas.iter().max()) {
+		(Some(&min), Some(&max)) if min == max => lines.push(match min {
+			0 => "constant sample duration of 0 (no usable timing)".to_string(),
+			delta => format!("constant rate: {} ticks per sample ({:.3} samples/s)", delta, timescale as f64 / delta as f64),
+		}),
+		(Some(&min), Some(&max)) => {
+			let mean = deltas.iter().map(|&d| d as f64).sum::<f64>() / deltas.len() as f64;
+			lines.push(format!("variable rate: sample durations {}..{} ticks (mean {:.1}, {:.3} samples/s on average)", min, max, mean, if mean > 0.0 { timescale as f64 / mean } else { 0.0 }));
+		},
+		_ => lines.push("no sample timing (stts missing or empty)".to_string()),
+	}
+
+	let offsets: Vec<i32> = times.iter().map(|t| t.composition_offset).collect();
+	match (offsets.iter().min(), offsets.iter().max()) {
+		(Some(0), Some(0)) | (None, None) => lines.push("no composition offsets (presentation order equals decoding order)".to_string()),
+		(Some(&min), Some(&max)) => {
+			let nonzero = offsets.iter().filter(|&&o| o != 0).count();
+			lines.push(format!("composition offsets {}..{} ticks on {} samples (reordered frames)", min, max, nonzero));
+		},
+		_ => {},
+	}
+
+	let total: u64 = times.iter().map(|t| t.duration as u64).sum();
+	lines.push(format!("total sample duration: {} ticks ({:.3} s)", total, seconds(total as i64, timescale)));
+
+	if let (Some(first), Some(end)) = (times.iter().map(|t| t.pts()).min(), times.iter().map(|t| t.pts() + t.duration as i64).max()) {
+		lines.push(format!("presentation span: {:.3} s .. {:.3} s", seconds(first, timescale), seconds(end, timescale)));
+	}
+
+	// compare against the durations declared in the headers; more than one sample's worth of difference is suspicious
+	let tolerance = deltas.iter().copied().max().unwrap_or(0) as f64 / timescale.max(1) as f64;
+	let table_seconds = seconds(total as i64, timescale);
+	let mut compare = |what: &str, declared: f64| {
+		let diff = declared - table_seconds;
+		if diff.abs() > tolerance {
+			lines.push(format!("{} duration is {:.3} s, {:.3} s {} than the sample table: metadata is truncated or inconsistent",
+				what, declared, diff.abs(), if diff > 0.0 { "longer" } else { "shorter" }));
+		} else {
+			lines.push(format!("{} duration is {:.3} s (consistent)", what, declared));
+		}
+	};
+
+	if let Some(mdhd) = &track.mdhd {
+		compare("mdhd", seconds(mdhd.duration as i64, mdhd.timescale));
+	}
+
+	let movie = index.find_top_level(BoxType::MoovBox)
+		.and_then(|moov| index.find_child(moov, BoxType::MvhdBox))
+		.and_then(|mvhd| index.boxes[mvhd].payload.as_deref())
+		.and_then(|payload| parse_mvhd(payload).ok());
+	if let Some(mvhd) = movie {
+		if let Some(tkhd) = &track.tkhd {
+			compare("tkhd", seconds(tkhd.duration as i64, mvhd.timescale));
+		}
+		// the movie lasts as long as its longest track, so only a shorter movie is a problem
+		let movie_seconds = seconds(mvhd.duration as i64, mvhd.timescale);
+		if movie_seconds + tolerance < table_seconds {
+			compare("mvhd", movie_seconds);
+		}
+	}
+
+	lines
+}
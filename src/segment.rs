@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::fragment::{decode, parse_tfdt, parse_tfhd, parse_trun};
+use crate::index::{index_file, BoxIndexVisitor};
+use crate::mp4::BoxType;
+use crate::payload::PayloadReader;
+use crate::times::format_unix;
+use crate::track::{self, parse_ftyp, read_versioned, Table};
+
+/// seconds between 1900-01-01 (the NTP epoch) and 1970-01-01
+const NTP_EPOCH_OFFSET: i64 = 2_208_988_800;
+
+/// A single reference of a `sidx`
+#[derive(Debug, Clone, Copy)]
+pub struct SidxReference {
+	/// the reference points at another `sidx` rather than at media
+	pub is_index: bool,
+	pub referenced_size: u32,
+	pub subsegment_duration: u32,
+	pub starts_with_sap: bool,
+	pub sap_type: u8,
+}
+
+/// Contents of `sidx`
+#[derive(Debug, Clone)]
+pub struct SegmentIndex {
+	pub reference_id: u32,
+	pub timescale: u32,
+	pub earliest_presentation_time: u64,
+	/// distance from the end of the `sidx` to the first referenced byte
+	pub first_offset: u64,
+	pub references: Table<SidxReference>,
+}
+
+/// A single random access point from `tfra`
+#[derive(Debug, Clone, Copy)]
+pub struct RandomAccessEntry {
+	pub time: u64,
+	pub moof_offset: u64,
+	/// 1-based numbers of the traf, trun and sample holding the random access point
+	pub traf_number: u32,
+	pub trun_number: u32,
+	pub sample_number: u32,
+}
+
+/// Contents of `tfra`
+#[derive(Debug, Clone)]
+pub struct TrackFragmentRandomAccess {
+	pub track_id: u32,
+	pub entries: Table<RandomAccessEntry>,
+}
+
+/// Contents of `prft`
+#[derive(Debug, Clone, Copy)]
+pub struct ProducerReferenceTime {
+	pub reference_track_id: u32,
+	/// 64-bit NTP timestamp: seconds since 1900 in the high word, fraction in the low word
+	pub ntp_timestamp: u64,
+	pub media_time: u64,
+}
+
+pub fn parse_sidx(data: &[u8]) -> io::Result<SegmentIndex> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+	let reference_id = reader.u32()?;
+	let timescale = reader.u32()?;
+	let earliest_presentation_time = read_versioned(&mut reader, version)?;
+	let first_offset = read_versioned(&mut reader, version)?;
+	reader.skip(2)?; // reserved
+	let declared = reader.u16()? as u32;
+
+	let available = (reader.remaining() / 12).min(declared as usize);
+	let mut entries = Vec::with_capacity(available);
+	for _ in 0..available {
+		let size = reader.u32()?;
+		let subsegment_duration = reader.u32()?;
+		let sap = reader.u32()?;
+		entries.push(SidxReference {
+			is_index: size >> 31 == 1,
+			referenced_size: size & 0x7fff_ffff,
+			subsegment_duration,
+			starts_with_sap: sap >> 31 == 1,
+			sap_type: (sap >> 28 & 7) as u8,
+		});
+	}
+
+	Ok(SegmentIndex { reference_id, timescale, earliest_presentation_time, first_offset, references: Table { declared, entries } })
+}
+
+pub fn parse_tfra(data: &[u8]) -> io::Result<TrackFragmentRandomAccess> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+	let track_id = reader.u32()?;
+	let sizes = reader.u32()?;
+	let declared = reader.u32()?;
+
+	// traf, trun and sample numbers are each stored in 1 to 4 bytes
+	let (traf_size, trun_size, sample_size) = ((sizes >> 4 & 3) as usize + 1, (sizes >> 2 & 3) as usize + 1, (sizes & 3) as usize + 1);
+	let entry_size = if version == 1 { 16 } else { 8 } + traf_size + trun_size + sample_size;
+	let number = |r: &mut PayloadReader, size: usize| r.bytes(size).map(|b| b.iter().fold(0u32, |acc, &byte| acc << 8 | byte as u32));
+
+	let available = (reader.remaining() / entry_size).min(declared as usize);
+	let mut entries = Vec::with_capacity(available);
+	for _ in 0..available {
+		entries.push(RandomAccessEntry {
+			time: read_versioned(&mut reader, version)?,
+			moof_offset: read_versioned(&mut reader, version)?,
+			traf_number: number(&mut reader, traf_size)?,
+			trun_number: number(&mut reader, trun_size)?,
+			sample_number: number(&mut reader, sample_size)?,
+		});
+	}
+
+	Ok(TrackFragmentRandomAccess { track_id, entries: Table { declared, entries } })
+}
+
+/// reads the size of the enclosing `mfra` from `mfro`
+pub fn parse_mfro(data: &[u8]) -> io::Result<u32> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	reader.u32()
+}
+
+/// reads the number of byte ranges in each subsegment of `ssix`
+pub fn parse_ssix(data: &[u8]) -> io::Result<Vec<u32>> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let subsegment_count = reader.u32()?;
+
+	let mut ranges = Vec::new();
+	for _ in 0..subsegment_count {
+		let range_count = reader.u32()?;
+		reader.skip(4 * range_count as usize)?; // level (8 bits) and range_size (24 bits)
+		ranges.push(range_count);
+	}
+	Ok(ranges)
+}
+
+pub fn parse_prft(data: &[u8]) -> io::Result<ProducerReferenceTime> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+
+	Ok(ProducerReferenceTime {
+		reference_track_id: reader.u32()?,
+		ntp_timestamp: reader.u64()?,
+		media_time: read_versioned(&mut reader, version)?,
+	})
+}
+
+/// describes what lies at `offset`: the top-level box starting there, or the box it falls inside
+fn describe_offset(index: &BoxIndexVisitor, offset: u64) -> String {
+	let top_level = || index.boxes.iter().filter(|b| b.parent.is_none());
+	match top_level().find(|b| b.offset <= offset && offset < b.end()) {
+		Some(b) if b.offset == offset => format!("a {} box", b.name),
+		Some(b) => format!("the middle of the {} box at {:#x}", b.name, b.offset),
+		None => "nothing (past the end of the file)".to_string(),
+	}
+}
+
+/// top-level box starting exactly at `offset`
+fn top_level_at(index: &BoxIndexVisitor, offset: u64) -> Option<usize> {
+	(0..index.boxes.len()).find(|&i| index.boxes[i].parent.is_none() && index.boxes[i].offset == offset)
+}
+
+/// index of the `moof` that a segment starting at `offset` begins with, skipping a leading `styp`
+fn segment_moof(index: &BoxIndexVisitor, offset: u64) -> Option<usize> {
+	let first = top_level_at(index, offset)?;
+	match index.boxes[first].name {
+		BoxType::MoofBox => Some(first),
+		BoxType::StypBox => top_level_at(index, index.boxes[first].end()).filter(|&i| index.boxes[i].name == BoxType::MoofBox),
+		_ => None,
+	}
+}
+
+/// reports styp, prft, sidx and ssix boxes, checks every sidx reference against the moof boxes it should point at,
+/// and checks that the mfra random access table can be found and resolves; `seek` shows which fragment a seek lands on
+pub fn segments(file: &Path, seek: Option<f64>) -> io::Result<()> {
+	let (index, file_size) = index_file(file, true)?;
+	let mut findings = Vec::new();
+	let timescales: HashMap<u32, u32> = track::tracks(&index).iter()
+		.filter_map(|t| Some((t.tkhd.as_ref()?.track_id, t.mdhd.as_ref()?.timescale)))
+		.collect();
+	let seconds = |ticks: u64, timescale: u32| if timescale == 0 { 0.0 } else { ticks as f64 / timescale as f64 };
+
+	let top_level: Vec<usize> = (0..index.boxes.len()).filter(|&i| index.boxes[i].parent.is_none()).collect();
+	let mut previous_sidx: Option<(u64, usize)> = None;
+	for &i in &top_level {
+		let record = &index.boxes[i];
+		match record.name {
+			BoxType::StypBox => match decode(&index, i, parse_ftyp, &mut findings) {
+				Some(styp) => println!("styp at {:#x}: {} ({})", record.offset, styp.major_brand, styp.compatible_brands.join(", ")),
+				None => println!("styp at {:#x}: unreadable", record.offset),
+			},
+
+			BoxType::PrftBox => if let Some(prft) = decode(&index, i, parse_prft, &mut findings) {
+				let unix = (prft.ntp_timestamp >> 32) as i64 - NTP_EPOCH_OFFSET;
+				println!("prft at {:#x}: track {} media time {} ({:.3} s) was produced at {}", record.offset, prft.reference_track_id,
+					prft.media_time, seconds(prft.media_time, timescales.get(&prft.reference_track_id).copied().unwrap_or(0)), format_unix(unix));
+			},
+
+			BoxType::SidxBox => if let Some(sidx) = decode(&index, i, parse_sidx, &mut findings) {
+				check_sidx(&index, i, &sidx, file_size, &mut findings);
+				previous_sidx = Some((record.offset, sidx.references.entries.len()));
+			},
+
+			BoxType::SsixBox => if let Some(ranges) = decode(&index, i, parse_ssix, &mut findings) {
+				println!("ssix at {:#x}: {} subsegments with {} byte ranges", record.offset, ranges.len(), ranges.iter().sum::<u32>());
+				match previous_sidx {
+					Some((_, references)) if references == ranges.len() => {},
+					Some((offset, references)) => findings.push(format!("ssix at {:#x} describes {} subsegments but the sidx at {:#x} has {} references",
+						record.offset, ranges.len(), offset, references)),
+					None => findings.push(format!("ssix at {:#x} is not preceded by a sidx", record.offset)),
+				}
+			},
+
+			_ => {},
+		}
+	}
+
+	let moofs: Vec<usize> = top_level.iter().copied().filter(|&i| index.boxes[i].name == BoxType::MoofBox).collect();
+	let random_access = read_mfra(file, &index, file_size, &moofs, &mut findings)?;
+
+	if let Some(target) = seek {
+		println!();
+		match &random_access {
+			Some(tables) => for tfra in tables {
+				let timescale = timescales.get(&tfra.track_id).copied().unwrap_or(0);
+				let ticks = (target * timescale as f64) as u64;
+				match tfra.entries.entries.iter().rev().find(|e| e.time <= ticks) {
+					Some(entry) => println!("seek to {:.3} s: track {} starts decoding at {:.3} s from the moof at {:#x} (via mfra)",
+						target, tfra.track_id, seconds(entry.time, timescale), entry.moof_offset),
+					None => println!("seek to {:.3} s: track {} has no random access point before it", target, tfra.track_id),
+				}
+			},
+			None => seek_by_scanning(&index, &moofs, target, &timescales),
+		}
+	}
+
+	println!();
+	if findings.is_empty() {
+		println!("no segment index problems found");
+	} else {
+		println!("{} finding{}:", findings.len(), if findings.len() == 1 { "" } else { "s" });
+		for finding in &findings {
+			println!("  {}", finding);
+		}
+	}
+
+	Ok(())
+}
+
+/// prints a sidx and checks that each reference lands on a moof (or a nested sidx) and that its size reaches the next one
+fn check_sidx(index: &BoxIndexVisitor, i: usize, sidx: &SegmentIndex, file_size: u64, findings: &mut Vec<String>) {
+	let record = &index.boxes[i];
+	println!("sidx at {:#x}: track {}, timescale {}, earliest presentation {:.3} s, {} references",
+		record.offset, sidx.reference_id, sidx.timescale,
+		if sidx.timescale == 0 { 0.0 } else { sidx.earliest_presentation_time as f64 / sidx.timescale as f64 }, sidx.references.declared);
+	if sidx.references.is_truncated() {
+		findings.push(format!("sidx at {:#x} declares {} references but only {} fit in the box", record.offset, sidx.references.declared, sidx.references.entries.len()));
+	}
+
+	// a corrupt first_offset or size can point past any 64-bit offset; the remaining references are then meaningless
+	let mut next = record.end().checked_add(sidx.first_offset);
+	let mut time = sidx.earliest_presentation_time;
+	for (n, reference) in sidx.references.entries.iter().enumerate() {
+		let Some((offset, end)) = next.and_then(|offset| Some((offset, offset.checked_add(reference.referenced_size as u64)?))) else {
+			findings.push(format!("sidx at {:#x}: reference {} lies past the largest representable offset (first_offset {})",
+				record.offset, n + 1, sidx.first_offset));
+			break;
+		};
+		println!("  reference {}: {:#x}..{:#x} ({} B), {} at {:.3} s, {}{}", n + 1, offset, end, reference.referenced_size,
+			if reference.is_index { "sidx" } else { "media" },
+			if sidx.timescale == 0 { 0.0 } else { time as f64 / sidx.timescale as f64 },
+			if reference.starts_with_sap { format!("starts with SAP type {}", reference.sap_type) } else { "no SAP at start".to_string() },
+			if reference.subsegment_duration == 0 { ", zero duration" } else { "" });
+
+		let found = if reference.is_index {
+			top_level_at(index, offset).filter(|&j| index.boxes[j].name == BoxType::SidxBox)
+		} else {
+			segment_moof(index, offset)
+		};
+		if end > file_size {
+			findings.push(format!("sidx at {:#x}: reference {} ends at {:#x}, {} B past the end of the file (truncated)", record.offset, n + 1, end, end - file_size));
+		}
+		if found.is_none() && offset < file_size {
+			findings.push(format!("sidx at {:#x}: reference {} should start with a {} at {:#x} but finds {}", record.offset, n + 1,
+				if reference.is_index { "sidx" } else { "moof" }, offset, describe_offset(index, offset)));
+		}
+		// a subsegment must end exactly where a box ends, otherwise the sizes are off
+		if end < file_size && top_level_at(index, end).is_none() {
+			findings.push(format!("sidx at {:#x}: reference {} ends at {:#x}, in {}", record.offset, n + 1, end, describe_offset(index, end)));
+		}
+
+		next = Some(end);
+		time = time.saturating_add(reference.subsegment_duration as u64);
+	}
+}
+
+/// locates `mfra` through the `mfro` at the very end of the file and checks that each of its random access points
+/// resolves to a sample; returns `None` when the table is missing or unusable
+fn read_mfra(file: &Path, index: &BoxIndexVisitor, file_size: u64, moofs: &[usize], findings: &mut Vec<String>) -> io::Result<Option<Vec<TrackFragmentRandomAccess>>> {
+	if moofs.is_empty() {
+		println!("no movie fragments");
+		return Ok(None);
+	}
+	println!();
+
+	// players read the last 16 bytes to find mfro, so check there rather than trusting the index
+	let mut tail = [0u8; 16];
+	let mfra_offset = if file_size >= 16 {
+		let mut f = File::open(file)?;
+		f.seek(SeekFrom::Start(file_size - 16))?;
+		f.read_exact(&mut tail)?;
+		let size = parse_mfro(&tail[8..])? as u64;
+		(BoxType::from(u32::from_be_bytes(tail[4..8].try_into().unwrap())) == BoxType::MfroBox && size <= file_size)
+			.then(|| file_size - size)
+	} else {
+		None
+	};
+
+	let mfra = mfra_offset.and_then(|offset| top_level_at(index, offset)).filter(|&i| index.boxes[i].name == BoxType::MfraBox);
+	let Some(mfra) = mfra else {
+		match (mfra_offset, index.find_top_level(BoxType::MfraBox)) {
+			(Some(offset), _) => findings.push(format!("mfro points at {:#x}, but finds {}", offset, describe_offset(index, offset))),
+			(None, Some(i)) => findings.push(format!("mfra at {:#x} is not followed by an mfro at the end of the file (truncated?)", index.boxes[i].offset)),
+			(None, None) => println!("no mfra at the end of the file; random access needs a scan of all {} moof boxes", moofs.len()),
+		}
+		return Ok(None);
+	};
+
+	let record = &index.boxes[mfra];
+	println!("mfra at {:#x} ({} B)", record.offset, record.size);
+	let mut tables = Vec::new();
+	for tfra in index.children(mfra).filter(|&i| index.boxes[i].name == BoxType::TfraBox) {
+		let Some(tfra) = decode(index, tfra, parse_tfra, findings) else { continue };
+		if tfra.entries.is_truncated() {
+			findings.push(format!("tfra for track {} declares {} entries but only {} fit in the box", tfra.track_id, tfra.entries.declared, tfra.entries.entries.len()));
+		}
+
+		let unresolved: Vec<String> = tfra.entries.entries.iter().enumerate()
+			.filter_map(|(n, entry)| resolve_entry(index, entry).err().map(|e| format!("entry {}: {}", n + 1, e)))
+			.collect();
+		println!("  tfra track {}: {} random access points, {} resolve to a sample", tfra.track_id, tfra.entries.entries.len(), tfra.entries.entries.len() - unresolved.len());
+		for problem in unresolved {
+			findings.push(format!("tfra for track {}: {}", tfra.track_id, problem));
+		}
+		tables.push(tfra);
+	}
+
+	Ok(Some(tables))
+}
+
+/// follows a random access point to its moof, traf, trun and sample
+fn resolve_entry(index: &BoxIndexVisitor, entry: &RandomAccessEntry) -> Result<(), String> {
+	let moof = top_level_at(index, entry.moof_offset)
+		.filter(|&i| index.boxes[i].name == BoxType::MoofBox)
+		.ok_or_else(|| format!("expected a moof at {:#x} but found {}", entry.moof_offset, describe_offset(index, entry.moof_offset)))?;
+	let traf = index.children(moof).filter(|&i| index.boxes[i].name == BoxType::TrafBox).nth(entry.traf_number.saturating_sub(1) as usize)
+		.ok_or_else(|| format!("moof at {:#x} has no traf {}", entry.moof_offset, entry.traf_number))?;
+	let trun = index.children(traf).filter(|&i| index.boxes[i].name == BoxType::TrunBox).nth(entry.trun_number.saturating_sub(1) as usize)
+		.ok_or_else(|| format!("traf {} of the moof at {:#x} has no trun {}", entry.traf_number, entry.moof_offset, entry.trun_number))?;
+	let samples = index.boxes[trun].payload.as_deref().and_then(|payload| parse_trun(payload).ok())
+		.map(|trun| trun.samples.len())
+		.unwrap_or(0);
+	if entry.sample_number == 0 || entry.sample_number as usize > samples {
+		return Err(format!("trun {} of the moof at {:#x} has {} samples, not sample {}", entry.trun_number, entry.moof_offset, samples, entry.sample_number));
+	}
+	Ok(())
+}
+
+/// finds the fragment a seek would land on without mfra, by reading the decode time of every moof
+fn seek_by_scanning(index: &BoxIndexVisitor, moofs: &[usize], target: f64, timescales: &HashMap<u32, u32>) {
+	let mut landing: HashMap<u32, (u64, u64)> = HashMap::new();
+	for &moof in moofs {
+		for traf in index.children(moof).filter(|&i| index.boxes[i].name == BoxType::TrafBox) {
+			let payload = |typ| index.find_child(traf, typ).and_then(|i| index.boxes[i].payload.as_deref());
+			let (Some(Ok(tfhd)), Some(Ok(time))) = (payload(BoxType::TfhdBox).map(parse_tfhd), payload(BoxType::TfdtBox).map(parse_tfdt)) else { continue };
+			let timescale = timescales.get(&tfhd.track_id).copied().unwrap_or(0);
+			if timescale > 0 && time as f64 / timescale as f64 <= target {
+				landing.insert(tfhd.track_id, (time, index.boxes[moof].offset));
+			}
+		}
+	}
+
+	let mut track_ids: Vec<&u32> = timescales.keys().collect();
+	track_ids.sort();
+	for track_id in track_ids {
+		match landing.get(track_id) {
+			Some(&(time, offset)) => println!("seek to {:.3} s: track {} starts decoding at {:.3} s from the moof at {:#x} (found by scanning {} moof boxes)",
+				target, track_id, time as f64 / timescales[track_id] as f64, offset, moofs.len()),
+			None => println!("seek to {:.3} s: track {} has no fragment starting before it", target, track_id),
+		}
+	}
+}
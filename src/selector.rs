@@ -0,0 +1,148 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::index::BoxIndexVisitor;
+use crate::mp4::BoxType;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+	/// a box of exactly this type
+	Box(BoxType),
+	/// `*`: any single box
+	Any,
+	/// `//`: zero or more intermediate boxes
+	Descendants,
+}
+
+/// Path pattern selecting boxes by ancestry
+///
+/// Components are separated by `/`, `*` matches any single box and `//` matches any number of
+/// intermediate boxes. Selectors match at any depth unless they start with `/`, so `stsz`,
+/// `moov//stsz` and `/moov/trak/mdia/minf/stbl/stsz` all select the same boxes in a typical file.
+#[derive(Clone, Debug)]
+pub struct Selector {
+	source: String,
+	steps: Vec<Step>,
+}
+
+#[derive(Debug)]
+pub struct SelectorError(String);
+
+impl std::error::Error for SelectorError {}
+
+impl fmt::Display for SelectorError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "invalid selector: {}", self.0)
+	}
+}
+
+impl FromStr for Selector {
+	type Err = SelectorError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut steps = Vec::new();
+		let anchored = s.starts_with('/');
+		if !anchored {
+			steps.push(Step::Descendants);
+		}
+
+		let components: Vec<&str> = s.trim_start_matches('/').split('/').collect();
+		for (i, component) in components.iter().enumerate() {
+			match *component {
+				"" if i + 1 == components.len() => return Err(SelectorError(format!("'{}' must not end with '/'", s))),
+				"" => {
+					if steps.last() != Some(&Step::Descendants) {
+						steps.push(Step::Descendants);
+					}
+				},
+				"*" => steps.push(Step::Any),
+				name => steps.push(Step::Box(BoxType::from_fourcc(name).ok_or_else(|| SelectorError(format!("'{}' is not a four-character box type", name)))?)),
+			}
+		}
+
+		Ok(Self { source: s.to_string(), steps })
+	}
+}
+
+impl fmt::Display for Selector {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.source)
+	}
+}
+
+impl Selector {
+	/// whether the box at `index` (identified by its full ancestry) matches this selector
+	pub fn matches(&self, index: &BoxIndexVisitor, i: usize) -> bool {
+		let mut path = Vec::new();
+		let mut current = Some(i);
+		while let Some(c) = current {
+			path.push(index.boxes[c].name);
+			current = index.boxes[c].parent;
+		}
+		path.reverse();
+
+		matches_steps(&self.steps, &path)
+	}
+}
+
+fn matches_steps(steps: &[Step], path: &[BoxType]) -> bool {
+	match steps.split_first() {
+		None => path.is_empty(),
+		Some((Step::Descendants, rest)) => (0..=path.len()).any(|skip| matches_steps(rest, &path[skip..])),
+		Some((step, rest)) => match path.split_first() {
+			Some((typ, path_rest)) => (*step == Step::Any || *step == Step::Box(*typ)) && matches_steps(rest, path_rest),
+			None => false,
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::index::BoxRecord;
+
+	/// indexes `boxes` as (type, parent) pairs in pre-order
+	fn index(boxes: &[(&str, Option<usize>)]) -> BoxIndexVisitor {
+		let mut index = BoxIndexVisitor::default();
+		index.boxes = boxes
+			.iter()
+			.map(|&(name, parent)| BoxRecord { name: BoxType::from_fourcc(name).unwrap(), parent, offset: 0, header_size: 8, size: 8, declared_size: 8, payload: None })
+			.collect();
+		index
+	}
+
+	fn selected(selector: &str, index: &BoxIndexVisitor) -> Vec<usize> {
+		let selector: Selector = selector.parse().unwrap();
+		(0..index.boxes.len()).filter(|&i| selector.matches(index, i)).collect()
+	}
+
+	#[test]
+	fn within_selectors_match_by_ancestry() {
+		let index = index(&[
+			("ftyp", None),
+			("moov", None),
+			("trak", Some(1)),
+			("mdia", Some(2)),
+			("minf", Some(3)),
+			("stbl", Some(4)),
+			("stsz", Some(5)),
+			("mdat", None),
+		]);
+
+		assert_eq!(selected("moov", &index), [1]);
+		assert_eq!(selected("moov//stsz", &index), [6]);
+		assert_eq!(selected("mdat", &index), [7]);
+		assert_eq!(selected("stsz", &index), [6]);
+		assert_eq!(selected("/moov/trak/mdia/minf/stbl/stsz", &index), [6]);
+		assert_eq!(selected("moov/*", &index), [2]);
+		assert!(selected("moov/stsz", &index).is_empty());
+		assert!(selected("/stsz", &index).is_empty());
+	}
+
+	#[test]
+	fn malformed_selectors_are_rejected() {
+		assert!("moov/".parse::<Selector>().is_err());
+		assert!("moovs".parse::<Selector>().is_err());
+		assert!("moov//".parse::<Selector>().is_err());
+	}
+}
@@ -81,7 +81,7 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 				// read entries ({sample_count: u32, composition_offset: u32})
 				let mut entries = Vec::new();
 				let mut entry_bytes = [0u8; 8];
-				while let Ok(_) = reader.read_exact(&mut entry_bytes) {
+				while reader.read_exact(&mut entry_bytes).is_ok() {
 					entries.push(entry_bytes);
 				}
 
@@ -89,10 +89,10 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 					log::warn!("correcting ctts table length: metadata says {} entries, but should actually be {} entries", entry_count, entries.len());
 				}
 
-				data.write(&version_flags)?;
-				data.write(&(entries.len() as u32).to_be_bytes())?;
+				data.write_all(&version_flags)?;
+				data.write_all(&(entries.len() as u32).to_be_bytes())?;
 				for entry in entries {
-					data.write(&entry)?;
+					data.write_all(&entry)?;
 				}
 			},
 
@@ -116,7 +116,7 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 					// samples have variable sizes that are stored in the table
 					// read entries ({sample_size: u32})
 					let mut entry_bytes = [0u8; 4];
-					while let Ok(_) = reader.read_exact(&mut entry_bytes) {
+					while reader.read_exact(&mut entry_bytes).is_ok() {
 						entries.push(entry_bytes);
 					}
 				}
@@ -125,11 +125,11 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 					log::warn!("correcting stsz table length: metadata says {} entries, but should actually be {} entries", entry_count, entries.len());
 				}
 
-				data.write(&version_flags)?;
-				data.write(&sample_size_bytes)?;
-				data.write(&(entries.len() as u32).to_be_bytes())?;
+				data.write_all(&version_flags)?;
+				data.write_all(&sample_size_bytes)?;
+				data.write_all(&(entries.len() as u32).to_be_bytes())?;
 				for entry in entries {
-					data.write(&entry)?;
+					data.write_all(&entry)?;
 				}
 			},
 
@@ -146,7 +146,7 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 				// read entries ({chunk_offset: u32})
 				let mut entries = Vec::new();
 				let mut entry_bytes = [0u8; 4];
-				while let Ok(_) = reader.read_exact(&mut entry_bytes) {
+				while reader.read_exact(&mut entry_bytes).is_ok() {
 					entries.push(entry_bytes);
 				}
 
@@ -154,10 +154,10 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 					log::warn!("correcting stco table length: metadata says {} entries, but should actually be {} entries", entry_count, entries.len());
 				}
 
-				data.write(&version_flags)?;
-				data.write(&(entries.len() as u32).to_be_bytes())?;
+				data.write_all(&version_flags)?;
+				data.write_all(&(entries.len() as u32).to_be_bytes())?;
 				for entry in entries {
-					data.write(&entry)?;
+					data.write_all(&entry)?;
 				}
 			},
 
@@ -174,7 +174,7 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 				// read entries ({first_chunk: u32, samples_per_chunk: u32, sample_description_id: u32})
 				let mut entries = Vec::new();
 				let mut entry_bytes = [0u8; 12];
-				while let Ok(_) = reader.read_exact(&mut entry_bytes) {
+				while reader.read_exact(&mut entry_bytes).is_ok() {
 					entries.push(entry_bytes);
 				}
 
@@ -182,10 +182,10 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 					log::warn!("correcting stsc table length: metadata says {} entries, but should actually be {} entries", entry_count, entries.len());
 				}
 
-				data.write(&version_flags)?;
-				data.write(&(entries.len() as u32).to_be_bytes())?;
+				data.write_all(&version_flags)?;
+				data.write_all(&(entries.len() as u32).to_be_bytes())?;
 				for entry in entries {
-					data.write(&entry)?;
+					data.write_all(&entry)?;
 				}
 			},
 
@@ -202,7 +202,7 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 				// read entries ({sample_count: u32, sample_duration: u32})
 				let mut entries = Vec::new();
 				let mut entry_bytes = [0u8; 8];
-				while let Ok(_) = reader.read_exact(&mut entry_bytes) {
+				while reader.read_exact(&mut entry_bytes).is_ok() {
 					entries.push(entry_bytes);
 				}
 
@@ -210,10 +210,10 @@ impl<'a> Mp4Visitor for StripVisitor<'a> {
 					log::warn!("correcting stts table length: metadata says {} entries, but should actually be {} entries", entry_count, entries.len());
 				}
 
-				data.write(&version_flags)?;
-				data.write(&(entries.len() as u32).to_be_bytes())?;
+				data.write_all(&version_flags)?;
+				data.write_all(&(entries.len() as u32).to_be_bytes())?;
 				for entry in entries {
-					data.write(&entry)?;
+					data.write_all(&entry)?;
 				}
 			},
 
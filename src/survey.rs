@@ -0,0 +1,158 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::index::index_file_partial;
+use crate::mp4::BoxType;
+use crate::track::{self, parse_ftyp, parse_mvhd};
+
+const HEADER: &str = "path,size,major_brand,compatible_brands,moov_offset,moov_size,tracks,codecs,duration_s,faststart,diagnostics,error";
+
+/// Statistics gathered for a single surveyed file
+#[derive(Default)]
+struct SurveyRow {
+	path: String,
+	size: Option<u64>,
+	major_brand: Option<String>,
+	compatible_brands: Vec<String>,
+	moov_offset: Option<u64>,
+	moov_size: Option<u64>,
+	tracks: Option<usize>,
+	codecs: Vec<String>,
+	duration_s: Option<f64>,
+	faststart: Option<bool>,
+	diagnostics: Option<usize>,
+	error: Option<String>,
+}
+
+impl SurveyRow {
+	fn fields(&self) -> Vec<String> {
+		fn opt<T: ToString>(value: &Option<T>) -> String {
+			value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+		}
+
+		vec![
+			self.path.clone(),
+			opt(&self.size),
+			opt(&self.major_brand),
+			self.compatible_brands.join(" "),
+			opt(&self.moov_offset),
+			opt(&self.moov_size),
+			opt(&self.tracks),
+			self.codecs.join(" "),
+			self.duration_s.map(|d| format!("{:.3}", d)).unwrap_or_default(),
+			opt(&self.faststart),
+			opt(&self.diagnostics),
+			opt(&self.error),
+		]
+	}
+}
+
+/// walks `dir` and writes one CSV row of structural statistics per file
+pub fn survey(dir: &Path, extensions: &[String], writer: &mut impl Write) -> io::Result<()> {
+	let mut files = Vec::new();
+	collect_files(dir, extensions, &mut files)?;
+
+	writeln!(writer, "{}", HEADER)?;
+
+	for file in files {
+		log::info!("surveying {}", file.display());
+		let row = survey_file(&file);
+		let fields: Vec<String> = row.fields().iter().map(|field| csv_escape(field)).collect();
+		writeln!(writer, "{}", fields.join(","))?;
+	}
+
+	writer.flush()
+}
+
+/// recursively gathers regular files (without following symlinks), in a stable order
+fn collect_files(dir: &Path, extensions: &[String], files: &mut Vec<PathBuf>) -> io::Result<()> {
+	let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+	entries.sort_by_key(|e| e.file_name());
+
+	for entry in entries {
+		let file_type = entry.file_type()?;
+		let path = entry.path();
+
+		if file_type.is_dir() {
+			if let Err(e) = collect_files(&path, extensions, files) {
+				log::warn!("unable to read directory {}: {}", path.display(), e);
+			}
+		} else if file_type.is_file() {
+			let matches = extensions.is_empty() || path.extension()
+				.and_then(|ext| ext.to_str())
+				.is_some_and(|ext| extensions.iter().any(|wanted| wanted.eq_ignore_ascii_case(ext)));
+
+			if matches {
+				files.push(path);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// gathers statistics for a single file; failures are recorded in the `error` column
+fn survey_file(file: &Path) -> SurveyRow {
+	let mut row = SurveyRow {
+		path: file.display().to_string(),
+		..Default::default()
+	};
+
+	// damaged files are what the survey is for, so whatever parsed before an error still fills in the row
+	let (index, size, error) = match index_file_partial(file, true) {
+		Ok(result) => result,
+		Err(e) => {
+			row.size = file.metadata().ok().map(|m| m.len());
+			row.error = Some(e.to_string());
+			return row;
+		},
+	};
+
+	row.size = Some(size);
+	row.error = error.map(|e| e.to_string());
+
+	if let Some(ftyp) = index.find_top_level(BoxType::FtypBox).and_then(|i| index.boxes[i].payload.as_deref()) {
+		if let Ok(ftyp) = parse_ftyp(ftyp) {
+			row.major_brand = Some(ftyp.major_brand.trim().to_string());
+			row.compatible_brands = ftyp.compatible_brands.iter().map(|b| b.trim().to_string()).collect();
+		}
+	}
+
+	if let Some(moov) = index.find_top_level(BoxType::MoovBox) {
+		row.moov_offset = Some(index.boxes[moov].offset);
+		row.moov_size = Some(index.boxes[moov].size);
+
+		if let Some(mdat) = index.find_top_level(BoxType::MdatBox) {
+			row.faststart = Some(index.boxes[moov].offset < index.boxes[mdat].offset);
+		}
+	}
+
+	let tracks = track::tracks(&index);
+	row.tracks = Some(tracks.len());
+
+	for codec in tracks.iter().flat_map(|t| t.codecs()) {
+		if !row.codecs.contains(&codec) {
+			row.codecs.push(codec);
+		}
+	}
+
+	let mvhd = index.find_all(BoxType::MvhdBox).first().and_then(|&i| index.boxes[i].payload.as_deref()).map(parse_mvhd);
+	if let Some(Ok(mvhd)) = mvhd {
+		if mvhd.timescale != 0 {
+			row.duration_s = Some(mvhd.duration as f64 / mvhd.timescale as f64);
+		}
+	}
+
+	row.diagnostics = Some(index.diagnostics() + tracks.iter().map(|t| t.errors.len()).sum::<usize>());
+
+	row
+}
+
+fn csv_escape(field: &str) -> String {
+	if field.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_string()
+	}
+}
@@ -0,0 +1,376 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Serializer};
+
+use crate::index::BoxIndexVisitor;
+use crate::mp4::BoxType;
+use crate::payload::PayloadReader;
+
+/// well-known `data` box type indicators
+pub const TYPE_IMPLICIT: u32 = 0;
+pub const TYPE_UTF8: u32 = 1;
+pub const TYPE_UTF16: u32 = 2;
+pub const TYPE_JPEG: u32 = 13;
+pub const TYPE_PNG: u32 = 14;
+pub const TYPE_FLOAT32: u32 = 23;
+pub const TYPE_FLOAT64: u32 = 24;
+pub const TYPE_SIGNED_INT: u32 = 21;
+pub const TYPE_UNSIGNED_INT: u32 = 22;
+pub const TYPE_BMP: u32 = 27;
+
+/// descriptive names for common `ilst` item keys
+pub const KEY_NAMES: &[(&str, &str)] = &[
+	("©nam", "title"),
+	("©ART", "artist"),
+	("aART", "album artist"),
+	("©alb", "album"),
+	("©day", "date"),
+	("©gen", "genre"),
+	("gnre", "genre"),
+	("©cmt", "comment"),
+	("desc", "description"),
+	("ldes", "long description"),
+	("©too", "encoder"),
+	("©wrt", "composer"),
+	("cprt", "copyright"),
+	("trkn", "track number"),
+	("disk", "disc number"),
+	("tmpo", "tempo"),
+	("covr", "cover art"),
+	("©xyz", "location"),
+	("©mak", "make"),
+	("©mod", "model"),
+	("©swr", "software"),
+	("com.apple.quicktime.location.ISO6709", "location"),
+	("com.apple.quicktime.make", "make"),
+	("com.apple.quicktime.model", "model"),
+	("com.apple.quicktime.software", "software"),
+	("com.apple.quicktime.creationdate", "creation date"),
+	("com.apple.quicktime.content.identifier", "content identifier"),
+	("com.android.manufacturer", "make"),
+	("com.android.model", "model"),
+	("com.android.version", "software"),
+];
+
+/// ID3v1 genres, as referenced (1-based) by `gnre` items
+pub const GENRES: &[&str] = &[
+	"Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop", "Jazz", "Metal",
+	"New Age", "Oldies", "Other", "Pop", "R&B", "Rap", "Reggae", "Rock", "Techno", "Industrial",
+	"Alternative", "Ska", "Death Metal", "Pranks", "Soundtrack", "Euro-Techno", "Ambient", "Trip-Hop", "Vocal", "Jazz+Funk",
+	"Fusion", "Trance", "Classical", "Instrumental", "Acid", "House", "Game", "Sound Clip", "Gospel", "Noise",
+	"AlternRock", "Bass", "Soul", "Punk", "Space", "Meditative", "Instrumental Pop", "Instrumental Rock", "Ethnic", "Gothic",
+	"Darkwave", "Techno-Industrial", "Electronic", "Pop-Folk", "Eurodance", "Dream", "Southern Rock", "Comedy", "Cult", "Gangsta",
+	"Top 40", "Christian Rap", "Pop/Funk", "Jungle", "Native American", "Cabaret", "New Wave", "Psychadelic", "Rave", "Showtunes",
+	"Trailer", "Lo-Fi", "Tribal", "Acid Punk", "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll", "Hard Rock",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TagValue {
+	Text { value: String },
+	Integer { value: i64 },
+	Float { value: f64 },
+	/// `trkn`/`disk` style "n of m" values
+	Index { number: u16, total: u16 },
+	Image {
+		format: &'static str,
+		#[serde(rename = "size", serialize_with = "serialize_len")]
+		data: Vec<u8>,
+	},
+	Binary {
+		type_indicator: u32,
+		#[serde(rename = "size", serialize_with = "serialize_len")]
+		data: Vec<u8>,
+	},
+}
+
+fn serialize_len<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.serialize_u64(data.len() as u64)
+}
+
+impl std::fmt::Display for TagValue {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			TagValue::Text { value } => write!(f, "{}", value),
+			TagValue::Integer { value } => write!(f, "{}", value),
+			TagValue::Float { value } => write!(f, "{}", value),
+			TagValue::Index { number, total: 0 } => write!(f, "{}", number),
+			TagValue::Index { number, total } => write!(f, "{} of {}", number, total),
+			TagValue::Image { format, data } => write!(f, "[{} image, {} B]", format, data.len()),
+			TagValue::Binary { type_indicator, data } => write!(f, "[type {} data, {} B]", type_indicator, data.len()),
+		}
+	}
+}
+
+/// A single decoded metadata item
+#[derive(Debug, Clone, Serialize)]
+pub struct Tag {
+	pub key: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub name: Option<&'static str>,
+	#[serde(flatten)]
+	pub value: TagValue,
+}
+
+/// renders a four-character code, treating each byte as Latin-1 so that `©` survives
+pub fn fourcc(typ: BoxType) -> String {
+	u32::from(typ).to_be_bytes().iter().map(|&b| b as char).collect()
+}
+
+/// content of `moov/udta/meta`, if present
+pub fn udta_meta(index: &BoxIndexVisitor) -> Option<&[u8]> {
+	let moov = index.find_top_level(BoxType::MoovBox)?;
+	let udta = index.find_child(moov, BoxType::UdtaBox)?;
+	let payload = index.boxes[udta].payload.as_deref()?;
+
+	PayloadReader::new(payload).child_boxes()
+		.find(|(typ, _)| *typ == BoxType::MetaBox)
+		.map(|(_, content)| content)
+}
+
+/// children of a `meta` box, which is a FullBox in ISO files but a plain box in QuickTime files
+pub fn meta_children(meta: &[u8]) -> PayloadReader<'_> {
+	let mut reader = PayloadReader::new(meta);
+
+	// a QuickTime-style meta box starts directly with a child box (normally hdlr)
+	let is_full_box = meta.len() >= 8 && meta[..4] == [0, 0, 0, 0] && meta[4..8] != *b"hdlr";
+	if is_full_box {
+		let _ = reader.skip(4);
+	}
+
+	reader
+}
+
+/// decodes every item in the `ilst` box of the given `meta` payload
+pub fn read_ilst(meta: &[u8]) -> Vec<(BoxType, Vec<TagValue>)> {
+	let Some((_, ilst)) = meta_children(meta).child_boxes().find(|(typ, _)| *typ == BoxType::IlstBox) else {
+		return Vec::new();
+	};
+
+	PayloadReader::new(ilst).child_boxes()
+		.map(|(key, item)| {
+			let values = PayloadReader::new(item).child_boxes()
+				.filter(|(typ, _)| *typ == BoxType::DataBox)
+				.filter_map(|(_, data)| match decode_data(&fourcc(key), data) {
+					Ok(value) => Some(value),
+					Err(e) => {
+						log::warn!("unable to decode {} item: {}", fourcc(key), e);
+						None
+					},
+				})
+				.collect();
+
+			(key, values)
+		})
+		.collect()
+}
+
+/// decodes the content of a `data` box according to its type indicator
+fn decode_data(key: &str, data: &[u8]) -> io::Result<TagValue> {
+	let mut reader = PayloadReader::new(data);
+	let type_indicator = reader.u32()? & 0x00ff_ffff;
+	reader.skip(4)?; // locale
+	let value = reader.rest();
+
+	Ok(match (type_indicator, key) {
+		(TYPE_UTF8, _) => TagValue::Text { value: String::from_utf8_lossy(value).into_owned() },
+		(TYPE_UTF16, _) => {
+			let units: Vec<u16> = value.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+			TagValue::Text { value: String::from_utf16_lossy(&units) }
+		},
+		(TYPE_JPEG, _) => TagValue::Image { format: "jpeg", data: value.to_vec() },
+		(TYPE_PNG, _) => TagValue::Image { format: "png", data: value.to_vec() },
+		(TYPE_BMP, _) => TagValue::Image { format: "bmp", data: value.to_vec() },
+		(TYPE_FLOAT32, _) if value.len() == 4 => TagValue::Float { value: f32::from_be_bytes(value.try_into().unwrap()) as f64 },
+		(TYPE_FLOAT64, _) if value.len() == 8 => TagValue::Float { value: f64::from_be_bytes(value.try_into().unwrap()) },
+		(TYPE_SIGNED_INT | TYPE_UNSIGNED_INT, _) if (1..=8).contains(&value.len()) => TagValue::Integer {
+			value: be_integer(value, type_indicator == TYPE_SIGNED_INT),
+		},
+		(TYPE_IMPLICIT, "trkn" | "disk") if value.len() >= 6 => TagValue::Index {
+			number: u16::from_be_bytes([value[2], value[3]]),
+			total: u16::from_be_bytes([value[4], value[5]]),
+		},
+		(TYPE_IMPLICIT, "gnre") if value.len() == 2 => {
+			let id = u16::from_be_bytes([value[0], value[1]]) as usize;
+			match GENRES.get(id.wrapping_sub(1)) {
+				Some(genre) => TagValue::Text { value: genre.to_string() },
+				None => TagValue::Integer { value: id as i64 },
+			}
+		},
+		(TYPE_IMPLICIT, "covr") if value.starts_with(&[0xff, 0xd8]) => TagValue::Image { format: "jpeg", data: value.to_vec() },
+		(TYPE_IMPLICIT, "covr") if value.starts_with(b"\x89PNG") => TagValue::Image { format: "png", data: value.to_vec() },
+		_ => TagValue::Binary { type_indicator, data: value.to_vec() },
+	})
+}
+
+/// sign- or zero-extends a big-endian integer of 1 to 8 bytes
+fn be_integer(value: &[u8], signed: bool) -> i64 {
+	let negative = signed && value[0] & 0x80 != 0;
+	let mut bytes = [if negative { 0xff } else { 0 }; 8];
+	bytes[8 - value.len()..].copy_from_slice(value);
+	i64::from_be_bytes(bytes)
+}
+
+/// decodes the QuickTime `mdta` tags stored in `moov/meta`, whose `ilst` items refer to entries in a `keys` box
+pub fn read_mdta(meta: &[u8]) -> Vec<(String, Vec<TagValue>)> {
+	let children: Vec<_> = meta_children(meta).child_boxes().collect();
+	let (Some((_, keys)), Some((_, ilst))) = (
+		children.iter().find(|(typ, _)| *typ == BoxType::from_fourcc("keys").unwrap()),
+		children.iter().find(|(typ, _)| *typ == BoxType::IlstBox),
+	) else {
+		return Vec::new();
+	};
+
+	let keys = match read_keys(keys) {
+		Ok(keys) => keys,
+		Err(e) => {
+			log::warn!("unable to read keys box: {}", e);
+			return Vec::new();
+		},
+	};
+
+	PayloadReader::new(ilst).child_boxes()
+		.filter_map(|(item_index, item)| {
+			// items are named after the 1-based index of their key
+			let Some(key) = keys.get((u32::from(item_index) as usize).wrapping_sub(1)) else {
+				log::warn!("ilst item refers to missing key {}", u32::from(item_index));
+				return None;
+			};
+
+			let values = PayloadReader::new(item).child_boxes()
+				.filter(|(typ, _)| *typ == BoxType::DataBox)
+				.filter_map(|(_, data)| decode_data(key, data).map_err(|e| log::warn!("unable to decode {} item: {}", key, e)).ok())
+				.collect();
+
+			Some((key.clone(), values))
+		})
+		.collect()
+}
+
+fn read_keys(keys: &[u8]) -> io::Result<Vec<String>> {
+	let mut reader = PayloadReader::new(keys);
+	reader.version_flags()?;
+	let count = reader.u32()?;
+
+	(0..count)
+		.map(|_| {
+			let size = reader.u32()? as usize;
+			let _namespace = reader.u32()?;
+			let value = reader.bytes(size.saturating_sub(8))?;
+			Ok(String::from_utf8_lossy(value).into_owned())
+		})
+		.collect()
+}
+
+/// decodes QuickTime user data text items (e.g. `©xyz`, `©mak`) stored directly in `moov/udta`, each
+/// holding one or more length-prefixed strings with a language code
+pub fn read_udta_text(udta: &[u8]) -> Vec<(BoxType, Vec<TagValue>)> {
+	PayloadReader::new(udta).child_boxes()
+		.filter(|(typ, _)| u32::from(*typ) >> 24 == 0xa9)
+		.filter_map(|(key, content)| {
+			let mut reader = PayloadReader::new(content);
+			let mut values = Vec::new();
+			while reader.remaining() >= 4 {
+				let (Ok(len), Ok(_language)) = (reader.u16(), reader.u16()) else { break };
+				let Ok(text) = reader.bytes(len as usize) else {
+					log::warn!("{} user data text is truncated", fourcc(key));
+					break;
+				};
+				values.push(TagValue::Text { value: String::from_utf8_lossy(text).trim_end_matches('\0').to_string() });
+			}
+
+			(!values.is_empty()).then_some((key, values))
+		})
+		.collect()
+}
+
+fn key_name(key: &str) -> Option<&'static str> {
+	KEY_NAMES.iter().find(|(k, _)| *k == key).map(|(_, name)| *name)
+}
+
+/// decodes the iTunes-style tags in `moov/udta/meta/ilst`, QuickTime `mdta` tags in `moov/meta` and
+/// QuickTime user data text items in `moov/udta`
+pub fn read_tags(index: &BoxIndexVisitor) -> Vec<Tag> {
+	let mut tags = Vec::new();
+	let mut push = |key: String, values: Vec<TagValue>| {
+		let name = key_name(&key);
+		tags.extend(values.into_iter().map(|value| Tag { key: key.clone(), name, value }));
+	};
+
+	if let Some(meta) = udta_meta(index) {
+		for (key, values) in read_ilst(meta) {
+			push(fourcc(key), values);
+		}
+	}
+
+	let moov = index.find_top_level(BoxType::MoovBox);
+	if let Some(meta) = moov.and_then(|moov| index.find_child(moov, BoxType::MetaBox)).and_then(|meta| index.boxes[meta].payload.as_deref()) {
+		for (key, values) in read_mdta(meta) {
+			push(key, values);
+		}
+	}
+
+	if let Some(udta) = moov.and_then(|moov| index.find_child(moov, BoxType::UdtaBox)).and_then(|udta| index.boxes[udta].payload.as_deref()) {
+		for (key, values) in read_udta_text(udta) {
+			push(fourcc(key), values);
+		}
+	}
+
+	tags
+}
+
+pub fn print_tags(tags: &[Tag], json: bool) -> io::Result<()> {
+	if json {
+		println!("{}", serde_json::to_string_pretty(tags)?);
+		return Ok(());
+	}
+
+	if tags.is_empty() {
+		println!("no tags found");
+	}
+
+	for tag in tags {
+		match tag.name {
+			Some(name) => println!("{} ({}): {}", tag.key, name, tag.value),
+			None => println!("{}: {}", tag.key, tag.value),
+		}
+	}
+
+	Ok(())
+}
+
+/// writes every embedded cover image; additional images are numbered after the first
+pub fn export_covers(tags: &[Tag], path: &Path) -> io::Result<()> {
+	let images: Vec<(&str, &Vec<u8>)> = tags.iter()
+		.filter(|tag| tag.key == "covr")
+		.filter_map(|tag| match &tag.value {
+			TagValue::Image { format, data } => Some((*format, data)),
+			_ => None,
+		})
+		.collect();
+
+	if images.is_empty() {
+		return Err(io::Error::new(io::ErrorKind::NotFound, "file has no embedded cover art"));
+	}
+
+	for (n, (format, data)) in images.iter().enumerate() {
+		let target = if n == 0 {
+			path.to_path_buf()
+		} else {
+			numbered_path(path, n + 1, format)
+		};
+
+		fs::write(&target, data)?;
+		log::info!("wrote {} B of cover art to {}", data.len(), target.display());
+	}
+
+	Ok(())
+}
+
+/// `cover.jpg` => `cover-2.png`
+fn numbered_path(path: &Path, n: usize, extension: &str) -> PathBuf {
+	let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+	path.with_file_name(format!("{}-{}.{}", stem, n, extension))
+}
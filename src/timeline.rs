@@ -0,0 +1,165 @@
+use std::io;
+use std::path::Path;
+
+use crate::index::index_file;
+use crate::mp4::BoxType;
+use crate::track::{self, parse_mvhd, Track};
+
+fn seconds(ticks: i64, timescale: u32) -> f64 {
+	if timescale == 0 { 0.0 } else { ticks as f64 / timescale as f64 }
+}
+
+/// Where a track's media lands on the movie timeline
+struct Placement {
+	label: String,
+	handler: Option<String>,
+	/// movie time (in seconds) at which the first presented sample appears; `None` if nothing is presented
+	start: Option<f64>,
+}
+
+/// prints each track's edit list mapped onto the movie timeline, followed by the resulting A/V offset and any problems
+pub fn timeline(file: &Path) -> io::Result<()> {
+	let (index, _file_size) = index_file(file, true)?;
+	let movie_timescale = index.find_top_level(BoxType::MoovBox)
+		.and_then(|moov| index.find_child(moov, BoxType::MvhdBox))
+		.and_then(|mvhd| index.boxes[mvhd].payload.as_deref())
+		.and_then(|payload| match parse_mvhd(payload) {
+			Ok(mvhd) => Some(mvhd.timescale),
+			Err(e) => {
+				log::warn!("unable to decode mvhd: {}", e);
+				None
+			},
+		});
+	let Some(movie_timescale) = movie_timescale else {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "file has no readable moov/mvhd box"));
+	};
+
+	let mut findings = Vec::new();
+	let mut placements = Vec::new();
+	for track in track::tracks(&index) {
+		placements.push(track_timeline(&track, movie_timescale, &mut findings));
+		println!();
+	}
+
+	// lip sync depends on where the first video and first audio samples land
+	let find = |handler: &str| placements.iter().find(|p| p.handler.as_deref() == Some(handler));
+	if let (Some(video), Some(audio)) = (find("vide"), find("soun")) {
+		if let (Some(video_start), Some(audio_start)) = (video.start, audio.start) {
+			let offset = audio_start - video_start;
+			println!("A/V offset: {} starts {:.3} s {} {}", audio.label, offset.abs(), if offset < 0.0 { "before" } else { "after" }, video.label);
+		}
+	}
+
+	if findings.is_empty() {
+		println!("no edit list problems found");
+	} else {
+		println!("{} finding{}:", findings.len(), if findings.len() == 1 { "" } else { "s" });
+		for finding in &findings {
+			println!("  {}", finding);
+		}
+	}
+
+	Ok(())
+}
+
+/// prints one track's edits, returning where its first presented sample lands
+fn track_timeline(track: &Track, movie_timescale: u32, findings: &mut Vec<String>) -> Placement {
+	let label = track.label();
+	let media_timescale = track.mdhd.as_ref().map(|m| m.timescale).unwrap_or(0);
+	let times = track.sample_times();
+	let first_pts = times.iter().map(|t| t.pts()).min();
+	let media_end = times.iter().map(|t| t.pts().saturating_add(t.duration as i64)).max().unwrap_or(0);
+	// a sample's worth of slack, since edits are commonly rounded to the movie timescale
+	let slack = times.iter().map(|t| t.duration as i64).max().unwrap_or(0);
+
+	println!("{}: media timescale {}, samples present {:.3} s .. {:.3} s", label, media_timescale,
+		seconds(first_pts.unwrap_or(0), media_timescale), seconds(media_end, media_timescale));
+
+	let mut placement = Placement { label: label.clone(), handler: track.handler.clone(), start: None };
+
+	let Some(elst) = &track.elst else {
+		println!("  no edit list: media time 0 is movie time 0");
+		if let Some(pts) = first_pts.filter(|&pts| pts > 0) {
+			findings.push(format!("{}: first sample is presented at {:.3} s and nothing shifts it to the start of the movie",
+				label, seconds(pts, media_timescale)));
+		}
+		placement.start = first_pts.map(|pts| seconds(pts, media_timescale));
+		return placement;
+	};
+
+	if elst.is_truncated() {
+		findings.push(format!("{}: elst declares {} edits but only {} fit in the box", label, elst.declared, elst.entries.len()));
+	}
+
+	let mut movie_time = 0u64;
+	let mut overflowed = false;
+	for (i, edit) in elst.entries.iter().enumerate() {
+		// corrupt 64-bit durations can run the timeline past what the arithmetic below can hold
+		let Some(next_movie_time) = movie_time.checked_add(edit.segment_duration).filter(|&t| i64::try_from(t).is_ok()) else {
+			findings.push(format!("{}: edit {} ends past the largest representable movie time; later edits are not checked", label, i + 1));
+			overflowed = true;
+			break;
+		};
+		let movie_start = seconds(movie_time as i64, movie_timescale);
+		let movie_end = seconds(next_movie_time as i64, movie_timescale);
+		let media_duration = i64::try_from(edit.segment_duration as u128 * media_timescale as u128 / movie_timescale.max(1) as u128).unwrap_or(i64::MAX);
+		let media_stop = edit.media_time.checked_add(media_duration);
+		print!("  edit {}: movie {:.3} s .. {:.3} s: ", i + 1, movie_start, movie_end);
+
+		if edit.media_time == -1 {
+			println!("empty (nothing presented)");
+		} else if edit.media_rate == 0.0 {
+			println!("dwell on media time {:.3} s", seconds(edit.media_time, media_timescale));
+			if placement.start.is_none() {
+				placement.start = Some(movie_start);
+			}
+		} else {
+			let media_start = edit.media_time;
+			println!("media {:.3} s .. {:.3} s{}", seconds(media_start, media_timescale), seconds(media_start.saturating_add(media_duration), media_timescale),
+				if edit.media_rate != 1.0 { format!(" at rate {}", edit.media_rate) } else { String::new() });
+
+			if placement.start.is_none() {
+				// samples before the edit's media time are decoded but never shown
+				let shown = first_pts.unwrap_or(0).max(media_start);
+				placement.start = Some(movie_start + seconds(shown - media_start, media_timescale));
+			}
+		}
+
+		if edit.media_time < -1 {
+			findings.push(format!("{}: edit {} has invalid media time {}", label, i + 1, edit.media_time));
+		} else if edit.media_time >= media_end && edit.media_time != -1 {
+			findings.push(format!("{}: edit {} starts at media time {:.3} s, past the end of the track's samples ({:.3} s)",
+				label, i + 1, seconds(edit.media_time, media_timescale), seconds(media_end, media_timescale)));
+		} else if edit.media_time >= 0 && edit.media_rate != 0.0 {
+			match media_stop {
+				None => findings.push(format!("{}: edit {} ends past the largest representable media time", label, i + 1)),
+				Some(stop) if stop > media_end.saturating_add(slack) => findings.push(format!("{}: edit {} runs {:.3} s past the end of the track's samples",
+					label, i + 1, seconds(stop - media_end, media_timescale))),
+				Some(_) => {},
+			}
+		}
+		if edit.segment_duration == 0 && i + 1 < elst.entries.len() {
+			findings.push(format!("{}: edit {} has zero duration but is not the last edit", label, i + 1));
+		}
+
+		movie_time = next_movie_time;
+	}
+
+	let leading_empty = elst.entries.iter().take_while(|e| e.media_time == -1).fold(0u64, |sum, e| sum.saturating_add(e.segment_duration));
+	if leading_empty > 0 {
+		println!("  presentation delayed by {:.3} s of empty edits", seconds(leading_empty.min(i64::MAX as u64) as i64, movie_timescale));
+	}
+	match placement.start {
+		Some(start) => println!("  first sample presented at movie time {:.3} s", start),
+		None => findings.push(format!("{}: edit list presents no media", label)),
+	}
+
+	if let Some(tkhd) = track.tkhd.as_ref().filter(|_| !overflowed) {
+		if tkhd.duration.abs_diff(movie_time) > 1 {
+			findings.push(format!("{}: tkhd duration {:.3} s does not match the edit list total of {:.3} s", label,
+				seconds(tkhd.duration as i64, movie_timescale), seconds(movie_time as i64, movie_timescale)));
+		}
+	}
+
+	placement
+}
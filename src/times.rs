@@ -0,0 +1,281 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::index::index_file;
+use crate::mp4::BoxType;
+use crate::tags;
+use crate::track::{self, parse_mvhd};
+
+/// seconds between 1904-01-01 (the MP4/QuickTime epoch) and 1970-01-01 (the Unix epoch)
+const MAC_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// 2000-01-01 in Unix time; earlier values are unlikely to be genuine capture dates
+const Y2K: i64 = 946_684_800;
+
+/// metadata tags holding a creation date
+const DATE_KEYS: &[&str] = &["com.apple.quicktime.creationdate", "©day"];
+
+/// A creation or modification time from one of the header boxes
+struct HeaderTime {
+	source: String,
+	kind: &'static str,
+	version: u8,
+	/// seconds since 1904
+	raw: u64,
+}
+
+impl HeaderTime {
+	/// the time in Unix seconds, or None when a 64-bit value does not fit
+	fn unix(&self) -> Option<i64> {
+		i64::try_from(self.raw).ok()?.checked_sub(MAC_EPOCH_OFFSET)
+	}
+}
+
+/// converts days since 1970-01-01 into a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	// Howard Hinnant's days_from_civil algorithm, inverted
+	let z = days + 719_468;
+	let era = z.div_euclid(146_097);
+	let doe = z.rem_euclid(146_097);
+	let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+	(year, month, day)
+}
+
+/// converts a civil date into days since 1970-01-01, or None when the year is too far out to represent
+fn days_from_civil(year: i64, month: u32, day: u32) -> Option<i64> {
+	let year = if month <= 2 { year.checked_sub(1)? } else { year };
+	let era = year.div_euclid(400);
+	let yoe = year.rem_euclid(400);
+	let mp = (month as i64 + 9) % 12;
+	let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	era.checked_mul(146_097)?.checked_add(doe - 719_468)
+}
+
+/// formats Unix time as `YYYY-MM-DD HH:MM:SS UTC`
+pub fn format_unix(secs: i64) -> String {
+	let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+	let time = secs.rem_euclid(86_400);
+	format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+/// parses an ISO 8601 date-time such as `2022-11-05T14:03:22-0700` or `2021-06-01T12:00:00Z` into Unix time;
+/// date-only values and missing offsets are not enough to pin down an instant, and out-of-range values give None
+pub fn parse_iso8601(s: &str) -> Option<i64> {
+	let s = s.trim();
+	let (date, time) = s.split_once(['T', ' '])?;
+
+	let mut date_parts = date.splitn(3, '-');
+	let year: i64 = date_parts.next()?.parse().ok()?;
+	let month: u32 = date_parts.next()?.parse().ok()?;
+	let day: u32 = date_parts.next()?.parse().ok()?;
+	if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+		return None;
+	}
+
+	let (clock, offset) = if let Some(clock) = time.strip_suffix('Z') {
+		(clock, 0)
+	} else {
+		let split = time.rfind(['+', '-'])?;
+		let (clock, zone) = time.split_at(split);
+		let sign = if zone.starts_with('-') { -1 } else { 1 };
+		let digits: String = zone[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+		if digits.len() != 4 {
+			return None;
+		}
+		let hours: i64 = digits[..2].parse().ok()?;
+		let minutes: i64 = digits[2..].parse().ok()?;
+		(clock, sign * (hours * 3600 + minutes * 60))
+	};
+
+	let mut clock_parts = clock.splitn(3, ':');
+	let hour: i64 = clock_parts.next()?.parse().ok()?;
+	let minute: i64 = clock_parts.next()?.parse().ok()?;
+	let second: f64 = clock_parts.next().unwrap_or("0").parse().ok()?;
+	if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0.0..61.0).contains(&second) {
+		return None;
+	}
+
+	// a garbage year can push the result past i64
+	days_from_civil(year, month, day)?.checked_mul(86_400)?.checked_add(hour * 3600 + minute * 60 + second as i64 - offset)
+}
+
+/// describes a difference in seconds, noting when it is within `tolerance` of a time zone offset
+fn describe_difference(diff: i64, tolerance: i64) -> String {
+	let abs = diff.unsigned_abs();
+	let text = if abs < 120 {
+		format!("{} s", abs)
+	} else if abs < 2 * 86_400 {
+		format!("{}h{:02}m", abs / 3600, abs / 60 % 60)
+	} else {
+		format!("{} days", abs / 86_400)
+	};
+
+	// local time written as UTC (or vice versa) is off by a whole number of quarter hours
+	let zone = (abs + 450) / 900 * 900;
+	if (900..=14 * 3600).contains(&zone) && zone.abs_diff(abs) <= tolerance.unsigned_abs() {
+		format!("{} (consistent with a {}:{:02} time zone offset)", text, zone / 3600, zone / 60 % 60)
+	} else {
+		text
+	}
+}
+
+/// decodes every creation/modification time in the file and reports inconsistencies between them
+pub fn times(file: &Path, tolerance: u64) -> io::Result<()> {
+	let (index, _file_size) = index_file(file, true)?;
+	let now = UNIX_EPOCH.elapsed().map(|d| d.as_secs() as i64).unwrap_or(i64::MAX);
+	let tolerance = i64::try_from(tolerance).unwrap_or(i64::MAX);
+
+	let movie = index.find_top_level(BoxType::MoovBox)
+		.and_then(|moov| index.find_child(moov, BoxType::MvhdBox))
+		.and_then(|mvhd| index.boxes[mvhd].payload.as_deref())
+		.map(parse_mvhd);
+
+	let mut header_times = Vec::new();
+	match movie {
+		Some(Ok(mvhd)) => {
+			header_times.push(HeaderTime { source: "mvhd".to_string(), kind: "creation", version: mvhd.version, raw: mvhd.creation_time });
+			header_times.push(HeaderTime { source: "mvhd".to_string(), kind: "modification", version: mvhd.version, raw: mvhd.modification_time });
+		},
+		Some(Err(e)) => log::warn!("unable to decode mvhd: {}", e),
+		None => log::warn!("file has no moov/mvhd box"),
+	}
+
+	for track in track::tracks(&index) {
+		let label = track.label();
+		if let Some(tkhd) = &track.tkhd {
+			header_times.push(HeaderTime { source: format!("{} tkhd", label), kind: "creation", version: tkhd.version, raw: tkhd.creation_time });
+			header_times.push(HeaderTime { source: format!("{} tkhd", label), kind: "modification", version: tkhd.version, raw: tkhd.modification_time });
+		}
+		if let Some(mdhd) = &track.mdhd {
+			header_times.push(HeaderTime { source: format!("{} mdhd", label), kind: "creation", version: mdhd.version, raw: mdhd.creation_time });
+			header_times.push(HeaderTime { source: format!("{} mdhd", label), kind: "modification", version: mdhd.version, raw: mdhd.modification_time });
+		}
+	}
+
+	let mut findings = Vec::new();
+
+	for time in &header_times {
+		let description = match time.unix() {
+			_ if time.raw == 0 => "unset".to_string(),
+			Some(unix) => format_unix(unix),
+			None => "out of range".to_string(),
+		};
+		println!("{:<32} {:<12} v{} {:>12}  {}", time.source, time.kind, time.version, time.raw, description);
+
+		match time.unix() {
+			_ if time.raw == 0 => findings.push(format!("{} {} time is zero", time.source, time.kind)),
+			None => findings.push(format!("{} {} time {} is out of range", time.source, time.kind, time.raw)),
+			Some(unix) if unix < 0 && (Y2K..=now).contains(&(time.raw as i64)) => {
+				findings.push(format!("{} {} time is before 1970; read with the Unix epoch it would be {}, so it was probably written relative to 1970 instead of 1904", time.source, time.kind, format_unix(time.raw as i64)));
+			},
+			Some(unix) if unix > now => findings.push(format!("{} {} time {} is in the future", time.source, time.kind, format_unix(unix))),
+			Some(_) => {},
+		}
+	}
+
+	// each box's modification should not precede its creation
+	for pair in header_times.chunks_exact(2) {
+		let (creation, modification) = (&pair[0], &pair[1]);
+		if let (Some(created), Some(modified)) = (creation.unix(), modification.unix()) {
+			if creation.raw != 0 && modification.raw != 0 && modified < created {
+				findings.push(format!("{} was modified {} before it was created", creation.source, describe_difference(created - modified, tolerance)));
+			}
+		}
+	}
+
+	// out-of-range times were reported above and take no part in comparisons
+	let movie_creation = header_times.iter()
+		.find(|t| t.source == "mvhd" && t.kind == "creation" && t.raw != 0)
+		.and_then(HeaderTime::unix);
+	if let Some(movie_creation) = movie_creation {
+		for time in header_times.iter().filter(|t| t.source != "mvhd" && t.kind == "creation") {
+			if let Some(created) = time.unix().filter(|&created| created > movie_creation) {
+				findings.push(format!("{} was created {} after the movie", time.source, describe_difference(created - movie_creation, tolerance)));
+			}
+		}
+	}
+
+	for tag in tags::read_tags(&index).iter().filter(|tag| DATE_KEYS.contains(&tag.key.as_str())) {
+		let tags::TagValue::Text { value } = &tag.value else { continue };
+		match parse_iso8601(value) {
+			Some(unix) => {
+				println!("{:<32} {:<12} {} = {}", tag.key, "tag", value, format_unix(unix));
+				if let Some(movie_creation) = movie_creation {
+					let diff = unix.saturating_sub(movie_creation);
+					if diff.saturating_abs() > tolerance {
+						findings.push(format!("{} differs from the mvhd creation time by {}", tag.key, describe_difference(diff, tolerance)));
+					}
+				}
+			},
+			None => println!("{:<32} {:<12} {} (no time or zone, or out of range; not compared)", tag.key, "tag", value),
+		}
+	}
+
+	let mtime = fs::metadata(file)?.modified().ok()
+		.and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+		.map(|d| d.as_secs() as i64);
+	if let Some(mtime) = mtime {
+		println!("{:<32} {:<12} {}", "filesystem", "mtime", format_unix(mtime));
+
+		let movie_modification = header_times.iter()
+			.find(|t| t.source == "mvhd" && t.kind == "modification" && t.raw != 0)
+			.and_then(HeaderTime::unix);
+		if let Some(movie_creation) = movie_creation {
+			if mtime.saturating_add(tolerance) < movie_creation {
+				findings.push(format!("file mtime is {} before the movie was created", describe_difference(movie_creation - mtime, tolerance)));
+			}
+		}
+		if let Some(movie_modification) = movie_modification {
+			let diff = mtime - movie_modification;
+			if diff.abs() > tolerance {
+				findings.push(format!("file mtime differs from the mvhd modification time by {}", describe_difference(diff, tolerance)));
+			}
+		}
+	}
+
+	if findings.is_empty() {
+		println!("no inconsistencies found");
+	} else {
+		println!("{} finding{}:", findings.len(), if findings.len() == 1 { "" } else { "s" });
+		for finding in &findings {
+			println!("  {}", finding);
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn iso8601_instants() {
+		assert_eq!(parse_iso8601("1970-01-01T00:00:00Z"), Some(0));
+		assert_eq!(parse_iso8601("2021-06-01T12:00:00Z"), Some(1_622_548_800));
+		assert_eq!(parse_iso8601("2022-11-05T14:03:22-0700"), Some(1_667_682_202));
+		assert_eq!(parse_iso8601("2022-11-05T23:03:22+02:00"), Some(1_667_682_202));
+		assert_eq!(parse_iso8601(" 2022-11-05 21:03:22.750Z "), Some(1_667_682_202));
+		assert_eq!(parse_iso8601("1969-12-31T23:59:59Z"), Some(-1));
+		assert_eq!(format_unix(1_667_682_202), "2022-11-05 21:03:22 UTC");
+	}
+
+	#[test]
+	fn incomplete_or_out_of_range_iso8601() {
+		assert_eq!(parse_iso8601("2022-11-05"), None);
+		assert_eq!(parse_iso8601("2022-11-05T14:03:22"), None);
+		assert_eq!(parse_iso8601("2022-13-05T14:03:22Z"), None);
+		assert_eq!(parse_iso8601("2022-11-05T24:00:00Z"), None);
+		assert_eq!(parse_iso8601("2022-11-05T14:03:22+7"), None);
+		assert_eq!(parse_iso8601("99999999999999999-01-01T00:00:00Z"), None);
+		assert_eq!(parse_iso8601("-9223372036854775808-01-01T00:00:00Z"), None);
+	}
+}
@@ -0,0 +1,501 @@
+use std::io;
+
+use crate::index::BoxIndexVisitor;
+use crate::mp4::BoxType;
+use crate::payload::PayloadReader;
+
+/// Entries of a sample table alongside the entry count declared in its header
+#[derive(Debug, Clone)]
+pub struct Table<T> {
+	pub declared: u32,
+	/// entries that actually fit within the box (at most `declared`)
+	pub entries: Vec<T>,
+}
+
+impl<T> Table<T> {
+	pub fn is_truncated(&self) -> bool {
+		self.entries.len() < self.declared as usize
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SttsEntry {
+	pub sample_count: u32,
+	pub sample_delta: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CttsEntry {
+	pub sample_count: u32,
+	/// composition time minus decoding time; only version 1 boxes may use negative offsets, but
+	/// version 0 boxes are read as signed too since many writers do the same
+	pub sample_offset: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StscEntry {
+	pub first_chunk: u32,
+	pub samples_per_chunk: u32,
+	pub sample_description_index: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct SampleSizes {
+	/// size shared by every sample, or 0 if sizes are listed individually
+	pub sample_size: u32,
+	pub sizes: Table<u32>,
+}
+
+impl SampleSizes {
+	pub fn sample_count(&self) -> u32 {
+		if self.sample_size == 0 {
+			self.sizes.entries.len() as u32
+		} else {
+			self.sizes.declared
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct MovieHeader {
+	pub version: u8,
+	/// seconds since 1904-01-01 UTC
+	pub creation_time: u64,
+	/// seconds since 1904-01-01 UTC
+	pub modification_time: u64,
+	pub timescale: u32,
+	/// duration in the movie timescale
+	pub duration: u64,
+	pub next_track_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileType {
+	pub major_brand: String,
+	pub compatible_brands: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackHeader {
+	pub version: u8,
+	/// seconds since 1904-01-01 UTC
+	pub creation_time: u64,
+	/// seconds since 1904-01-01 UTC
+	pub modification_time: u64,
+	pub track_id: u32,
+	/// duration in the movie (`mvhd`) timescale
+	pub duration: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaHeader {
+	pub version: u8,
+	/// seconds since 1904-01-01 UTC
+	pub creation_time: u64,
+	/// seconds since 1904-01-01 UTC
+	pub modification_time: u64,
+	pub timescale: u32,
+	/// duration in the media (`mdhd`) timescale
+	pub duration: u64,
+}
+
+/// A sample description from `stsd`, e.g. `avc1` or `mp4a`
+#[derive(Debug, Clone)]
+pub struct SampleEntry {
+	pub format: BoxType,
+	/// content following the entry's box header
+	pub data: Vec<u8>,
+}
+
+/// A single edit from `elst`
+#[derive(Debug, Clone, Copy)]
+pub struct EditEntry {
+	/// length of the edit in the movie timescale
+	pub segment_duration: u64,
+	/// start of the edit in the media timescale, or -1 for an empty edit
+	pub media_time: i64,
+	/// playback rate; 0 holds (dwells on) the frame at `media_time`
+	pub media_rate: f64,
+}
+
+/// Decoding and composition timing of a single sample, in the media timescale
+#[derive(Debug, Clone, Copy)]
+pub struct SampleTime {
+	pub dts: u64,
+	pub duration: u32,
+	pub composition_offset: i32,
+}
+
+impl SampleTime {
+	pub fn pts(&self) -> i64 {
+		self.dts as i64 + self.composition_offset as i64
+	}
+}
+
+/// Location of a single sample within the file
+#[derive(Debug, Clone, Copy)]
+pub struct SampleLocation {
+	/// 1-based sample number
+	pub number: u32,
+	/// 1-based chunk number
+	pub chunk: u32,
+	pub offset: u64,
+	pub size: u32,
+}
+
+impl SampleLocation {
+	pub fn end(&self) -> u64 {
+		self.offset.saturating_add(self.size as u64)
+	}
+}
+
+/// Sample tables and identifying information for a single `trak` box
+#[derive(Debug, Default)]
+pub struct Track {
+	/// index of the `trak` box in the originating `BoxIndexVisitor`
+	pub trak: usize,
+	pub tkhd: Option<TrackHeader>,
+	pub elst: Option<Table<EditEntry>>,
+	pub mdhd: Option<MediaHeader>,
+	pub handler: Option<String>,
+	pub stsd: Option<Table<SampleEntry>>,
+	pub stts: Option<Table<SttsEntry>>,
+	pub ctts: Option<Table<CttsEntry>>,
+	pub stsc: Option<Table<StscEntry>>,
+	pub stsz: Option<SampleSizes>,
+	/// 1-based numbers of the sync samples; absent when every sample is a sync sample
+	pub stss: Option<Table<u32>>,
+	pub chunk_offsets: Option<Table<u64>>,
+	/// problems encountered while decoding the boxes above
+	pub errors: Vec<String>,
+}
+
+impl Track {
+	/// four-character codes of the track's sample descriptions, e.g. `avc1`
+	pub fn codecs(&self) -> Vec<String> {
+		self.stsd.iter().flat_map(|stsd| stsd.entries.iter()).map(|e| e.format.to_string()).collect()
+	}
+
+	/// whether the 1-based sample `number` is a sync sample (keyframe)
+	pub fn is_sync(&self, number: u32) -> bool {
+		self.stss.as_ref().is_none_or(|stss| stss.entries.binary_search(&number).is_ok())
+	}
+
+	/// human-readable label such as `track 1 (vide)`
+	pub fn label(&self) -> String {
+		format!("track {} ({})",
+			self.tkhd.as_ref().map(|tkhd| tkhd.track_id.to_string()).unwrap_or("?".into()),
+			self.handler.as_deref().unwrap_or("????"))
+	}
+
+	/// resolves the byte range of every sample using the sample-to-chunk, chunk offset, and sample size tables
+	pub fn sample_locations(&self) -> Vec<SampleLocation> {
+		let (Some(stsc), Some(stsz), Some(chunk_offsets)) = (&self.stsc, &self.stsz, &self.chunk_offsets) else {
+			return Vec::new();
+		};
+
+		let sample_count = stsz.sample_count();
+		let mut locations = Vec::with_capacity(sample_count as usize);
+		let mut run = 0;
+
+		for (i, &chunk_offset) in chunk_offsets.entries.iter().enumerate() {
+			let chunk = i as u32 + 1;
+
+			// advance to the stsc run containing this chunk
+			while run + 1 < stsc.entries.len() && stsc.entries[run + 1].first_chunk <= chunk {
+				run += 1;
+			}
+			let Some(entry) = stsc.entries.get(run) else { break };
+			if entry.first_chunk > chunk {
+				continue;
+			}
+
+			let mut offset = chunk_offset;
+			for _ in 0..entry.samples_per_chunk {
+				let number = locations.len() as u32 + 1;
+				if number > sample_count {
+					return locations;
+				}
+
+				let size = if stsz.sample_size == 0 {
+					stsz.sizes.entries[number as usize - 1]
+				} else {
+					stsz.sample_size
+				};
+
+				locations.push(SampleLocation { number, chunk, offset, size });
+				offset = offset.saturating_add(size as u64);
+			}
+		}
+
+		locations
+	}
+
+	/// expands `stts` and `ctts` into per-sample times, in decoding order; a corrupt `sample_count`
+	/// can declare billions of samples, so expansion stops at the `stsz` sample count
+	pub fn sample_times(&self) -> Vec<SampleTime> {
+		let (Some(stts), Some(stsz)) = (&self.stts, &self.stsz) else {
+			return Vec::new();
+		};
+
+		let limit = stsz.sample_count() as usize;
+		let mut times = Vec::new();
+		let mut dts = 0u64;
+		for entry in &stts.entries {
+			for _ in 0..(entry.sample_count as usize).min(limit - times.len()) {
+				times.push(SampleTime { dts, duration: entry.sample_delta, composition_offset: 0 });
+				dts = dts.saturating_add(entry.sample_delta as u64);
+			}
+		}
+
+		if let Some(ctts) = &self.ctts {
+			let offsets = ctts.entries.iter().flat_map(|e| std::iter::repeat_n(e.sample_offset, e.sample_count as usize));
+			for (time, offset) in times.iter_mut().zip(offsets) {
+				time.composition_offset = offset;
+			}
+		}
+
+		times
+	}
+
+	/// total duration of all samples according to `stts`, in the media timescale
+	pub fn stts_duration(&self) -> Option<u64> {
+		self.stts.as_ref().map(|stts| stts.entries.iter().fold(0u64, |sum, e| sum.saturating_add(e.sample_count as u64 * e.sample_delta as u64)))
+	}
+}
+
+/// decodes the tracks of every `moov/trak` box in an index built with payloads
+pub fn tracks(index: &BoxIndexVisitor) -> Vec<Track> {
+	index.find_all(BoxType::TrakBox).into_iter()
+		.map(|trak| {
+			let mut track = Track { trak, ..Default::default() };
+
+			track.tkhd = decode(index, trak, &[BoxType::TkhdBox], &mut track.errors, parse_tkhd);
+			track.elst = decode(index, trak, &[BoxType::EdtsBox, BoxType::ElstBox], &mut track.errors, parse_elst);
+			track.mdhd = decode(index, trak, &[BoxType::MdiaBox, BoxType::MdhdBox], &mut track.errors, parse_mdhd);
+			track.handler = decode(index, trak, &[BoxType::MdiaBox, BoxType::HdlrBox], &mut track.errors, parse_hdlr);
+
+			let stbl = [BoxType::MdiaBox, BoxType::MinfBox, BoxType::StblBox];
+			let table = |typ| [stbl[0], stbl[1], stbl[2], typ];
+
+			track.stsd = decode(index, trak, &table(BoxType::StsdBox), &mut track.errors, parse_stsd);
+			track.stts = decode(index, trak, &table(BoxType::SttsBox), &mut track.errors, parse_stts);
+			track.ctts = decode(index, trak, &table(BoxType::CttsBox), &mut track.errors, parse_ctts);
+			track.stsc = decode(index, trak, &table(BoxType::StscBox), &mut track.errors, parse_stsc);
+			track.stsz = decode(index, trak, &table(BoxType::StszBox), &mut track.errors, parse_stsz);
+			track.stss = decode(index, trak, &table(BoxType::StssBox), &mut track.errors, parse_stss);
+			track.chunk_offsets = decode(index, trak, &table(BoxType::StcoBox), &mut track.errors, parse_stco)
+				.or_else(|| decode(index, trak, &table(BoxType::Co64Box), &mut track.errors, parse_co64));
+
+			track
+		})
+		.collect()
+}
+
+/// locates the box at `path` beneath `parent` and decodes its payload, recording any failure
+fn decode<T>(index: &BoxIndexVisitor, parent: usize, path: &[BoxType], errors: &mut Vec<String>, parse: fn(&[u8]) -> io::Result<T>) -> Option<T> {
+	let target = index.find_path(parent, path)?;
+	let payload = index.boxes[target].payload.as_deref()?;
+
+	match parse(payload) {
+		Ok(value) => Some(value),
+		Err(e) => {
+			errors.push(format!("unable to decode {} box: {}", index.boxes[target].name, e));
+			None
+		},
+	}
+}
+
+/// reads up to `declared` fixed-size entries, stopping early if the payload runs out
+fn read_entries<T>(reader: &mut PayloadReader, declared: u32, entry_size: usize, mut read: impl FnMut(&mut PayloadReader) -> io::Result<T>) -> io::Result<Table<T>> {
+	let available = reader.remaining() / entry_size;
+	let count = available.min(declared as usize);
+
+	let mut entries = Vec::with_capacity(count);
+	for _ in 0..count {
+		entries.push(read(reader)?);
+	}
+
+	Ok(Table { declared, entries })
+}
+
+pub fn parse_ftyp(data: &[u8]) -> io::Result<FileType> {
+	let mut reader = PayloadReader::new(data);
+	let major_brand = String::from_utf8_lossy(reader.bytes(4)?).into_owned();
+	reader.skip(4)?; // minor version
+
+	let mut compatible_brands = Vec::new();
+	while reader.remaining() >= 4 {
+		compatible_brands.push(String::from_utf8_lossy(reader.bytes(4)?).into_owned());
+	}
+
+	Ok(FileType { major_brand, compatible_brands })
+}
+
+/// reads a field that is 32 bits wide in version 0 boxes and 64 bits wide in version 1 boxes
+pub fn read_versioned(reader: &mut PayloadReader, version: u8) -> io::Result<u64> {
+	match version {
+		0 => reader.u32().map(u64::from),
+		1 => reader.u64(),
+		v => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported version {}", v))),
+	}
+}
+
+pub fn parse_mvhd(data: &[u8]) -> io::Result<MovieHeader> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+
+	let creation_time = read_versioned(&mut reader, version)?;
+	let modification_time = read_versioned(&mut reader, version)?;
+	let timescale = reader.u32()?;
+	let duration = read_versioned(&mut reader, version)?;
+
+	// rate (4 B), volume (2 B), reserved (10 B), matrix (36 B), pre_defined (24 B)
+	reader.skip(76)?;
+	let next_track_id = reader.u32()?;
+
+	Ok(MovieHeader { version, creation_time, modification_time, timescale, duration, next_track_id })
+}
+
+pub fn parse_tkhd(data: &[u8]) -> io::Result<TrackHeader> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+
+	let creation_time = read_versioned(&mut reader, version)?;
+	let modification_time = read_versioned(&mut reader, version)?;
+	let track_id = reader.u32()?;
+	reader.skip(4)?; // reserved
+	let duration = read_versioned(&mut reader, version)?;
+
+	Ok(TrackHeader { version, creation_time, modification_time, track_id, duration })
+}
+
+pub fn parse_mdhd(data: &[u8]) -> io::Result<MediaHeader> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+
+	let creation_time = read_versioned(&mut reader, version)?;
+	let modification_time = read_versioned(&mut reader, version)?;
+	let timescale = reader.u32()?;
+	let duration = read_versioned(&mut reader, version)?;
+
+	Ok(MediaHeader { version, creation_time, modification_time, timescale, duration })
+}
+
+pub fn parse_hdlr(data: &[u8]) -> io::Result<String> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+
+	// pre_defined (u32), then the handler type
+	reader.skip(4)?;
+	Ok(String::from_utf8_lossy(reader.bytes(4)?).into_owned())
+}
+
+pub fn parse_stsd(data: &[u8]) -> io::Result<Table<SampleEntry>> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let declared = reader.u32()?;
+
+	let mut entries = Vec::new();
+	while entries.len() < declared as usize && reader.remaining() >= 8 {
+		let size = reader.u32()? as usize;
+		let format = BoxType::from(reader.u32()?);
+		if size < 8 || size - 8 > reader.remaining() {
+			break;
+		}
+
+		let data = reader.bytes(size - 8)?.to_vec();
+		entries.push(SampleEntry { format, data });
+	}
+
+	Ok(Table { declared, entries })
+}
+
+pub fn parse_stts(data: &[u8]) -> io::Result<Table<SttsEntry>> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let declared = reader.u32()?;
+
+	read_entries(&mut reader, declared, 8, |r| Ok(SttsEntry {
+		sample_count: r.u32()?,
+		sample_delta: r.u32()?,
+	}))
+}
+
+pub fn parse_ctts(data: &[u8]) -> io::Result<Table<CttsEntry>> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let declared = reader.u32()?;
+
+	read_entries(&mut reader, declared, 8, |r| Ok(CttsEntry {
+		sample_count: r.u32()?,
+		sample_offset: r.u32()? as i32,
+	}))
+}
+
+pub fn parse_elst(data: &[u8]) -> io::Result<Table<EditEntry>> {
+	let mut reader = PayloadReader::new(data);
+	let (version, _flags) = reader.version_flags()?;
+	let declared = reader.u32()?;
+	let entry_size = if version == 1 { 20 } else { 12 };
+
+	read_entries(&mut reader, declared, entry_size, |r| Ok(EditEntry {
+		segment_duration: read_versioned(r, version)?,
+		media_time: match version {
+			0 => r.u32()? as i32 as i64,
+			_ => r.u64()? as i64,
+		},
+		media_rate: r.u32()? as i32 as f64 / 65536.0,
+	}))
+}
+
+pub fn parse_stsc(data: &[u8]) -> io::Result<Table<StscEntry>> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let declared = reader.u32()?;
+
+	read_entries(&mut reader, declared, 12, |r| Ok(StscEntry {
+		first_chunk: r.u32()?,
+		samples_per_chunk: r.u32()?,
+		sample_description_index: r.u32()?,
+	}))
+}
+
+pub fn parse_stsz(data: &[u8]) -> io::Result<SampleSizes> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let sample_size = reader.u32()?;
+	let declared = reader.u32()?;
+
+	let sizes = if sample_size == 0 {
+		read_entries(&mut reader, declared, 4, |r| r.u32())?
+	} else {
+		Table { declared, entries: Vec::new() }
+	};
+
+	Ok(SampleSizes { sample_size, sizes })
+}
+
+pub fn parse_stss(data: &[u8]) -> io::Result<Table<u32>> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let declared = reader.u32()?;
+
+	read_entries(&mut reader, declared, 4, |r| r.u32())
+}
+
+pub fn parse_stco(data: &[u8]) -> io::Result<Table<u64>> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let declared = reader.u32()?;
+
+	read_entries(&mut reader, declared, 4, |r| r.u32().map(u64::from))
+}
+
+pub fn parse_co64(data: &[u8]) -> io::Result<Table<u64>> {
+	let mut reader = PayloadReader::new(data);
+	reader.version_flags()?;
+	let declared = reader.u32()?;
+
+	read_entries(&mut reader, declared, 8, |r| r.u64())
+}